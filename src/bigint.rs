@@ -0,0 +1,559 @@
+use std::{ cmp::Ordering, fmt, ops, str::FromStr };
+
+/// Arbitrary-precision signed integer.
+///
+/// Stored as a sign and a little-endian magnitude in base 2^32 without
+/// trailing zero limbs, so zero is an empty magnitude and never negative.
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    mag: Vec<u32>,
+}
+
+fn trim(mag: &mut Vec<u32>) {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+}
+
+fn mag_cmp(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn mag_add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut res = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+
+    for (i, &l) in long.iter().enumerate() {
+        let s = (l as u64) + (short.get(i).copied().unwrap_or(0) as u64) + carry;
+        res.push(s as u32);
+        carry = s >> 32;
+    }
+    if carry != 0 {
+        res.push(carry as u32);
+    }
+    res
+}
+
+/// `a - b`, requires `a >= b`.
+fn mag_sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+
+    for (i, &l) in a.iter().enumerate() {
+        let mut d = (l as i64) - (b.get(i).copied().unwrap_or(0) as i64) - borrow;
+        if d < 0 {
+            d += 1 << 32;
+            borrow = 1;
+        } else {
+            borrow = 0;
+        }
+        res.push(d as u32);
+    }
+    trim(&mut res);
+    res
+}
+
+fn mag_mul(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+
+    let mut res = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let t = (x as u64) * (y as u64) + (res[i + j] as u64) + carry;
+            res[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        res[i + b.len()] = carry as u32;
+    }
+    trim(&mut res);
+    res
+}
+
+/// Divides in place by a single limb and returns the remainder.
+fn mag_divrem_small(a: &mut Vec<u32>, d: u32) -> u32 {
+    let mut rem = 0u64;
+    for limb in a.iter_mut().rev() {
+        let cur = (rem << 32) | (*limb as u64);
+        *limb = (cur / (d as u64)) as u32;
+        rem = cur % (d as u64);
+    }
+    trim(a);
+    rem as u32
+}
+
+fn mag_shl(a: &[u32], bits: usize) -> Vec<u32> {
+    if a.is_empty() {
+        return Vec::new();
+    }
+
+    let (limbs, shift) = (bits / 32, bits % 32);
+    let mut res = vec![0u32; limbs];
+    let mut carry = 0u32;
+
+    for &l in a {
+        if shift == 0 {
+            res.push(l);
+        } else {
+            res.push((l << shift) | carry);
+            carry = l >> (32 - shift);
+        }
+    }
+    if carry != 0 {
+        res.push(carry);
+    }
+    res
+}
+
+/// Truncating division of magnitudes, `b` must be non-zero.
+fn mag_divrem(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if mag_cmp(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+
+    if b.len() == 1 {
+        let mut q = a.to_vec();
+        let r = mag_divrem_small(&mut q, b[0]);
+        let r = if r == 0 { Vec::new() } else { vec![r] };
+        return (q, r);
+    }
+
+    // Binary long division; fine for the operand sizes of a calculator.
+    let mut q = vec![0u32; a.len()];
+    let mut r: Vec<u32> = Vec::new();
+
+    for bit in (0..a.len() * 32).rev() {
+        r = mag_shl(&r, 1);
+        if (a[bit / 32] >> (bit % 32)) & 1 == 1 {
+            if r.is_empty() {
+                r.push(1);
+            } else {
+                r[0] |= 1;
+            }
+        }
+        if mag_cmp(&r, b) != Ordering::Less {
+            r = mag_sub(&r, b);
+            q[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    trim(&mut q);
+    (q, r)
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, mut mag: Vec<u32>) -> BigInt {
+        trim(&mut mag);
+        BigInt { negative: negative && !mag.is_empty(), mag }
+    }
+
+    pub fn zero() -> BigInt {
+        BigInt::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt { negative: false, mag: self.mag.clone() }
+    }
+
+    /// Number of significant bits of the magnitude.
+    pub fn bits(&self) -> usize {
+        match self.mag.last() {
+            Some(top) => self.mag.len() * 32 - (top.leading_zeros() as usize),
+            None => 0,
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 2 {
+            return None;
+        }
+
+        let m = (self.mag.first().copied().unwrap_or(0) as u64) |
+        ((self.mag.get(1).copied().unwrap_or(0) as u64) << 32);
+
+        if self.negative {
+            if m <= (i64::MAX as u64) + 1 { Some((m as i64).wrapping_neg()) } else { None }
+        } else {
+            i64::try_from(m).ok()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let m = self.mag
+            .iter()
+            .rev()
+            .fold(0f64, |acc, &l| acc * 4294967296.0 + (l as f64));
+        if self.negative { -m } else { m }
+    }
+
+    /// Truncates a finite float towards zero.
+    pub fn from_f64(f: f64) -> Option<BigInt> {
+        if !f.is_finite() {
+            return None;
+        }
+
+        let t = f.trunc();
+        if t.abs() < 9.2e18 {
+            return Some(BigInt::from(t as i64));
+        }
+
+        let bits = t.abs().to_bits();
+        let exp = (((bits >> 52) & 0x7ff) as i64) - 1075;
+        let mant = (bits & ((1u64 << 52) - 1)) | (1u64 << 52);
+        let mag = mag_shl(&[mant as u32, (mant >> 32) as u32], exp as usize);
+
+        Some(BigInt::from_parts(t < 0.0, mag))
+    }
+
+    /// Truncating division and remainder, like `/` and `%` on `i64`.
+    /// Returns `None` if `rhs` is zero.
+    pub fn div_rem(&self, rhs: &BigInt) -> Option<(BigInt, BigInt)> {
+        if rhs.is_zero() {
+            return None;
+        }
+
+        let (q, r) = mag_divrem(&self.mag, &rhs.mag);
+        Some((
+            BigInt::from_parts(self.negative != rhs.negative, q),
+            BigInt::from_parts(self.negative, r),
+        ))
+    }
+
+    pub fn pow(&self, mut exp: u32) -> BigInt {
+        let mut base = self.clone();
+        let mut acc = BigInt::from(1);
+
+        while exp > 0 {
+            if exp & 1 == 1 {
+                acc = &acc * &base;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = &base * &base;
+            }
+        }
+        acc
+    }
+
+    /// Greatest common divisor, always non-negative.
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while let Some((_, r)) = a.div_rem(&b) {
+            a = b;
+            b = r;
+        }
+        a
+    }
+
+    /// Two's complement limbs, sign-extended to `len` limbs.
+    fn to_twos(&self, len: usize) -> Vec<u32> {
+        if self.negative {
+            let mut m = mag_sub(&self.mag, &[1]);
+            m.resize(len, 0);
+            m.iter().map(|l| !l).collect()
+        } else {
+            let mut m = self.mag.clone();
+            m.resize(len, 0);
+            m
+        }
+    }
+
+    fn from_twos(limbs: Vec<u32>) -> BigInt {
+        if limbs.last().is_some_and(|l| l >> 31 == 1) {
+            let inv: Vec<u32> = limbs.iter().map(|l| !l).collect();
+            BigInt::from_parts(true, mag_add(&inv, &[1]))
+        } else {
+            BigInt::from_parts(false, limbs)
+        }
+    }
+
+    fn bitwise(&self, rhs: &BigInt, f: impl Fn(u32, u32) -> u32) -> BigInt {
+        let len = self.mag.len().max(rhs.mag.len()) + 1;
+        let a = self.to_twos(len);
+        let b = rhs.to_twos(len);
+
+        BigInt::from_twos(
+            a
+                .iter()
+                .zip(b.iter())
+                .map(|(x, y)| f(*x, *y))
+                .collect()
+        )
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(v: i64) -> BigInt {
+        let m = v.unsigned_abs();
+        BigInt::from_parts(v < 0, vec![m as u32, (m >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => mag_cmp(&self.mag, &other.mag),
+            (true, true) => mag_cmp(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl ops::Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.mag.clone())
+    }
+}
+
+impl ops::Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: &BigInt) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::from_parts(self.negative, mag_add(&self.mag, &rhs.mag));
+        }
+
+        match mag_cmp(&self.mag, &rhs.mag) {
+            Ordering::Less => BigInt::from_parts(rhs.negative, mag_sub(&rhs.mag, &self.mag)),
+            _ => BigInt::from_parts(self.negative, mag_sub(&self.mag, &rhs.mag)),
+        }
+    }
+}
+
+impl ops::Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: &BigInt) -> BigInt {
+        self + &-rhs
+    }
+}
+
+impl ops::Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: &BigInt) -> BigInt {
+        BigInt::from_parts(self.negative != rhs.negative, mag_mul(&self.mag, &rhs.mag))
+    }
+}
+
+impl ops::BitAnd for &BigInt {
+    type Output = BigInt;
+
+    fn bitand(self, rhs: &BigInt) -> BigInt {
+        self.bitwise(rhs, |a, b| a & b)
+    }
+}
+
+impl ops::BitOr for &BigInt {
+    type Output = BigInt;
+
+    fn bitor(self, rhs: &BigInt) -> BigInt {
+        self.bitwise(rhs, |a, b| a | b)
+    }
+}
+
+impl ops::BitXor for &BigInt {
+    type Output = BigInt;
+
+    fn bitxor(self, rhs: &BigInt) -> BigInt {
+        self.bitwise(rhs, |a, b| a ^ b)
+    }
+}
+
+impl ops::Not for &BigInt {
+    type Output = BigInt;
+
+    fn not(self) -> BigInt {
+        &-self - &BigInt::from(1)
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        let mut mag = self.mag.clone();
+        let mut chunks = Vec::new();
+        while !mag.is_empty() {
+            chunks.push(mag_divrem_small(&mut mag, 1_000_000_000));
+        }
+
+        let mut s = String::new();
+        if self.negative {
+            s.push('-');
+        }
+        s.push_str(&chunks.last().unwrap().to_string());
+        for c in chunks.iter().rev().skip(1) {
+            s.push_str(&format!("{:09}", c));
+        }
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = ();
+
+    /// Parses an optionally signed string of decimal digits.
+    fn from_str(s: &str) -> Result<BigInt, ()> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+
+        if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(());
+        }
+
+        let mut mag: Vec<u32> = Vec::new();
+        for chunk in digits.as_bytes().chunks(9) {
+            let scale = (10u32).pow(chunk.len() as u32);
+            let v = std::str::from_utf8(chunk).unwrap().parse::<u32>().unwrap();
+
+            mag = mag_mul(&mag, &[scale]);
+            mag = mag_add(&mag, &[v]);
+        }
+
+        Ok(BigInt::from_parts(negative, mag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn zero_is_never_negative() {
+        assert!(!(-&BigInt::zero()).is_negative());
+        assert!(!(&BigInt::from(5) - &BigInt::from(5)).is_negative());
+        assert!(!(&BigInt::from(-3) * &BigInt::zero()).is_negative());
+        assert_eq!(big("-0"), BigInt::zero());
+        assert_eq!(big("-0").to_string(), "0");
+    }
+
+    #[test]
+    fn carry_and_borrow_across_limbs() {
+        let limb = BigInt::from(u32::MAX as i64);
+        assert_eq!((&limb + &BigInt::from(1)).to_string(), "4294967296");
+
+        let two_64 = big("18446744073709551616");
+        assert_eq!((&big("18446744073709551615") + &BigInt::from(1)), two_64);
+        assert_eq!((&two_64 - &BigInt::from(1)).to_string(), "18446744073709551615");
+        assert_eq!(
+            (&BigInt::from(2).pow(96) - &BigInt::from(1)).to_string(),
+            "79228162514264337593543950335"
+        );
+        // Adding numbers of opposite signs subtracts the smaller magnitude.
+        assert_eq!((&BigInt::from(3) + &-&two_64).to_string(), "-18446744073709551613");
+    }
+
+    #[test]
+    fn i64_bounds() {
+        let min = BigInt::from(i64::MIN);
+        assert_eq!(min.to_string(), "-9223372036854775808");
+        assert_eq!(min.to_i64(), Some(i64::MIN));
+        assert_eq!((-&min).to_string(), "9223372036854775808");
+        assert_eq!((-&min).to_i64(), None);
+        assert_eq!((&min - &BigInt::from(1)).to_i64(), None);
+        assert_eq!(BigInt::from(i64::MAX).to_i64(), Some(i64::MAX));
+        assert_eq!((&BigInt::from(i64::MAX) + &BigInt::from(1)).to_i64(), None);
+    }
+
+    #[test]
+    fn div_rem_truncates() {
+        let div = |a: i64, b: i64| BigInt::from(a).div_rem(&BigInt::from(b)).unwrap();
+        assert_eq!(div(7, 2), (BigInt::from(3), BigInt::from(1)));
+        assert_eq!(div(-7, 2), (BigInt::from(-3), BigInt::from(-1)));
+        assert_eq!(div(7, -2), (BigInt::from(-3), BigInt::from(1)));
+        assert_eq!(div(-7, -2), (BigInt::from(3), BigInt::from(-1)));
+        assert_eq!(BigInt::from(1).div_rem(&BigInt::zero()), None);
+
+        let a = &BigInt::from(2).pow(100) + &BigInt::from(12345);
+        let b = &BigInt::from(2).pow(40) + &BigInt::from(3);
+        let (q, r) = a.div_rem(&b).unwrap();
+        assert_eq!((q.to_string(), r.to_string()), ("1152921504603701248".into(), "9449529".into()));
+        let (q, r) = (-&a).div_rem(&b).unwrap();
+        assert_eq!((q.to_string(), r.to_string()), ("-1152921504603701248".into(), "-9449529".into()));
+    }
+
+    #[test]
+    fn pow_and_gcd() {
+        assert_eq!(
+            BigInt::from(3).pow(100).to_string(),
+            "515377520732011331036461129765621272702107522001"
+        );
+        assert_eq!(BigInt::from(-2).pow(3), BigInt::from(-8));
+        assert_eq!(BigInt::from(7).pow(0), BigInt::from(1));
+        assert_eq!(BigInt::from(-12).gcd(&BigInt::from(18)), BigInt::from(6));
+        assert_eq!(BigInt::zero().gcd(&BigInt::from(-5)), BigInt::from(5));
+    }
+
+    #[test]
+    fn bitwise_uses_twos_complement() {
+        let (a, b) = (BigInt::from(-6), BigInt::from(3));
+        assert_eq!(&a & &b, BigInt::from(2));
+        assert_eq!(&a | &b, BigInt::from(-5));
+        assert_eq!(&a ^ &b, BigInt::from(-7));
+        assert_eq!(!&BigInt::zero(), BigInt::from(-1));
+
+        let p = BigInt::from(2).pow(70);
+        assert_eq!(&-&p & &(&p + &BigInt::from(5)), p);
+        assert_eq!((&-&p | &BigInt::from(5)).to_string(), "-1180591620717411303419");
+        assert_eq!((&p ^ &BigInt::from(-1)).to_string(), "-1180591620717411303425");
+    }
+
+    #[test]
+    fn ordering_follows_sign() {
+        let p = BigInt::from(2).pow(70);
+        let mut values = vec![p.clone(), BigInt::zero(), -&p, BigInt::from(-1)];
+        values.sort();
+        assert_eq!(values, vec![-&p, BigInt::from(-1), BigInt::zero(), p]);
+    }
+
+    #[test]
+    fn text_and_float_conversions() {
+        let text = "-123456789012345678901234567890";
+        assert_eq!(big(text).to_string(), text);
+        assert_eq!(big("+1000000000").to_string(), "1000000000");
+        assert!("12a".parse::<BigInt>().is_err());
+        assert!("-".parse::<BigInt>().is_err());
+
+        assert_eq!(BigInt::from_f64(1e20).unwrap().to_string(), "100000000000000000000");
+        assert_eq!(BigInt::from_f64(-2.5), Some(BigInt::from(-2)));
+        assert_eq!(BigInt::from_f64(f64::NAN), None);
+        assert_eq!(big("100000000000000000000").to_f64(), 1e20);
+    }
+}
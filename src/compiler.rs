@@ -0,0 +1,574 @@
+use std::sync::Arc;
+
+use crate::{
+    error::{ Error, EvalError },
+    operator::{ BinaryOp, Operator, UnaryOp },
+    parser::Expr,
+    scope::{ Globals, ScopeKind },
+    span::{ Span, Spanned },
+    token::Keyword,
+    user_function::UserFunction,
+    value::Value,
+};
+
+/// Value an instruction reads in place. Constants and variables are
+/// borrowed instead of being pushed as copies first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    /// Popped from the stack.
+    Stack,
+    /// `constants[i]`.
+    Const(usize),
+    /// The local variable in `slot`.
+    Local(usize),
+    /// The variable `names[name]`, read as for `Load`.
+    Global {
+        name: usize,
+        slot: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    /// Push `constants[i]`.
+    Const(usize),
+    /// Push the variable `names[name]`: the global in `slot`, unless a scope
+    /// above the global one binds the name.
+    Load {
+        name: usize,
+        slot: usize,
+    },
+    /// Push the local variable in `slot`.
+    LoadLocal(usize),
+    /// Run the macro `names[i]` and push its result.
+    Macro(usize),
+    Unary(UnaryOp),
+    /// Read rhs and lhs, push `lhs op rhs`. `operands` are the spans of lhs
+    /// and rhs. lhs is only read in place if rhs is too, so that it is read
+    /// first.
+    Binary {
+        op: BinaryOp,
+        lhs: Operand,
+        rhs: Operand,
+        operands: [Span; 2],
+    },
+    /// Peek lhs of `&&`: if it is false, replace it with `false` and jump.
+    AndShort(usize),
+    /// Peek lhs of `||`: if it is true, replace it with `true` and jump.
+    OrShort(usize),
+    /// Pop rhs, store it into the variable `names[name]`, whose global is in
+    /// `slot`, and push the result.
+    Assign {
+        op: BinaryOp,
+        name: usize,
+        slot: usize,
+        operands: [Span; 2],
+    },
+    /// Pop rhs, store it into the local variable in `slot` and push the
+    /// result.
+    AssignLocal {
+        op: BinaryOp,
+        slot: usize,
+        operands: [Span; 2],
+    },
+    /// Fail if `names[name]` is not a user or native function or a variable
+    /// holding a lambda. `local` is the slot of a local variable of that
+    /// name.
+    Resolve {
+        name: usize,
+        local: Option<usize>,
+    },
+    /// Pop `argc` arguments and call the function `names[func]`. `args`
+    /// indexes the argument spans in `Program::arg_spans`, `local` is as
+    /// for `Resolve`.
+    Call {
+        func: usize,
+        argc: usize,
+        args: usize,
+        local: Option<usize>,
+    },
+    /// Pop `argc` arguments, then the callee, and call it. `callee` is the
+    /// span of the callee expression.
+    CallValue {
+        argc: usize,
+        callee: Span,
+    },
+    /// Push a lambda running `functions[i]` that captures the current
+    /// variables.
+    Closure(usize),
+    /// Store `functions[func]` as the user function `names[name]` and push
+    /// it as a function value.
+    Define {
+        name: usize,
+        func: usize,
+    },
+    /// Open a scope of the given kind.
+    PushScope(ScopeKind),
+    /// Pop a value into a new local variable `names[i]` of the innermost
+    /// scope, in the next slot.
+    Bind(usize),
+    /// Close the innermost scope and drop its local variables.
+    PopScope,
+    /// Open a loop whose result is the value `locals` slots below the top of
+    /// the stack. `break` unwinds to here and jumps to `exit`, `continue`
+    /// unwinds to here and jumps to the next instruction.
+    PushLoop {
+        exit: usize,
+        locals: usize,
+    },
+    /// Close the innermost loop.
+    PopLoop,
+    /// Pop the value of a finished iteration into the innermost loop result.
+    StoreResult,
+    /// Fail unless the two values on top of the stack, the bounds of a `for`
+    /// range, are integers.
+    ForRange,
+    /// With a counter and an end bound on top of the stack: jump to `exit`
+    /// if the counter has reached the end, else store the counter into the
+    /// local variable in `slot` and increment it.
+    ForNext {
+        slot: usize,
+        exit: usize,
+    },
+    /// Pop `n` values and push them as a list, or a matrix if they are rows.
+    List(usize),
+    /// Pop an index and a target and push the element.
+    Index {
+        target: Span,
+        index: Span,
+    },
+    /// Pop the bounds that are present, then the target, and push the slice.
+    Slice {
+        start: bool,
+        end: bool,
+        target: Span,
+    },
+    Jump(usize),
+    /// Pop a condition and jump if it is false.
+    JumpIfFalse(usize),
+    Pop,
+    /// Errors raised before the matching `PopHandler` truncate the stack and
+    /// jump to the target instead of propagating.
+    PushHandler(usize),
+    PopHandler,
+    Raise(Error),
+}
+
+/// Compiled form of one expression. `spans[i]` is the span of the expression
+/// that produced `code[i]` and is used to report errors raised by it.
+///
+/// Variables bound by `let` and `for` and the parameters of a function are
+/// locals, which live in numbered slots while the program runs instead of in
+/// the scopes. In the program of a function the parameters are the first
+/// names and take the first slots. Other variables are looked up by their
+/// slot in the `Globals` table with the id `globals`, or by name when the
+/// program runs against another table.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    pub globals: u64,
+    pub code: Vec<Instr>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    pub names: Vec<String>,
+    pub arg_spans: Vec<Vec<Span>>,
+    pub functions: Vec<Arc<UserFunction>>,
+}
+
+impl Program {
+    fn emit(&mut self, instr: Instr, span: Span) -> usize {
+        self.code.push(instr);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    fn constant(&mut self, v: Value) -> usize {
+        self.constants.push(v);
+        self.constants.len() - 1
+    }
+
+    fn name(&mut self, s: &str) -> usize {
+        if let Some(i) = self.names.iter().position(|n| n == s) {
+            i
+        } else {
+            self.names.push(s.to_string());
+            self.names.len() - 1
+        }
+    }
+
+    fn patch(&mut self, at: usize) {
+        let target = self.code.len();
+        match &mut self.code[at] {
+            | Instr::Jump(t)
+            | Instr::JumpIfFalse(t)
+            | Instr::AndShort(t)
+            | Instr::OrShort(t)
+            | Instr::PushHandler(t)
+            | Instr::PushLoop { exit: t, .. }
+            | Instr::ForNext { exit: t, .. } => {
+                *t = target;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_assign(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        | BinaryOp::Assign
+        | BinaryOp::AddAssign
+        | BinaryOp::SubAssign
+        | BinaryOp::MulAssign
+        | BinaryOp::DivAssign
+        | BinaryOp::ModAssign
+        | BinaryOp::AndAssign
+        | BinaryOp::OrAssign
+        | BinaryOp::BitAndAssign
+        | BinaryOp::BitOrAssign
+        | BinaryOp::BitXorAssign
+    )
+}
+
+/// `expr` as an operand read in place, if it is a constant or a variable.
+fn operand(
+    program: &mut Program,
+    locals: &[usize],
+    globals: &mut Globals,
+    expr: &Spanned<Expr>
+) -> Option<Operand> {
+    match &expr.data {
+        Expr::Value(v) => Some(Operand::Const(program.constant(v.clone()))),
+        Expr::Identifier(s) => {
+            let name = program.name(s);
+            match local(locals, name) {
+                Some(slot) => Some(Operand::Local(slot)),
+                None => Some(Operand::Global { name, slot: globals.slot(s) }),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Compiles `expr` where the local variables `locals` are visible, given as
+/// indexes into `program.names` by slot. Global variables are given slots
+/// in `globals`.
+fn compile_expr(
+    program: &mut Program,
+    locals: &mut Vec<usize>,
+    globals: &mut Globals,
+    expr: &Spanned<Expr>
+) {
+    match &expr.data {
+        Expr::Value(v) => {
+            let i = program.constant(v.clone());
+            program.emit(Instr::Const(i), expr.span);
+        }
+        Expr::Identifier(s) => {
+            let i = program.name(s);
+            match local(locals, i) {
+                Some(slot) => program.emit(Instr::LoadLocal(slot), expr.span),
+                None => program.emit(Instr::Load { name: i, slot: globals.slot(s) }, expr.span),
+            };
+        }
+        Expr::Macro(s) => {
+            let i = program.name(s);
+            program.emit(Instr::Macro(i), expr.span);
+        }
+        Expr::Binary { op: Operator::Binary(op), lhs, rhs } => {
+            let operands = [lhs.span, rhs.span];
+
+            if is_assign(*op) {
+                compile_expr(program, locals, globals, rhs);
+
+                if let Expr::Identifier(s) = &lhs.data {
+                    let name = program.name(s);
+                    match local(locals, name) {
+                        Some(slot) => program.emit(Instr::AssignLocal { op: *op, slot, operands }, expr.span),
+                        None => {
+                            let slot = globals.slot(s);
+                            program.emit(Instr::Assign { op: *op, name, slot, operands }, expr.span)
+                        }
+                    };
+                } else {
+                    program.emit(Instr::Pop, expr.span);
+                    program.emit(
+                        Instr::Raise(
+                            Error::EvalError(EvalError::NotAssignable {
+                                op: Operator::Binary(BinaryOp::Assign),
+                            })
+                        ),
+                        Span { start: lhs.span.start, end: rhs.span.end }
+                    );
+                }
+                return;
+            }
+
+            let short = match op {
+                BinaryOp::And | BinaryOp::Or => {
+                    compile_expr(program, locals, globals, lhs);
+                    Some(match op {
+                        BinaryOp::And => program.emit(Instr::AndShort(0), expr.span),
+                        _ => program.emit(Instr::OrShort(0), expr.span),
+                    })
+                }
+                _ => None,
+            };
+
+            // Constants and variables are read in place. lhs only is if rhs
+            // is too, since otherwise rhs would run before lhs is read.
+            let (lhs, rhs) = if short.is_some() {
+                compile_expr(program, locals, globals, rhs);
+                (Operand::Stack, Operand::Stack)
+            } else if is_operand(rhs) {
+                let lhs = match operand(program, locals, globals, lhs) {
+                    Some(lhs) => lhs,
+                    None => {
+                        compile_expr(program, locals, globals, lhs);
+                        Operand::Stack
+                    }
+                };
+                (lhs, operand(program, locals, globals, rhs).unwrap_or(Operand::Stack))
+            } else {
+                compile_expr(program, locals, globals, lhs);
+                compile_expr(program, locals, globals, rhs);
+                (Operand::Stack, Operand::Stack)
+            };
+            program.emit(Instr::Binary { op: *op, lhs, rhs, operands }, expr.span);
+
+            if let Some(at) = short {
+                program.patch(at);
+            }
+        }
+        Expr::Binary { lhs, rhs, .. } => {
+            compile_expr(program, locals, globals, lhs);
+            compile_expr(program, locals, globals, rhs);
+            program.emit(Instr::Raise(Error::UnexpectedError), Span {
+                start: lhs.span.start,
+                end: rhs.span.end,
+            });
+        }
+        Expr::Unary { op, rhs } => {
+            let span = Span::merge(&expr.span, &rhs.span);
+            compile_expr(program, locals, globals, rhs);
+            match op {
+                Operator::Unary(op) => program.emit(Instr::Unary(*op), span),
+                _ => program.emit(Instr::Raise(Error::UnexpectedError), span),
+            };
+        }
+        Expr::Ternary { cond, statement1, statement2 } => {
+            compile_expr(program, locals, globals, cond);
+            let jump_else = program.emit(Instr::JumpIfFalse(0), cond.span);
+            compile_expr(program, locals, globals, statement1);
+            let jump_end = program.emit(Instr::Jump(0), expr.span);
+            program.patch(jump_else);
+            compile_expr(program, locals, globals, statement2);
+            program.patch(jump_end);
+        }
+        Expr::Call { func, args } => {
+            let call_span = Span {
+                start: func.span.start,
+                end: if let Some(e) = args.last() { e.span.end } else { func.span.end },
+            };
+
+            match &func.data {
+                Expr::Identifier(s) => {
+                    let f = program.name(s);
+                    let local = local(locals, f);
+
+                    program.emit(Instr::Resolve { name: f, local }, call_span);
+                    for arg in args {
+                        compile_expr(program, locals, globals, arg);
+                    }
+
+                    program.arg_spans.push(
+                        args
+                            .iter()
+                            .map(|a| a.span)
+                            .collect()
+                    );
+                    let spans = program.arg_spans.len() - 1;
+                    program.emit(Instr::Call { func: f, argc: args.len(), args: spans, local }, call_span);
+                }
+                _ => {
+                    compile_expr(program, locals, globals, func);
+                    for arg in args {
+                        compile_expr(program, locals, globals, arg);
+                    }
+                    program.emit(Instr::CallValue { argc: args.len(), callee: func.span }, call_span);
+                }
+            }
+        }
+        Expr::FunctionDef { name, params, body } => {
+            let name = program.name(name);
+            program.functions.push(Arc::new(UserFunction::new(params.clone(), body.clone())));
+            let func = program.functions.len() - 1;
+            program.emit(Instr::Define { name, func }, expr.span);
+        }
+        Expr::Let { bindings, body } => {
+            let outer = locals.len();
+            program.emit(Instr::PushScope(ScopeKind::Let), expr.span);
+            for (name, value) in bindings {
+                compile_expr(program, locals, globals, value);
+                let name = program.name(name);
+                program.emit(Instr::Bind(name), value.span);
+                locals.push(name);
+            }
+            compile_expr(program, locals, globals, body);
+            program.emit(Instr::PopScope, expr.span);
+            locals.truncate(outer);
+        }
+        Expr::If { cond, then_branch, else_branch } => {
+            compile_expr(program, locals, globals, cond);
+            let jump_else = program.emit(Instr::JumpIfFalse(0), cond.span);
+            compile_expr(program, locals, globals, then_branch);
+            let jump_end = program.emit(Instr::Jump(0), expr.span);
+            program.patch(jump_else);
+            match else_branch {
+                Some(e) => compile_expr(program, locals, globals, e),
+                None => {
+                    let i = program.constant(Value::Boolean(false));
+                    program.emit(Instr::Const(i), expr.span);
+                }
+            }
+            program.patch(jump_end);
+        }
+        Expr::While { cond, body } => {
+            let i = program.constant(Value::Boolean(false));
+            program.emit(Instr::Const(i), expr.span);
+            let exit = program.emit(Instr::PushLoop { exit: 0, locals: 0 }, expr.span);
+
+            let top = program.code.len();
+            compile_expr(program, locals, globals, cond);
+            let done = program.emit(Instr::JumpIfFalse(0), cond.span);
+            compile_expr(program, locals, globals, body);
+            program.emit(Instr::StoreResult, body.span);
+            program.emit(Instr::Jump(top), expr.span);
+
+            program.patch(done);
+            program.emit(Instr::PopLoop, expr.span);
+            program.patch(exit);
+        }
+        Expr::For { var, start, end, body } => {
+            let i = program.constant(Value::Boolean(false));
+            program.emit(Instr::Const(i), expr.span);
+            compile_expr(program, locals, globals, start);
+            compile_expr(program, locals, globals, end);
+            program.emit(Instr::ForRange, Span::merge(&start.span, &end.span));
+            program.emit(Instr::PushScope(ScopeKind::For), expr.span);
+            // The variable is bound on the first iteration; this only gives
+            // it a slot.
+            let outer = locals.len();
+            program.emit(Instr::Const(i), expr.span);
+            let var = program.name(var);
+            program.emit(Instr::Bind(var), expr.span);
+            locals.push(var);
+            let exit = program.emit(Instr::PushLoop { exit: 0, locals: 2 }, expr.span);
+
+            let top = program.code.len();
+            let done = program.emit(Instr::ForNext { slot: outer, exit: 0 }, expr.span);
+            compile_expr(program, locals, globals, body);
+            program.emit(Instr::StoreResult, body.span);
+            program.emit(Instr::Jump(top), expr.span);
+
+            program.patch(done);
+            program.emit(Instr::PopLoop, expr.span);
+            program.patch(exit);
+            program.emit(Instr::PopScope, expr.span);
+            program.emit(Instr::Pop, expr.span);
+            program.emit(Instr::Pop, expr.span);
+            locals.truncate(outer);
+        }
+        Expr::Break | Expr::Continue => {
+            let keyword = if expr.data == Expr::Break { Keyword::Break } else { Keyword::Continue };
+            program.emit(
+                Instr::Raise(Error::EvalError(EvalError::LoopControl { keyword })),
+                expr.span
+            );
+        }
+        Expr::Error => {
+            program.emit(Instr::Raise(Error::UnexpectedError), expr.span);
+        }
+        Expr::Lambda { params, body } => {
+            program.functions.push(Arc::new(UserFunction::new(params.clone(), body.clone())));
+            let func = program.functions.len() - 1;
+            program.emit(Instr::Closure(func), expr.span);
+        }
+        Expr::List { items } => {
+            for item in items {
+                compile_expr(program, locals, globals, item);
+            }
+            program.emit(Instr::List(items.len()), expr.span);
+        }
+        Expr::Index { target, index } => {
+            compile_expr(program, locals, globals, target);
+            compile_expr(program, locals, globals, index);
+            program.emit(Instr::Index { target: target.span, index: index.span }, expr.span);
+        }
+        Expr::Slice { target, start, end } => {
+            compile_expr(program, locals, globals, target);
+            for bound in [start, end].into_iter().flatten() {
+                compile_expr(program, locals, globals, bound);
+            }
+            program.emit(
+                Instr::Slice { start: start.is_some(), end: end.is_some(), target: target.span },
+                expr.span
+            );
+        }
+        Expr::Block { exprs } => {
+            if let Some((last, init)) = exprs.split_last() {
+                for e in init {
+                    compile_expr(program, locals, globals, e);
+                    program.emit(Instr::Pop, e.span);
+                }
+                compile_expr(program, locals, globals, last);
+            }
+        }
+        Expr::Comma { exprs } => {
+            // Errors in all but the last expression are discarded, as in the
+            // tree walker.
+            if let Some((last, init)) = exprs.split_last() {
+                for e in init {
+                    let handler = program.emit(Instr::PushHandler(0), e.span);
+                    compile_expr(program, locals, globals, e);
+                    program.emit(Instr::Pop, e.span);
+                    program.emit(Instr::PopHandler, e.span);
+                    program.patch(handler);
+                }
+                compile_expr(program, locals, globals, last);
+            } else {
+                program.emit(Instr::Raise(Error::UnexpectedError), expr.span);
+            }
+        }
+    }
+}
+
+/// Whether `operand` reads `expr` in place.
+fn is_operand(expr: &Spanned<Expr>) -> bool {
+    matches!(expr.data, Expr::Value(_) | Expr::Identifier(_))
+}
+
+/// Slot of the innermost local variable `names[name]`.
+fn local(locals: &[usize], name: usize) -> Option<usize> {
+    locals.iter().rposition(|&n| n == name)
+}
+
+/// Compiles an expression into a program for `vm::run`, with its global
+/// variables given slots in `globals`.
+pub fn compile(expr: &Spanned<Expr>, globals: &mut Globals) -> Program {
+    compile_function(&[], expr, globals)
+}
+
+/// Compiles the body of a function whose parameters are `params`.
+pub fn compile_function(
+    params: &[String],
+    body: &Spanned<Expr>,
+    globals: &mut Globals
+) -> Program {
+    let mut program = Program { globals: globals.id(), ..Program::default() };
+    let mut locals: Vec<usize> = params
+        .iter()
+        .map(|p| program.name(p))
+        .collect();
+    compile_expr(&mut program, &mut locals, globals, body);
+    program
+}
@@ -0,0 +1,75 @@
+use crate::{ interpreter::Interpreter, lexer::is_identifier_char, script::COMMANDS };
+
+/// Names offered by Tab completion, taken from an interpreter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Names {
+    /// Variables, native functions and user functions.
+    pub identifiers: Vec<String>,
+    pub macros: Vec<String>,
+}
+
+impl Names {
+    pub fn new(interpreter: &Interpreter) -> Names {
+        let mut identifiers: Vec<String> = interpreter
+            .vars()
+            .keys()
+            .chain(interpreter.functions().keys())
+            .chain(interpreter.user_functions().keys())
+            .cloned()
+            .collect();
+        identifiers.sort();
+        identifiers.dedup();
+
+        let mut macros: Vec<String> = interpreter.macros().keys().cloned().collect();
+        macros.sort();
+
+        Names { identifiers, macros }
+    }
+}
+
+/// A completion: `replacement` replaces the word being completed and
+/// `display` is shown when several candidates are listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub display: String,
+    pub replacement: String,
+}
+
+/// Completes the word that ends at byte `pos` of `line`. After `{` the word
+/// is a macro name, after a `[` that starts the line a bracket command, and
+/// otherwise an identifier. Returns where the word starts and the matching
+/// candidates in order.
+pub fn complete(names: &Names, line: &str, pos: usize) -> (usize, Vec<Candidate>) {
+    let pos = pos.min(line.len());
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_identifier_char(*c))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let word = &line[start..pos];
+    let before = &line[..start];
+
+    let candidates = if before.ends_with('{') {
+        let close = if line[pos..].starts_with('}') { "" } else { "}" };
+        names.macros
+            .iter()
+            .filter(|m| m.starts_with(word))
+            .map(|m| Candidate { display: format!("{{{}}}", m), replacement: format!("{}{}", m, close) })
+            .collect()
+    } else if before.trim_start() == "[" {
+        COMMANDS.iter()
+            .map(|c| &c[1..])
+            .filter(|c| c.starts_with(&word.to_lowercase()))
+            .map(|c| Candidate { display: format!("[{}", c.trim_end()), replacement: c.to_string() })
+            .collect()
+    } else {
+        names.identifiers
+            .iter()
+            .filter(|n| n.starts_with(word))
+            .map(|n| Candidate { display: n.clone(), replacement: n.clone() })
+            .collect()
+    };
+
+    (start, candidates)
+}
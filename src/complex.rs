@@ -0,0 +1,144 @@
+use std::{ fmt, ops };
+
+/// Complex number with `f64` parts.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    pub fn from_polar(r: f64, theta: f64) -> Complex {
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.re == 0.0 && self.im == 0.0
+    }
+
+    pub fn is_finite(self) -> bool {
+        self.re.is_finite() && self.im.is_finite()
+    }
+
+    /// Principal natural logarithm.
+    pub fn ln(self) -> Complex {
+        Complex::new(self.abs().ln(), self.arg())
+    }
+
+    pub fn exp(self) -> Complex {
+        Complex::from_polar(self.re.exp(), self.im)
+    }
+
+    /// Principal square root. Computed from the parts rather than in polar
+    /// form so that `sqrt(-1)` is exactly `i`.
+    pub fn sqrt(self) -> Complex {
+        let r = self.abs();
+        let re = ((r + self.re) / 2.0).sqrt();
+        let im = ((r - self.re) / 2.0).sqrt();
+        Complex::new(re, if self.im.is_sign_negative() { -im } else { im })
+    }
+
+    /// Principal value of `self ** rhs`. Zero to any power is zero, except
+    /// that `0 ** 0` is 1.
+    pub fn pow(self, rhs: Complex) -> Complex {
+        if self.is_zero() {
+            return if rhs.is_zero() { Complex::new(1.0, 0.0) } else { Complex::default() };
+        }
+
+        // Small integer powers by repeated multiplication, which keeps
+        // results like `i ** 2` free of rounding noise.
+        if rhs.im == 0.0 && rhs.re.fract() == 0.0 && rhs.re.abs() <= 64.0 {
+            let mut acc = Complex::new(1.0, 0.0);
+            for _ in 0..rhs.re.abs() as u32 {
+                acc = acc * self;
+            }
+            if rhs.re < 0.0 {
+                return Complex::new(1.0, 0.0).checked_div(acc).unwrap_or(acc);
+            }
+            return acc;
+        }
+        (rhs * self.ln()).exp()
+    }
+
+    /// `self / rhs`, or `None` if `rhs` is zero.
+    pub fn checked_div(self, rhs: Complex) -> Option<Complex> {
+        if rhs.is_zero() {
+            return None;
+        }
+
+        let d = rhs.re * rhs.re + rhs.im * rhs.im;
+        Some(
+            Complex::new(
+                (self.re * rhs.re + self.im * rhs.im) / d,
+                (self.im * rhs.re - self.re * rhs.im) / d
+            )
+        )
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Complex {
+        Complex::new(re, 0.0)
+    }
+}
+
+impl ops::Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl fmt::Display for Complex {
+    /// Formats as `a+bi`, or just `bi` when the real part is zero.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.re == 0.0 {
+            write!(f, "{}i", self.im)
+        } else if self.im.is_sign_negative() {
+            write!(f, "{}-{}i", self.re, -self.im)
+        } else {
+            write!(f, "{}+{}i", self.re, self.im)
+        }
+    }
+}
@@ -1,8 +1,23 @@
-use std::collections::HashMap;
+use std::{ collections::HashMap, sync::Arc };
 
-use crate::{ error::Error, parser::Expr, span::Spanned, token::Token, value::Value };
+use crate::{
+    compiler::Program,
+    native_function::NativeFunction,
+    parser::Expr,
+    scope::Globals,
+    span::Spanned,
+    token::Token,
+    user_function::UserFunction,
+};
 
 pub fn expr_to_text(expr: &Spanned<Expr>) -> String {
+    // A comma between braces would separate statements, so a comma
+    // expression as a whole body keeps its parentheses.
+    fn braced(body: &Spanned<Expr>) -> String {
+        let bp = if matches!(body.data, Expr::Comma { .. }) { 2 } else { 0 };
+        format!("{{ {} }}", walk(body, bp))
+    }
+
     fn walk(e: &Spanned<Expr>, parent_prec: u32) -> String {
         match &e.data {
             Expr::Value(v) => v.symbol(),
@@ -44,6 +59,15 @@ pub fn expr_to_text(expr: &Spanned<Expr>) -> String {
                 }
             }
 
+            // Only found between braces, which the enclosing node prints.
+            Expr::Block { exprs } => {
+                exprs
+                    .iter()
+                    .map(|e| walk(e, 2))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            }
+
             Expr::Ternary { cond, statement1, statement2 } => {
                 let s = format!(
                     "{} ? {} : {}",
@@ -67,6 +91,101 @@ pub fn expr_to_text(expr: &Spanned<Expr>) -> String {
                     .join(", ");
                 format!("{f}({a})")
             }
+
+            Expr::Lambda { params, body } => {
+                let head = match params.as_slice() {
+                    [p] => p.clone(),
+                    _ => format!("({})", params.join(", ")),
+                };
+                let bp = if matches!(body.data, Expr::Comma { .. }) { 2 } else { 0 };
+                let s = format!("{} => {}", head, walk(body, bp));
+                if parent_prec > Token::Arrow.lbp() {
+                    format!("({s})")
+                } else {
+                    s
+                }
+            }
+
+            Expr::Let { bindings, body } => {
+                let bindings = bindings
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, walk(value, 2)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let bp = if matches!(body.data, Expr::Comma { .. }) { 2 } else { 0 };
+                let s = format!("let {} in {}", bindings, walk(body, bp));
+                // The body extends as far as a lambda body does.
+                if parent_prec > Token::Arrow.lbp() {
+                    format!("({s})")
+                } else {
+                    s
+                }
+            }
+
+            Expr::If { cond, then_branch, else_branch } => {
+                let mut s = format!("if {} {}", walk(cond, 0), braced(then_branch));
+                match else_branch.as_deref() {
+                    Some(e @ Spanned { data: Expr::If { .. }, .. }) => {
+                        s += &format!(" else {}", walk(e, 0));
+                    }
+                    Some(e) => {
+                        s += &format!(" else {}", braced(e));
+                    }
+                    None => {}
+                }
+                s
+            }
+
+            Expr::While { cond, body } => {
+                format!("while {} {}", walk(cond, 0), braced(body))
+            }
+
+            Expr::For { var, start, end, body } => {
+                format!(
+                    "for {} in {}..{} {}",
+                    var,
+                    walk(start, 0),
+                    walk(end, 0),
+                    braced(body)
+                )
+            }
+
+            Expr::Break => "break".to_string(),
+
+            Expr::Continue => "continue".to_string(),
+
+            Expr::Error => "<error>".to_string(),
+
+            Expr::List { items } => {
+                let s = items
+                    .iter()
+                    .map(|e| walk(e, 0))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("[{s}]")
+            }
+
+            Expr::Index { target, index } => {
+                format!("{}[{}]", walk(target, u32::MAX), walk(index, 0))
+            }
+
+            Expr::Slice { target, start, end } => {
+                let bound = |b: &Option<Box<Spanned<Expr>>>| {
+                    b.as_ref().map_or(String::new(), |b| walk(b, 0))
+                };
+                format!("{}[{}:{}]", walk(target, u32::MAX), bound(start), bound(end))
+            }
+
+            Expr::FunctionDef { name, params, body } => {
+                // A comma body needs parentheses to stay part of the definition.
+                let bp = if matches!(body.data, Expr::Comma { .. }) { 2 } else { 0 };
+                let s = format!("{}({}) = {}", name, params.join(", "), walk(body, bp));
+                if parent_prec > 0 {
+                    format!("({s})")
+                } else {
+                    s
+                }
+            }
         }
     }
 
@@ -128,6 +247,111 @@ pub fn print_debug_expr(expr: Spanned<Expr>, indent: usize) {
                     walk(a, indent + 3);
                 }
             }
+            Expr::Block { exprs } => {
+                println!("{pad}Block @ {}..{}", span.start, span.end - 1);
+                println!("{pad}  statements:");
+                for (i, a) in exprs.iter().enumerate() {
+                    println!("{pad}    [{i}]:");
+                    walk(a, indent + 3);
+                }
+            }
+            Expr::List { items } => {
+                println!("{pad}List @ {}..{}", span.start, span.end - 1);
+                for (i, a) in items.iter().enumerate() {
+                    println!("{pad}    [{i}]:");
+                    walk(a, indent + 3);
+                }
+            }
+            Expr::Index { target, index } => {
+                println!("{pad}Index @ {}..{}", span.start, span.end - 1);
+                println!("{pad}  target:");
+                walk(target, indent + 2);
+                println!("{pad}  index:");
+                walk(index, indent + 2);
+            }
+            Expr::Slice { target, start, end } => {
+                println!("{pad}Slice @ {}..{}", span.start, span.end - 1);
+                println!("{pad}  target:");
+                walk(target, indent + 2);
+                for (label, bound) in [("start", start), ("end", end)] {
+                    match bound {
+                        Some(b) => {
+                            println!("{pad}  {label}:");
+                            walk(b, indent + 2);
+                        }
+                        None => println!("{pad}  {label}: <none>"),
+                    }
+                }
+            }
+            Expr::FunctionDef { name, params, body } => {
+                println!(
+                    "{pad}FunctionDef {}({}) @ {}..{}",
+                    name,
+                    params.join(", "),
+                    span.start,
+                    span.end - 1
+                );
+                println!("{pad}  body:");
+                walk(body, indent + 2);
+            }
+            Expr::Let { bindings, body } => {
+                println!("{pad}Let @ {}..{}", span.start, span.end - 1);
+                for (name, value) in bindings {
+                    println!("{pad}  {}:", name);
+                    walk(value, indent + 2);
+                }
+                println!("{pad}  body:");
+                walk(body, indent + 2);
+            }
+            Expr::Lambda { params, body } => {
+                println!(
+                    "{pad}Lambda ({}) @ {}..{}",
+                    params.join(", "),
+                    span.start,
+                    span.end - 1
+                );
+                println!("{pad}  body:");
+                walk(body, indent + 2);
+            }
+            Expr::If { cond, then_branch, else_branch } => {
+                println!("{pad}If @ {}..{}", span.start, span.end - 1);
+                println!("{pad}  cond:");
+                walk(cond, indent + 2);
+                println!("{pad}  then:");
+                walk(then_branch, indent + 2);
+                match else_branch {
+                    Some(e) => {
+                        println!("{pad}  else:");
+                        walk(e, indent + 2);
+                    }
+                    None => println!("{pad}  else: <none>"),
+                }
+            }
+            Expr::While { cond, body } => {
+                println!("{pad}While @ {}..{}", span.start, span.end - 1);
+                println!("{pad}  cond:");
+                walk(cond, indent + 2);
+                println!("{pad}  body:");
+                walk(body, indent + 2);
+            }
+            Expr::For { var, start, end, body } => {
+                println!("{pad}For {} @ {}..{}", var, span.start, span.end - 1);
+                println!("{pad}  start:");
+                walk(start, indent + 2);
+                println!("{pad}  end:");
+                walk(end, indent + 2);
+                println!("{pad}  body:");
+                walk(body, indent + 2);
+            }
+            Expr::Break => {
+                println!("{pad}Break @ {}..{}", span.start, span.end - 1);
+            }
+            Expr::Continue => {
+                println!("{pad}Continue @ {}..{}", span.start, span.end - 1);
+            }
+            Expr::Error => {
+                println!("{pad}Error @ {}..{}", span.start, span.end - 1);
+            }
         }
     }
 
@@ -142,14 +366,31 @@ pub fn print_debug_tokens(tokens: &[Spanned<Token>]) {
     }
 }
 
-pub fn print_debug_user_def_function(functions: &HashMap<String, Box<Spanned<Expr>>>, debug: bool) {
+pub fn print_debug_user_def_function(
+    functions: &HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &HashMap<String, Arc<UserFunction>>,
+    debug: bool
+) {
     println!("User-defined functions:");
 
-    if functions.is_empty() {
+    if functions.is_empty() && user_functions.is_empty() {
         println!("  <empty>");
         return;
     }
 
+    let mut names: Vec<_> = user_functions.keys().collect();
+    names.sort();
+
+    for name in names {
+        if let Some(f) = user_functions.get(name) {
+            println!("  {} = {}", f.signature(name), expr_to_text(&f.body));
+
+            if debug {
+                print_debug_expr(*f.body.clone(), 2);
+            }
+        }
+    }
+
     let mut names: Vec<_> = functions.keys().collect();
     names.sort();
 
@@ -164,7 +405,7 @@ pub fn print_debug_user_def_function(functions: &HashMap<String, Box<Spanned<Exp
     }
 }
 
-pub fn print_debug_vars(vars: &HashMap<String, Value>) {
+pub fn print_debug_vars(vars: &Globals) {
     println!("Variables:");
 
     if vars.is_empty() {
@@ -172,12 +413,38 @@ pub fn print_debug_vars(vars: &HashMap<String, Value>) {
         return;
     }
 
-    let mut keys: Vec<_> = vars.keys().collect();
-    keys.sort();
+    let mut vars: Vec<_> = vars.iter().collect();
+    vars.sort_by_key(|(name, _)| *name);
+
+    for (name, value) in vars {
+        println!("  {} = {:?}", name, value);
+    }
+}
 
-    for key in keys {
-        if let Some(value) = vars.get(key) {
-            println!("  {} = {:?}", key, value);
+pub fn print_debug_functions(functions: &HashMap<String, NativeFunction>) {
+    println!("Functions:");
+
+    let mut names: Vec<_> = functions.keys().collect();
+    names.sort();
+
+    for name in names {
+        if let Some(f) = functions.get(name) {
+            println!("  {:<28} {}", f.signature(name), f.doc);
         }
     }
 }
+
+pub fn print_debug_program(program: &Program) {
+    println!("Bytecode:");
+
+    for (i, (instr, span)) in program.code.iter().zip(program.spans.iter()).enumerate() {
+        println!("  [{:03}] {:<40} @ {}..{}", i, format!("{:?}", instr), span.start, span.end - 1);
+    }
+
+    if !program.constants.is_empty() {
+        println!("  constants: {:?}", program.constants);
+    }
+    if !program.names.is_empty() {
+        println!("  names: {:?}", program.names);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::{
+    error::{ Error, EvalError, ParseError },
+    interpreter::Interpreter,
+    lexer::lex_string,
+    operator::{ BinaryOp, Operator },
+    parser::{ Expr, parse_string },
+    span::{ Span, Spanned, TAB_WIDTH, display_cols, line_col },
+    value::ValueType,
+};
+
+/// Named text that labels point into: a script, an input line or the
+/// source of a macro.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelKind {
+    /// Where the error is, underlined with `^`.
+    Primary,
+    /// Related code, underlined with `-`.
+    Secondary,
+}
+
+/// Message attached to `span` of `sources[source]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub kind: LabelKind,
+    pub source: usize,
+    pub span: Span,
+    pub message: String,
+}
+
+/// An error rendered in the style of rustc:
+///
+/// ```text
+/// error[evaluation]: Unable to unify values [Int(1), Str("a")]
+///  --> script.math:2:7
+///   |
+/// 2 | x = 1 + "a"
+///   |     - ^ --- Str
+///   |     | |
+///   |     | not supported for these operand types
+///   |     Int
+///   |
+///   = note: ...
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Shown in brackets after `error`, such as the stage that failed.
+    pub code: Option<String>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub helps: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic {
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            helps: Vec::new(),
+        }
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn primary(mut self, source: usize, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { kind: LabelKind::Primary, source, span, message: message.into() });
+        self
+    }
+
+    pub fn secondary(mut self, source: usize, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { kind: LabelKind::Secondary, source, span, message: message.into() });
+        self
+    }
+
+    pub fn note(mut self, message: impl Into<String>) -> Self {
+        self.notes.push(message.into());
+        self
+    }
+
+    pub fn help(mut self, message: impl Into<String>) -> Self {
+        self.helps.push(message.into());
+        self
+    }
+
+    /// Renders the diagnostic over `sources`, with ANSI colours if `colour`
+    /// is set. Sources are shown in the order of their first label, each
+    /// line once with all of its labels.
+    pub fn render(&self, sources: &[Source], colour: bool) -> String {
+        let style = Style { colour };
+        let mut out = String::new();
+
+        let title = match &self.code {
+            Some(code) => format!("error[{}]", code),
+            None => "error".to_string(),
+        };
+        out.push_str(&format!("{}{}\n", style.paint(RED, &title), style.paint(BOLD, &format!(": {}", self.message))));
+
+        let mut order: Vec<usize> = Vec::new();
+        for label in &self.labels {
+            if !order.contains(&label.source) && label.source < sources.len() {
+                order.push(label.source);
+            }
+        }
+
+        let width = self.labels
+            .iter()
+            .filter(|l| l.source < sources.len())
+            .map(|l| line_col(&sources[l.source].text, l.span.start).0.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(width);
+        let gutter = style.paint(BLUE, &format!("{} |", pad));
+
+        for (n, &source) in order.iter().enumerate() {
+            let text = &sources[source].text;
+            let labels: Vec<&Label> = self.labels.iter().filter(|l| l.source == source).collect();
+            let first = labels
+                .iter()
+                .find(|l| l.kind == LabelKind::Primary)
+                .unwrap_or(&labels[0]);
+            let (line, col) = line_col(text, first.span.start);
+
+            let arrow = if n == 0 { "-->" } else { ":::" };
+            out.push_str(&format!("{}{} {}:{}:{}\n", pad, style.paint(BLUE, arrow), sources[source].name, line, col));
+            out.push_str(&format!("{}\n", gutter));
+
+            let mut lines: Vec<usize> = labels.iter().map(|l| line_col(text, l.span.start).0).collect();
+            lines.sort();
+            lines.dedup();
+
+            let mut previous: Option<usize> = None;
+            for line in lines {
+                if previous.is_some_and(|p| line > p + 1) {
+                    out.push_str(&format!("{}\n", style.paint(BLUE, "...")));
+                }
+                previous = Some(line);
+
+                let line_text = text.lines().nth(line - 1).unwrap_or("");
+                let number = format!("{:>width$} |", line, width = width);
+                let line_text = line_text.replace('\t', &" ".repeat(TAB_WIDTH));
+                out.push_str(format!("{} {}", style.paint(BLUE, &number), line_text).trim_end());
+                out.push('\n');
+
+                let on_line: Vec<&Label> = labels
+                    .iter()
+                    .copied()
+                    .filter(|l| line_col(text, l.span.start).0 == line)
+                    .collect();
+                for annotation in annotate(text, &on_line, &style) {
+                    out.push_str(format!("{} {}", gutter, annotation).trim_end());
+                    out.push('\n');
+                }
+            }
+        }
+
+        if !self.notes.is_empty() || !self.helps.is_empty() {
+            out.push_str(&format!("{}\n", gutter));
+        }
+        for note in &self.notes {
+            out.push_str(&format!("{} {}: {}\n", style.paint(BLUE, &format!("{} =", pad)), style.paint(BOLD, "note"), note));
+        }
+        for help in &self.helps {
+            out.push_str(&format!("{} {}: {}\n", style.paint(BLUE, &format!("{} =", pad)), style.paint(BOLD, "help"), help));
+        }
+
+        out
+    }
+}
+
+const RED: &str = "1;31";
+const BLUE: &str = "1;34";
+const BOLD: &str = "1";
+
+struct Style {
+    colour: bool,
+}
+
+impl Style {
+    fn paint(&self, code: &str, s: &str) -> String {
+        if self.colour && !s.is_empty() {
+            format!("\x1b[{}m{}\x1b[0m", code, s)
+        } else {
+            s.to_string()
+        }
+    }
+
+    fn label(&self, kind: LabelKind, s: &str) -> String {
+        self.paint(if kind == LabelKind::Primary { RED } else { BLUE }, s)
+    }
+}
+
+/// Lines under a source line of `text` that mark `labels` on it: the
+/// underlines with the message of the rightmost label, then the other
+/// messages, each hanging from its underline.
+fn annotate(text: &str, labels: &[&Label], style: &Style) -> Vec<String> {
+    // Display column and width of each underline, so that it lines up
+    // under wide characters too.
+    let mut marks: Vec<(usize, usize, &Label)> = labels
+        .iter()
+        .map(|l| {
+            let (start, end) = display_cols(text, l.span);
+            (start, (end - start).max(1), *l)
+        })
+        .collect();
+    marks.sort_by_key(|m| (m.0, m.2.kind == LabelKind::Primary));
+
+    let mut underline = String::new();
+    let mut cells: Vec<Option<(char, LabelKind)>> = Vec::new();
+    for &(col, width, label) in &marks {
+        let c = if label.kind == LabelKind::Primary { '^' } else { '-' };
+        if cells.len() < col + width {
+            cells.resize(col + width, None);
+        }
+        for cell in &mut cells[col..col + width] {
+            if cell.is_none_or(|(_, kind)| kind != LabelKind::Primary) {
+                *cell = Some((c, label.kind));
+            }
+        }
+    }
+    for run in cells.chunk_by(|a, b| a == b) {
+        match run[0] {
+            Some((c, kind)) => underline.push_str(&style.label(kind, &c.to_string().repeat(run.len()))),
+            None => underline.push_str(&" ".repeat(run.len())),
+        }
+    }
+
+    let with_message: Vec<&(usize, usize, &Label)> = marks
+        .iter()
+        .filter(|m| !m.2.message.is_empty())
+        .collect();
+
+    // The rightmost message goes at the end of the underlines if no other
+    // label starts in the same column; the rest hang below, the rightmost
+    // first, with `|` leading down from each underline.
+    let hanging = match with_message.split_last() {
+        Some((last, rest)) if rest.iter().all(|m| m.0 < last.0) => {
+            underline.push(' ');
+            underline.push_str(&style.label(last.2.kind, &last.2.message));
+            rest
+        }
+        _ => &with_message[..],
+    };
+
+    let mut lines = vec![underline];
+    if hanging.is_empty() {
+        return lines;
+    }
+
+    let connectors = |count: usize| {
+        let mut s = String::new();
+        let mut width = 0;
+        for m in &hanging[..count] {
+            s.push_str(&" ".repeat(m.0.saturating_sub(width)));
+            s.push_str(&style.label(m.2.kind, "|"));
+            width = m.0 + 1;
+        }
+        (s, width)
+    };
+
+    lines.push(connectors(hanging.len()).0);
+    for (i, m) in hanging.iter().enumerate().rev() {
+        let (mut s, width) = connectors(i);
+        s.push_str(&" ".repeat(m.0.saturating_sub(width)));
+        s.push_str(&style.label(m.2.kind, &m.2.message));
+        lines.push(s);
+    }
+
+    lines
+}
+
+/// Message of `err`, as in the headline of its diagnostic.
+fn message(err: &Error) -> String {
+    match err {
+        Error::LexingError(err) => crate::error::lexing_error::error_to_string(err.clone()),
+        Error::ParseError(err) => crate::error::parse_error::error_to_string(err.clone()),
+        Error::EvalError(err) => crate::error::eval_error::error_to_string(err.clone()),
+        Error::SessionError(err) => crate::error::session_error::error_to_string(err.clone()),
+        Error::UnexpectedError => "Unexpected Error".to_string(),
+    }
+}
+
+/// Describes `err`, whose span is relative to byte `offset` of
+/// `sources[source]`. An error raised inside a macro is shown in the macro
+/// source, which is added to `sources`, with the call site as a secondary
+/// label.
+pub fn describe(
+    err: &Spanned<Error>,
+    source: usize,
+    offset: usize,
+    sources: &mut Vec<Source>,
+    interpreter: &Interpreter
+) -> Diagnostic {
+    let span = Span { start: err.span.start + offset, end: err.span.end + offset };
+
+    if let Error::EvalError(EvalError::InMacro { name, error }) = &err.data
+        && let Some(text) = interpreter.macro_source(name)
+    {
+        sources.push(Source { name: format!("{{{}}}", name), text: text.to_string() });
+        let inner = describe(error, sources.len() - 1, 0, sources, interpreter);
+        return inner.secondary(source, span, "in this macro invocation");
+    }
+
+    let message = match &err.data {
+        Error::EvalError(EvalError::InMacro { error, .. }) => message(&error.data),
+        data => message(data),
+    };
+    let diagnostic = Diagnostic::error(message);
+    let text = &sources[source].text;
+    let at = |s: Span| Span { start: s.start + offset, end: s.end + offset };
+
+    match &err.data {
+        Error::ParseError(ParseError::Unclosed { close, open }) =>
+            diagnostic
+                .primary(source, span, format!("expected '{}'", close.symbol()))
+                .secondary(source, at(*open), "unclosed delimiter"),
+        Error::ParseError(ParseError::MissingTernaryElse { question }) =>
+            diagnostic
+                .primary(source, span, "expected ':'")
+                .secondary(source, at(*question), "this '?' has no ':'"),
+        Error::ParseError(ParseError::LoopControlOutsideLoop { .. }) =>
+            diagnostic
+                .primary(source, span, "")
+                .help("'break' and 'continue' belong in the body of 'while' or 'for'"),
+        Error::EvalError(EvalError::TypeMismatch { op: Operator::Binary(BinaryOp::Assign), found, .. }) =>
+            operands(diagnostic, text, source, span, found, "the variable keeps its type"),
+        Error::EvalError(EvalError::TypeMismatch { found: types, .. })
+        | Error::EvalError(EvalError::OpNotSupported { operand_types: types, .. }) =>
+            operands(diagnostic, text, source, span, types, "not supported for these operand types").note(
+                PROMOTION
+            ),
+        Error::EvalError(EvalError::UnableToUnify { values }) => {
+            let types: Vec<ValueType> = values.iter().map(|v| v.value_type()).collect();
+            operands(diagnostic, text, source, span, &types, "not supported for these operand types").note(
+                PROMOTION
+            )
+        }
+        Error::EvalError(EvalError::NameNotFound { suggestions, .. }) => {
+            let diagnostic = diagnostic.primary(source, span, "not found");
+            let names: Vec<String> = suggestions.iter().map(|s| format!("`{}`", s)).collect();
+            match names.as_slice() {
+                [] => diagnostic,
+                [name] => diagnostic.help(format!("did you mean {}?", name)),
+                names => diagnostic.help(format!("did you mean one of {}?", names.join(", "))),
+            }
+        }
+        Error::EvalError(EvalError::InvalidCondition { found }) =>
+            diagnostic
+                .primary(source, span, format!("found {:?}", found))
+                .note("conditions are Booleans, or numbers that are true when not zero"),
+        Error::EvalError(EvalError::InvalidRange { .. }) =>
+            diagnostic
+                .primary(source, span, "")
+                .note("'for' ranges are half-open and need Int bounds"),
+        _ => diagnostic.primary(source, span, ""),
+    }
+}
+
+const PROMOTION: &str =
+    "Boolean, Int, BigInt, Rational, Float and Complex promote to each other in that order; Str, List, Matrix and Function do not";
+
+/// Labels both operands of the binary expression at `span` with their
+/// types and the operator with `message` as the primary label. Falls back to a single
+/// label if `span` is not a binary expression.
+fn operands(
+    diagnostic: Diagnostic,
+    text: &str,
+    source: usize,
+    span: Span,
+    types: &[ValueType],
+    message: &str
+) -> Diagnostic {
+    let binary = text
+        .get(span.start..span.end)
+        .and_then(|snippet| parse_string(snippet, false).ok())
+        .and_then(|expr| {
+            match expr.data {
+                Expr::Binary { lhs, rhs, .. } => Some((lhs.span, rhs.span)),
+                _ => None,
+            }
+        });
+
+    let (Some((lhs, rhs)), [left, right]) = (binary, types) else {
+        return diagnostic.primary(source, span, "");
+    };
+    let at = |s: Span| Span { start: s.start + span.start, end: s.end + span.start };
+
+    // The operator is the first token between the operands; implicit
+    // multiplication has none.
+    let operator = text
+        .get(span.start + lhs.end..span.start + rhs.start)
+        .and_then(|between| lex_string(between).ok())
+        .and_then(|tokens| tokens.first().map(|t| t.span))
+        .map(|t| Span { start: t.start + span.start + lhs.end, end: t.end + span.start + lhs.end })
+        .unwrap_or(span);
+
+    diagnostic
+        .primary(source, operator, message)
+        .secondary(source, at(lhs), format!("{:?}", left))
+        .secondary(source, at(rhs), format!("{:?}", right))
+}
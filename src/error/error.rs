@@ -1,9 +1,13 @@
 use crate::error::eval_error::*;
 use crate::error::lexing_error::*;
+use crate::error::parse_error::*;
+use crate::error::session_error::*;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     LexingError(LexingError),
+    ParseError(ParseError),
     EvalError(EvalError),
+    SessionError(SessionError),
     UnexpectedError,
 }
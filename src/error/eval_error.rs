@@ -1,4 +1,9 @@
+use crate::error::Error;
+use crate::native_function::FnArity;
 use crate::operator::Operator;
+use crate::scope::ScopeKind;
+use crate::span::Spanned;
+use crate::token::Keyword;
 use crate::value::{ Value, ValueType };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -7,7 +12,7 @@ pub enum Arity {
     Binary,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NameKind {
     Function,
     Macro,
@@ -43,9 +48,14 @@ pub enum EvalError {
     },
 
     /// A referenced name was not found in the current environment/scope.
+    /// `searched` lists the variable scopes that were searched, innermost
+    /// first, and is empty for functions and macros. `suggestions` holds
+    /// close names from every namespace, as they would be written.
     NameNotFound {
         kind: NameKind,
         name: String,
+        searched: Vec<ScopeKind>,
+        suggestions: Vec<String>,
     },
 
     /// Operands are invalid for this operation (even if types look acceptable).
@@ -66,11 +76,113 @@ pub enum EvalError {
         op: Operator,
     },
 
+    /// A function was called with the wrong number of arguments.
     ArityMismatch {
         func: String,
-        expected: usize,
+        expected: FnArity,
         found: usize,
     },
+
+    /// User function calls or macro expansions were nested too deeply,
+    /// usually because of unbounded recursion.
+    RecursionLimit {
+        kind: NameKind,
+        func: String,
+        depth: usize,
+    },
+
+    /// A function argument has the right type but an unusable value.
+    InvalidArgument {
+        func: String,
+        arg: Value,
+    },
+
+    /// A function argument could not be promoted to the declared parameter type.
+    ArgTypeMismatch {
+        func: String,
+        index: usize,
+        expected: ValueType,
+        found: ValueType,
+    },
+
+    /// Element-wise operator applied to lists of different lengths.
+    LengthMismatch {
+        op: Operator,
+        left: usize,
+        right: usize,
+    },
+
+    /// Matrix or vector operands have shapes that do not fit together.
+    /// `shapes` lists the operand shapes as rows x columns, with lists
+    /// counted as column vectors; `operand` is the position of the operand
+    /// that does not fit, which the error span points at.
+    DimensionMismatch {
+        op: String,
+        shapes: Vec<(usize, usize)>,
+        operand: usize,
+    },
+
+    /// Indexed a value that has no elements.
+    NotIndexable {
+        found: ValueType,
+    },
+
+    /// A user function was defined with the name of a native function,
+    /// which it would hide.
+    BuiltinRedefined {
+        name: String,
+    },
+
+    /// Call target is a value that is not a function. `name` is set when
+    /// the target is a variable called by name, as in `x(1)`.
+    NotCallable {
+        name: Option<String>,
+        found: ValueType,
+    },
+
+    /// Bound of a `for` range is not an integer.
+    InvalidRange {
+        start: Value,
+        end: Value,
+    },
+
+    /// Condition of `if`, `while` or `?:` is not a Boolean and does not
+    /// promote to one.
+    InvalidCondition {
+        found: ValueType,
+    },
+
+    /// `break` or `continue` reached no enclosing loop. Loops consume this
+    /// while it is raised inside their body.
+    LoopControl {
+        keyword: Keyword,
+    },
+
+    /// Index or slice bound is not an integer.
+    InvalidIndex {
+        index: Value,
+    },
+
+    /// Index is past either end of the indexed value.
+    IndexOutOfRange {
+        index: i64,
+        len: usize,
+    },
+
+    /// `error` was raised in the body of the macro `{name}`, with its span in
+    /// the macro source. The error wrapping it is located at the call site.
+    InMacro {
+        name: String,
+        error: Box<Spanned<Error>>,
+    },
+}
+
+/// `a` or `an`, whichever goes before the name of `ty`.
+fn article(ty: ValueType) -> &'static str {
+    match ty {
+        ValueType::Int => "an",
+        _ => "a",
+    }
 }
 
 pub fn error_to_string(err: EvalError) -> String {
@@ -93,7 +205,17 @@ pub fn error_to_string(err: EvalError) -> String {
 
         EvalError::UnableToUnify { values } => { format!("Unable to unify values {:?}", values) }
 
-        EvalError::NameNotFound { kind, name } => { format!("{:?} '{}' not found", kind, name) }
+        EvalError::NameNotFound { kind, name, searched, .. } => {
+            if searched.is_empty() {
+                format!("{:?} '{}' not found", kind, name)
+            } else {
+                let searched = searched
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>();
+                format!("{:?} '{}' not found (searched: {})", kind, name, searched.join(", "))
+            }
+        }
 
         EvalError::InvalidOperands { op, operands } => {
             format!("Invalid operands {:?} for operator {:?}", operands, op)
@@ -120,5 +242,77 @@ pub fn error_to_string(err: EvalError) -> String {
                 found
             )
         }
+
+        EvalError::RecursionLimit { kind, func, depth } => {
+            format!("{:?} {:?} exceeded the maximum call depth of {}", kind, func, depth)
+        }
+
+        EvalError::InvalidArgument { func, arg } => {
+            format!("Invalid argument {} for function {:?}", arg.symbol(), func)
+        }
+
+        EvalError::ArgTypeMismatch { func, index, expected, found } => {
+            format!(
+                "Argument {} of function {:?} expects {:?}, found {:?}",
+                index + 1,
+                func,
+                expected,
+                found
+            )
+        }
+
+        EvalError::LengthMismatch { op, left, right } => {
+            format!(
+                "Operator {:?} applied element-wise to lists of length {} and {}",
+                op,
+                left,
+                right
+            )
+        }
+
+        EvalError::DimensionMismatch { op, shapes, .. } => {
+            let shapes = shapes
+                .iter()
+                .map(|(r, c)| format!("{}x{}", r, c))
+                .collect::<Vec<_>>();
+            let noun = if shapes.len() == 1 { "shape" } else { "shapes" };
+            format!("Dimension mismatch in {} for {} {}", op, noun, shapes.join(" and "))
+        }
+
+        EvalError::NotIndexable { found } => format!("Values of type {:?} cannot be indexed", found),
+
+        EvalError::BuiltinRedefined { name } => {
+            format!("Cannot define '{}': it is a builtin function", name)
+        }
+        EvalError::NotCallable { name: Some(name), found } => {
+            format!("'{}' is {} {:?}, not a function", name, article(found), found)
+        }
+        EvalError::NotCallable { name: None, found } => format!("Values of type {:?} cannot be called", found),
+
+        EvalError::InvalidRange { start, end } => {
+            format!("Range {}..{} does not have integer bounds", start.symbol(), end.symbol())
+        }
+
+        EvalError::InvalidCondition { found } => {
+            format!("Condition is {} {:?}, not a Boolean or a number", article(found), found)
+        }
+
+        EvalError::LoopControl { keyword } => format!("'{}' outside of a loop", keyword.symbol()),
+
+        EvalError::InvalidIndex { index } => {
+            format!("Index {} is not an integer", index.symbol())
+        }
+
+        EvalError::IndexOutOfRange { index, len } => {
+            format!("Index {} is out of range for length {}", index, len)
+        }
+
+        EvalError::InMacro { name, error } => {
+            let inner = match error.data {
+                Error::EvalError(err) => error_to_string(err),
+                _ => "Unexpected Error".to_string(),
+            };
+            format!("{} (in macro {{{}}})", inner, name)
+        }
     }
 }
@@ -2,12 +2,23 @@
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LexingError {
     InvalidToken { src: String, index: usize },
+    UnterminatedString { index: usize },
+    InvalidEscape { index: usize },
+    /// `/*` without a matching `*/`. `index` is the position of the opener.
+    UnterminatedComment { index: usize },
 }
 
 pub fn error_to_string(err: LexingError) -> String {
+    // The span of the error locates it, so messages leave out `index`.
     match err {
-        LexingError::InvalidToken { src: _, index } => {
-            format!("Invalid token at position {}", index)
+        LexingError::InvalidToken { src, index } => {
+            match src.get(index..).and_then(|s| s.chars().next()) {
+                Some(c) => format!("Invalid token '{}'", c),
+                None => "Invalid token".to_string(),
+            }
         }
+        LexingError::UnterminatedString { .. } => "Unterminated string literal".to_string(),
+        LexingError::InvalidEscape { .. } => "Invalid escape sequence".to_string(),
+        LexingError::UnterminatedComment { .. } => "Unterminated block comment".to_string(),
     }
 }
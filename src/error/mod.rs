@@ -1,8 +1,15 @@
 pub mod lexing_error;
-pub use lexing_error::*;
+pub use lexing_error::LexingError;
 
 pub mod eval_error;
-pub use eval_error::*;
+pub use eval_error::{ Arity, EvalError, NameKind };
 
+pub mod parse_error;
+pub use parse_error::ParseError;
+
+pub mod session_error;
+pub use session_error::SessionError;
+
+#[allow(clippy::module_inception)]
 pub mod error;
 pub use error::*;
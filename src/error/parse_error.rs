@@ -0,0 +1,68 @@
+use crate::span::Span;
+use crate::token::{ Keyword, Token };
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// A token that cannot appear where it was found.
+    UnexpectedToken { token: Token },
+    /// The input ended in the middle of an expression.
+    UnexpectedEnd,
+    /// A specific token is required next, such as `in` after the bindings
+    /// of `let`. `found` is `None` at the end of the input.
+    Expected { expected: Token, found: Option<Token> },
+    ExpectedIdentifier { found: Option<Token> },
+    /// `)`, `]` or `}` is missing for the opening delimiter at `open`.
+    Unclosed { close: Token, open: Span },
+    /// The `?` of a ternary at `question` has no `:`.
+    MissingTernaryElse { question: Span },
+    /// Tokens after a complete expression, starting with `token`.
+    TrailingInput { token: Token },
+    EmptyInput,
+    /// `break` or `continue` outside of a loop body.
+    LoopControlOutsideLoop { keyword: Keyword },
+    /// The left side of `=>` is not a parameter list.
+    InvalidParameters,
+    /// A function or lambda has two parameters called `name`.
+    DuplicateParameter { name: String },
+}
+
+/// `found` as quoted in messages.
+fn found_text(found: &Option<Token>) -> String {
+    match found {
+        Some(t) => format!("'{}'", t.symbol()),
+        None => "end of input".to_string(),
+    }
+}
+
+pub fn error_to_string(err: ParseError) -> String {
+    match err {
+        ParseError::UnexpectedToken { token } => format!("Unexpected '{}'", token.symbol()),
+        ParseError::UnexpectedEnd => "Unexpected end of input".to_string(),
+        ParseError::Expected { expected, found } => {
+            format!("Expected '{}', found {}", expected.symbol(), found_text(&found))
+        }
+        ParseError::ExpectedIdentifier { found } => {
+            format!("Expected an identifier, found {}", found_text(&found))
+        }
+        ParseError::Unclosed { close, .. } => {
+            let opening = match close.symbol().as_str() {
+                ")" => "(",
+                "]" => "[",
+                _ => "{",
+            };
+            format!("Expected '{}' to close the '{}'", close.symbol(), opening)
+        }
+        ParseError::MissingTernaryElse { .. } => "Expected ':' for the '?'".to_string(),
+        ParseError::TrailingInput { token } => {
+            format!("Unexpected '{}' after the end of the expression", token.symbol())
+        }
+        ParseError::EmptyInput => "Expected an expression, found empty input".to_string(),
+        ParseError::LoopControlOutsideLoop { keyword } => {
+            format!("'{}' outside of a loop", keyword.symbol())
+        }
+        ParseError::InvalidParameters => "Lambda parameters must be identifiers".to_string(),
+        ParseError::DuplicateParameter { name } => {
+            format!("Parameter '{}' is declared more than once", name)
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::value::ValueType;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionError {
+    /// The file does not start with the `math_interpreter session` line.
+    MissingHeader,
+    /// The file was written by a newer, unknown format version.
+    UnsupportedVersion { version: String },
+    /// A line that is neither a `var`, a `macro` nor a comment.
+    InvalidLine,
+    UnknownType { name: String },
+    /// Text that is not a value of the session format.
+    InvalidValue { src: String },
+    /// The line ends before its value is complete.
+    UnexpectedEndOfLine,
+    /// The value of `name` is not of the type its line declares.
+    TypeMismatch { name: String, declared: ValueType, found: ValueType },
+    /// Reading or writing the session file failed.
+    Io { path: String, message: String },
+}
+
+pub fn error_to_string(err: SessionError) -> String {
+    match err {
+        SessionError::MissingHeader => {
+            "Not a session file: missing 'math_interpreter session' header".to_string()
+        }
+        SessionError::UnsupportedVersion { version } => {
+            format!("Unsupported session format version {}", version)
+        }
+        SessionError::InvalidLine => "Expected 'var name: Type = value' or 'macro name = source'".to_string(),
+        SessionError::UnknownType { name } => format!("Unknown value type '{}'", name),
+        SessionError::InvalidValue { src } => format!("Invalid value '{}'", src),
+        SessionError::UnexpectedEndOfLine => "Unexpected end of line".to_string(),
+        SessionError::TypeMismatch { name, declared, found } => {
+            format!("Variable '{}' is declared {:?} but its value is {:?}", name, declared, found)
+        }
+        SessionError::Io { path, message } => format!("Cannot access '{}': {}", path, message),
+    }
+}
@@ -1,12 +1,17 @@
 use crate::{
     error::{ Error, EvalError, NameKind },
+    native_function::{ Caller, NativeFunction },
     operator::*,
     parser::Expr,
+    scope::{ ScopeKind, Scopes },
     span::{ Span, Spanned },
+    suggestion::{ Namespaces, suggest },
+    token::Keyword,
+    user_function::{ CallGuard, LAMBDA_NAME, Lambda, UserFunction, grow_stack },
     value::{ Value, ValueType },
 };
 
-use std::collections::HashMap;
+use std::{ collections::HashMap, sync::Arc };
 
 #[derive(Debug, PartialEq)]
 pub enum EvalResult<'a> {
@@ -30,7 +35,7 @@ impl<'a> EvalResult<'a> {
 
     pub fn as_value(&self) -> Option<Value> {
         match self {
-            EvalResult::Value(v) => Some(*v),
+            EvalResult::Value(v) => Some(v.clone()),
             _ => None,
         }
     }
@@ -43,8 +48,65 @@ impl<'a> EvalResult<'a> {
     }
 }
 
+/// Moves an error raised in the body of the macro `{name}` to its call site
+/// at `call`, keeping the original inside. A recursion limit error is only
+/// moved, since it would otherwise be wrapped once per nested expansion.
+fn in_macro(name: &str, call: Span, err: Spanned<Error>) -> Spanned<Error> {
+    if let Error::EvalError(EvalError::RecursionLimit { .. }) = err.data {
+        return Spanned { span: call, data: err.data };
+    }
+    Spanned {
+        span: call,
+        data: Error::EvalError(EvalError::InMacro { name: name.to_string(), error: Box::new(err) }),
+    }
+}
+
+/// Adds the names close to the one missing in a `NameNotFound` error,
+/// taken from every namespace visible here.
+fn with_suggestions(
+    err: Error,
+    scopes: &Scopes,
+    user_def_functions: &HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Error {
+    match err {
+        Error::EvalError(EvalError::NameNotFound { kind, name, searched, .. }) => {
+            let namespaces = Namespaces {
+                variables: scopes.names().collect(),
+                functions: functions
+                    .keys()
+                    .chain(user_functions.keys())
+                    .map(String::as_str)
+                    .collect(),
+                macros: user_def_functions.keys().map(String::as_str).collect(),
+            };
+            let suggestions = suggest(kind, &name, &namespaces);
+            Error::EvalError(EvalError::NameNotFound { kind, name, searched, suggestions })
+        }
+        err => err,
+    }
+}
+
+/// The variable `name`, or `NameNotFound` at `span` with suggestions.
+fn lookup_variable<'s>(
+    name: &str,
+    span: Span,
+    scopes: &'s mut Scopes,
+    user_def_functions: &HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<&'s mut Value, Spanned<Error>> {
+    if let Err(err) = scopes.lookup(name) {
+        let data = with_suggestions(err, scopes, user_def_functions, user_functions, functions);
+        return Err(Spanned { span, data });
+    }
+    scopes.lookup_mut(name).map_err(|err| Spanned { span, data: err })
+}
+
 fn is_assign(op: Operator) -> bool {
-    match op {
+    matches!(
+        op,
         | Operator::Binary(BinaryOp::Assign)
         | Operator::Binary(BinaryOp::AddAssign)
         | Operator::Binary(BinaryOp::SubAssign)
@@ -55,60 +117,189 @@ fn is_assign(op: Operator) -> bool {
         | Operator::Binary(BinaryOp::OrAssign)
         | Operator::Binary(BinaryOp::BitAndAssign)
         | Operator::Binary(BinaryOp::BitOrAssign)
-        | Operator::Binary(BinaryOp::BitXorAssign) => true,
-        _ => false,
-    }
+        | Operator::Binary(BinaryOp::BitXorAssign)
+    )
 }
 
 fn is_cond(op: Operator) -> bool {
-    match op {
-        Operator::Binary(BinaryOp::And) | Operator::Binary(BinaryOp::Or) => true,
-        _ => false,
+    matches!(op, Operator::Binary(BinaryOp::And) | Operator::Binary(BinaryOp::Or))
+}
+
+/// Evaluates `expr` to an owned value.
+fn evaluate_value(
+    expr: &Spanned<Expr>,
+    scopes: &mut Scopes,
+    user_def_functions: &mut HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<Value, Spanned<Error>> {
+    match evaluate_expr(expr, scopes, user_def_functions, user_functions, functions)? {
+        EvalResult::Value(v) => Ok(v),
+        EvalResult::Ref(r) => Ok(r.clone()),
+    }
+}
+
+/// Evaluates the body of a user function or lambda in a call scope holding
+/// `locals`. Errors raised in the body keep their data but are reported at
+/// `call_span`, since the body spans refer to the source of the definition.
+#[allow(clippy::too_many_arguments)]
+fn call_user_function(
+    name: &str,
+    body: &Spanned<Expr>,
+    locals: HashMap<String, Value>,
+    call_span: Span,
+    scopes: &mut Scopes,
+    user_def_functions: &mut HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<Value, Spanned<Error>> {
+    let _guard = CallGuard::enter(NameKind::Function, name).map_err(|err| Spanned { span: call_span, data: err })?;
+
+    grow_stack(|| {
+        scopes.call(name, locals, |scopes| {
+            evaluate_value(body, scopes, user_def_functions, user_functions, functions)
+        })
+    }).map_err(|err| Spanned { span: call_span, data: err.data })
+}
+
+/// Calls the function values passed to a native function, such as the
+/// function given to `map`.
+struct NativeCaller<'a> {
+    /// Call of the native function.
+    span: Span,
+    scopes: &'a mut Scopes,
+    user_def_functions: &'a mut HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &'a mut HashMap<String, Arc<UserFunction>>,
+    functions: &'a HashMap<String, NativeFunction>,
+}
+
+impl Caller for NativeCaller<'_> {
+    fn call(&mut self, func: &Lambda, args: Vec<Value>) -> Result<Value, Error> {
+        let locals = func.bind(LAMBDA_NAME, args)?;
+        call_user_function(
+            LAMBDA_NAME,
+            &func.func.body,
+            locals,
+            self.span,
+            self.scopes,
+            self.user_def_functions,
+            self.user_functions,
+            self.functions
+        ).map_err(|err| err.data)
+    }
+}
+
+/// Binds `bindings` in order in the innermost scope, so that later values
+/// see earlier names, then evaluates `body`.
+fn evaluate_let(
+    bindings: &[(String, Spanned<Expr>)],
+    body: &Spanned<Expr>,
+    scopes: &mut Scopes,
+    user_def_functions: &mut HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<Value, Spanned<Error>> {
+    for (name, value) in bindings {
+        let v = evaluate_value(value, scopes, user_def_functions, user_functions, functions)?;
+        scopes.define(name, v);
+    }
+    evaluate_value(body, scopes, user_def_functions, user_functions, functions)
+}
+
+/// Evaluates the condition of `if` or `while`.
+fn evaluate_condition(
+    cond: &Spanned<Expr>,
+    scopes: &mut Scopes,
+    user_def_functions: &mut HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<bool, Spanned<Error>> {
+    let value = evaluate_value(cond, scopes, user_def_functions, user_functions, functions)?;
+    value.as_condition().map_err(|err| Spanned { span: cond.span, data: err })
+}
+
+/// Runs one iteration of a loop body, storing its value in `last` if it
+/// runs to the end. Returns whether the loop goes on, which is false after
+/// `break`.
+fn loop_iteration(
+    body: &Spanned<Expr>,
+    last: &mut Value,
+    scopes: &mut Scopes,
+    user_def_functions: &mut HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<bool, Spanned<Error>> {
+    match evaluate_value(body, scopes, user_def_functions, user_functions, functions) {
+        Ok(v) => {
+            *last = v;
+            Ok(true)
+        }
+        Err(Spanned { data: Error::EvalError(EvalError::LoopControl { keyword }), .. }) =>
+            Ok(keyword == Keyword::Continue),
+        Err(err) => Err(err),
+    }
+}
+
+/// Runs `body` with `var` bound to each integer of `range` in the innermost
+/// scope.
+fn evaluate_for(
+    var: &str,
+    range: std::ops::Range<i64>,
+    body: &Spanned<Expr>,
+    scopes: &mut Scopes,
+    user_def_functions: &mut HashMap<String, Box<Spanned<Expr>>>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<Value, Spanned<Error>> {
+    let mut last = Value::Boolean(false);
+    for i in range {
+        scopes.define(var, Value::Int(i));
+        if !loop_iteration(body, &mut last, scopes, user_def_functions, user_functions, functions)? {
+            break;
+        }
     }
+    Ok(last)
 }
 
 pub fn evaluate_expr<'a>(
     expr: &Spanned<Expr>,
-    variables: &'a mut HashMap<String, Value>,
+    scopes: &'a mut Scopes,
     user_def_functions: &'a mut HashMap<String, Box<Spanned<Expr>>>,
-    functions: &HashMap<String, fn(&Vec<Value>) -> Result<Value, Error>>
+    user_functions: &'a mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
 ) -> Result<EvalResult<'a>, Spanned<Error>> {
     match &expr.data {
-        Expr::Value(v) => Ok(EvalResult::Value(*v)),
-        Expr::Identifier(s) => {
-            if let Some(v) = variables.get_mut(s) {
-                Ok(EvalResult::Ref(v))
-            } else {
-                Err(Spanned {
-                    span: expr.span,
-                    data: Error::EvalError(EvalError::NameNotFound {
-                        kind: NameKind::Variable,
-                        name: s.to_string(),
-                    }),
-                })
-            }
-        }
+        Expr::Value(v) => Ok(EvalResult::Value(v.clone())),
+        Expr::Identifier(s) =>
+            lookup_variable(s, expr.span, scopes, user_def_functions, user_functions, functions).map(
+                EvalResult::Ref
+            ),
         Expr::Macro(s) => {
             if let Some(v) = user_def_functions.get(s).cloned() {
-                evaluate_expr(&v, variables, user_def_functions, functions)
+                let _guard = CallGuard::enter(NameKind::Macro, s).map_err(|err| Spanned {
+                    span: expr.span,
+                    data: err,
+                })?;
+                grow_stack(|| {
+                    evaluate_expr(&v, scopes, user_def_functions, user_functions, functions)
+                }).map_err(|err| in_macro(s, expr.span, err))
             } else {
+                let err = Error::EvalError(EvalError::NameNotFound {
+                    kind: NameKind::Macro,
+                    name: s.to_string(),
+                    searched: Vec::new(),
+                    suggestions: Vec::new(),
+                });
                 Err(Spanned {
                     span: expr.span,
-                    data: Error::EvalError(EvalError::NameNotFound {
-                        kind: NameKind::Macro,
-                        name: s.to_string(),
-                    }),
+                    data: with_suggestions(err, scopes, user_def_functions, user_functions, functions),
                 })
             }
         }
         Expr::Binary { op, lhs, rhs } => {
-            let mut l: EvalResult<'_>;
-            let left: Value;
-            let right: Value;
-
-            if !is_assign(*op) {
-                l = evaluate_expr(&lhs, variables, user_def_functions, functions)?;
-                left = (
+            let left = if !is_assign(*op) {
+                let mut l = evaluate_expr(lhs, scopes, user_def_functions, user_functions, functions)?;
+                (
                     match l.result_type() {
                         EvalResultType::Value => l.as_value(),
                         EvalResultType::Ref => l.as_ref().cloned(),
@@ -116,14 +307,14 @@ pub fn evaluate_expr<'a>(
                 ).ok_or(Spanned {
                     span: expr.span,
                     data: Error::UnexpectedError,
-                })?;
+                })?
             } else {
-                left = Value::Boolean(false); // dummy
-            }
+                Value::Boolean(false) // dummy
+            };
 
-            if !is_cond(*op) {
-                let mut r = evaluate_expr(&rhs, variables, user_def_functions, functions)?;
-                right = (
+            let right = if !is_cond(*op) {
+                let mut r = evaluate_expr(rhs, scopes, user_def_functions, user_functions, functions)?;
+                (
                     match r.result_type() {
                         EvalResultType::Value => r.as_value(),
                         EvalResultType::Ref => r.as_ref().cloned(),
@@ -131,16 +322,17 @@ pub fn evaluate_expr<'a>(
                 ).ok_or(Spanned {
                     span: expr.span,
                     data: Error::UnexpectedError,
-                })?;
+                })?
             } else {
-                right = Value::Boolean(false); // dummy
-            }
+                Value::Boolean(false) // dummy
+            };
 
             let result = (
                 match op {
                     Operator::Binary(BinaryOp::Addition) => add::apply(&left, &right),
                     Operator::Binary(BinaryOp::Subtraction) => sub::apply(&left, &right),
                     Operator::Binary(BinaryOp::Multiplication) => mul::apply(&left, &right),
+                    Operator::Binary(BinaryOp::ElementMul) => elem_mul::apply(&left, &right),
                     Operator::Binary(BinaryOp::Division) => div::apply(&left, &right),
                     Operator::Binary(BinaryOp::Modulo) => modulo::apply(&left, &right),
                     Operator::Binary(BinaryOp::Exponentiation) => exp::apply(&left, &right),
@@ -152,7 +344,7 @@ pub fn evaluate_expr<'a>(
                         if let Some(Value::Boolean(b)) = left.promote(ValueType::Boolean) && !b {
                             return Ok(EvalResult::Value(Value::Boolean(false)));
                         }
-                        let mut r = evaluate_expr(&rhs, variables, user_def_functions, functions)?;
+                        let mut r = evaluate_expr(rhs, scopes, user_def_functions, user_functions, functions)?;
                         let right = (
                             match r.result_type() {
                                 EvalResultType::Value => r.as_value(),
@@ -168,7 +360,7 @@ pub fn evaluate_expr<'a>(
                         if let Some(Value::Boolean(b)) = left.promote(ValueType::Boolean) && b {
                             return Ok(EvalResult::Value(Value::Boolean(true)));
                         }
-                        let mut r = evaluate_expr(&rhs, variables, user_def_functions, functions)?;
+                        let mut r = evaluate_expr(rhs, scopes, user_def_functions, user_functions, functions)?;
                         let right = (
                             match r.result_type() {
                                 EvalResultType::Value => r.as_value(),
@@ -178,19 +370,13 @@ pub fn evaluate_expr<'a>(
                             span: expr.span,
                             data: Error::UnexpectedError,
                         })?;
-                        and::apply(&left, &right)
+                        or::apply(&left, &right)
                     }
 
                     Operator::Binary(BinaryOp::Assign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.entry(name.clone()).or_insert_with(|| {
-                                    match right.value_type() {
-                                        ValueType::Boolean => Value::Boolean(false),
-                                        ValueType::Int => Value::Int(0),
-                                        ValueType::Float => Value::Float(0.0),
-                                    }
-                                });
+                                let slot = scopes.slot(name, || right.value_type().default_value());
 
                                 assign::apply(slot, &right)
                             }
@@ -202,15 +388,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // =
                     Operator::Binary(BinaryOp::AddAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 add_assign::apply(slot, &right)
                             }
@@ -222,15 +409,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // +=
                     Operator::Binary(BinaryOp::SubAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 sub_assign::apply(slot, &right)
                             }
@@ -242,15 +430,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // -=
                     Operator::Binary(BinaryOp::MulAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 mul_assign::apply(slot, &right)
                             }
@@ -262,15 +451,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // *=
                     Operator::Binary(BinaryOp::DivAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 div_assign::apply(slot, &right)
                             }
@@ -282,15 +472,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // /=
                     Operator::Binary(BinaryOp::ModAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 mod_assign::apply(slot, &right)
                             }
@@ -302,15 +493,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // %=
                     Operator::Binary(BinaryOp::AndAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 and_assign::apply(slot, &right)
                             }
@@ -322,15 +514,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // &&=
                     Operator::Binary(BinaryOp::OrAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 or_assign::apply(slot, &right)
                             }
@@ -342,15 +535,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // ||=
                     Operator::Binary(BinaryOp::BitAndAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 bitand_assign::apply(slot, &right)
                             }
@@ -362,15 +556,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // &=
                     Operator::Binary(BinaryOp::BitOrAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 bitor_assign::apply(slot, &right)
                             }
@@ -382,15 +577,16 @@ pub fn evaluate_expr<'a>(
                                 ),
                         } // |=
                     Operator::Binary(BinaryOp::BitXorAssign) =>
-                        match &(**lhs).data {
+                        match &lhs.data {
                             Expr::Identifier(name) => {
-                                let slot = variables.get_mut(name).ok_or(Spanned {
-                                    span: expr.span,
-                                    data: Error::EvalError(EvalError::NameNotFound {
-                                        kind: NameKind::Variable,
-                                        name: name.clone(),
-                                    }),
-                                })?;
+                                let slot = lookup_variable(
+                                    name,
+                                    expr.span,
+                                    scopes,
+                                    user_def_functions,
+                                    user_functions,
+                                    functions
+                                )?;
 
                                 bitxor_assign::apply(slot, &right)
                             }
@@ -411,12 +607,22 @@ pub fn evaluate_expr<'a>(
 
                     _ => Err(Error::UnexpectedError),
                 }
-            ).map_err(|err| Spanned {
-                span: Span {
-                    start: lhs.span.start,
-                    end: rhs.span.end,
-                },
-                data: err,
+            ).map_err(|err| {
+                match err {
+                    Error::EvalError(EvalError::DimensionMismatch { operand, .. }) =>
+                        Spanned {
+                            span: if operand == 0 { lhs.span } else { rhs.span },
+                            data: err,
+                        },
+                    _ =>
+                        Spanned {
+                            span: Span {
+                                start: lhs.span.start,
+                                end: rhs.span.end,
+                            },
+                            data: err,
+                        },
+                }
             })?;
 
             if result.1 {
@@ -433,7 +639,7 @@ pub fn evaluate_expr<'a>(
             }
         }
         Expr::Unary { op, rhs } => {
-            let mut v = evaluate_expr(&rhs, variables, user_def_functions, functions)?;
+            let mut v = evaluate_expr(rhs, scopes, user_def_functions, user_functions, functions)?;
             let value = (
                 match v.result_type() {
                     EvalResultType::Value => v.as_value(),
@@ -470,7 +676,7 @@ pub fn evaluate_expr<'a>(
             }
         }
         Expr::Ternary { cond, statement1, statement2 } => {
-            let mut cond_v = evaluate_expr(&cond, variables, user_def_functions, functions)?;
+            let mut cond_v = evaluate_expr(cond, scopes, user_def_functions, user_functions, functions)?;
             let cond_value = (
                 match cond_v.result_type() {
                     EvalResultType::Value => cond_v.as_value(),
@@ -486,55 +692,96 @@ pub fn evaluate_expr<'a>(
                     data: err,
                 })?;
 
-            let cond_bool = cond_value
-                .promote(ValueType::Boolean)
-                .ok_or(Error::UnexpectedError)
-                .map_err(|err| Spanned {
-                    span: Span {
-                        start: cond.span.start,
-                        end: statement2.span.end,
-                    },
-                    data: err,
-                })?;
+            let cond_bool = cond_value.as_condition().map_err(|err| Spanned {
+                span: cond.span,
+                data: err,
+            })?;
 
-            if let Some(b) = cond_bool.as_boolean() {
-                if b {
-                    evaluate_expr(&statement1, variables, user_def_functions, functions)
-                } else {
-                    evaluate_expr(&statement2, variables, user_def_functions, functions)
-                }
+            if cond_bool {
+                evaluate_expr(statement1, scopes, user_def_functions, user_functions, functions)
             } else {
-                Err(Spanned {
-                    span: Span {
-                        start: cond.span.start,
-                        end: statement2.span.end,
-                    },
-                    data: Error::UnexpectedError,
-                })
+                evaluate_expr(statement2, scopes, user_def_functions, user_functions, functions)
             }
         }
-        Expr::Call { func, args } =>
+        Expr::Call { func, args } => {
+            let call_span = Span {
+                start: func.span.start,
+                end: if let Some(e) = args.last() { e.span.end } else { func.span.end },
+            };
+            let at_call = |err: Error| Spanned { span: call_span, data: err };
+
             match &func.data {
                 Expr::Identifier(s) => {
-                    let f = functions.get(s).ok_or(Spanned {
-                        span: Span {
-                            start: func.span.start,
-                            end: if let Some(e) = args.last() {
-                                e.span.end
-                            } else {
-                                func.span.end
-                            },
-                        },
-                        data: Error::EvalError(EvalError::NameNotFound {
+                    if let Some(f) = user_functions.get(s).cloned() {
+                        let mut v = Vec::with_capacity(args.len());
+                        for arg in args {
+                            v.push(evaluate_value(arg, scopes, user_def_functions, user_functions, functions)?);
+                        }
+
+                        let locals = f.bind(s, v).map_err(at_call)?;
+                        let result = call_user_function(
+                            s,
+                            &f.body,
+                            locals,
+                            call_span,
+                            scopes,
+                            user_def_functions,
+                            user_functions,
+                            functions
+                        )?;
+                        return Ok(EvalResult::Value(result));
+                    }
+
+                    if let Some(Value::Function(_)) = scopes.get(s) {
+                        let mut v = Vec::with_capacity(args.len());
+                        for arg in args {
+                            v.push(evaluate_value(arg, scopes, user_def_functions, user_functions, functions)?);
+                        }
+
+                        // Looked up after the arguments, which may assign to it.
+                        let f = scopes
+                            .get(s)
+                            .and_then(|v| v.as_function())
+                            .cloned()
+                            .ok_or(at_call(Error::UnexpectedError))?;
+                        let locals = f.bind(s, v).map_err(at_call)?;
+                        let result = call_user_function(
+                            s,
+                            &f.func.body,
+                            locals,
+                            call_span,
+                            scopes,
+                            user_def_functions,
+                            user_functions,
+                            functions
+                        )?;
+                        return Ok(EvalResult::Value(result));
+                    }
+
+                    let Some(f) = functions.get(s) else {
+                        if let Some(v) = scopes.get(s) {
+                            return Err(
+                                at_call(
+                                    Error::EvalError(EvalError::NotCallable {
+                                        name: Some(s.to_string()),
+                                        found: v.value_type(),
+                                    })
+                                )
+                            );
+                        }
+                        let err = Error::EvalError(EvalError::NameNotFound {
                             kind: NameKind::Function,
                             name: s.to_string(),
-                        }),
-                    })?;
+                            searched: Vec::new(),
+                            suggestions: Vec::new(),
+                        });
+                        return Err(at_call(with_suggestions(err, scopes, user_def_functions, user_functions, functions)));
+                    };
 
                     let mut v = Vec::new();
 
                     for in_arg in args.iter() {
-                        let res = evaluate_expr(&in_arg, variables, user_def_functions, functions);
+                        let res = evaluate_expr(in_arg, scopes, user_def_functions, user_functions, functions);
 
                         if let Err(err) = res {
                             return Err(err);
@@ -546,34 +793,191 @@ pub fn evaluate_expr<'a>(
                         }
                     }
 
-                    let result = f(&v).map_err(|err| Spanned {
-                        span: Span {
-                            start: func.span.start,
-                            end: if let Some(e) = args.last() {
-                                e.span.end
-                            } else {
-                                func.span.end
-                            },
-                        },
-                        data: err,
+                    let v = f.check_args(s, &v).map_err(|err| {
+                        match err {
+                            Error::EvalError(EvalError::ArgTypeMismatch { index, .. }) =>
+                                Spanned {
+                                    span: args[index].span,
+                                    data: err,
+                                },
+                            _ => Spanned { span: call_span, data: err },
+                        }
+                    })?;
+
+                    let mut caller = NativeCaller {
+                        span: call_span,
+                        scopes,
+                        user_def_functions,
+                        user_functions,
+                        functions,
+                    };
+                    let result = f.call_unchecked(&v, &mut caller).map_err(|err| {
+                        match err {
+                            Error::EvalError(EvalError::DimensionMismatch { operand, .. }) =>
+                                Spanned {
+                                    span: args[operand].span,
+                                    data: err,
+                                },
+                            _ => Spanned { span: call_span, data: err },
+                        }
                     })?;
                     Ok(EvalResult::Value(result))
                 }
-                _ =>
-                    Err(Spanned {
-                        span: expr.span,
-                        data: Error::UnexpectedError,
-                    }),
+                _ => {
+                    let callee = evaluate_value(func, scopes, user_def_functions, user_functions, functions)?;
+
+                    let mut v = Vec::with_capacity(args.len());
+                    for arg in args {
+                        v.push(evaluate_value(arg, scopes, user_def_functions, user_functions, functions)?);
+                    }
+
+                    let f = callee.as_function().ok_or(Spanned {
+                        span: func.span,
+                        data: Error::EvalError(EvalError::NotCallable { name: None, found: callee.value_type() }),
+                    })?;
+                    let locals = f.bind(LAMBDA_NAME, v).map_err(at_call)?;
+                    let result = call_user_function(
+                        LAMBDA_NAME,
+                        &f.func.body,
+                        locals,
+                        call_span,
+                        scopes,
+                        user_def_functions,
+                        user_functions,
+                        functions
+                    )?;
+                    Ok(EvalResult::Value(result))
+                }
             }
+        }
+        Expr::Block { exprs } => {
+            let (last, init) = exprs
+                .split_last()
+                .ok_or(Spanned { span: expr.span, data: Error::UnexpectedError })?;
+            for e in init {
+                evaluate_expr(e, scopes, user_def_functions, user_functions, functions)?;
+            }
+            evaluate_expr(last, scopes, user_def_functions, user_functions, functions)
+        }
         Expr::Comma { exprs } => {
             for i in 0..exprs.len() {
                 if i == exprs.len() - 1 {
-                    return evaluate_expr(&exprs[i], variables, user_def_functions, functions);
-                } else {
-                    let _ = evaluate_expr(&exprs[i], variables, user_def_functions, functions);
+                    return evaluate_expr(&exprs[i], scopes, user_def_functions, user_functions, functions);
+                } else if
+                    let Err(err) = evaluate_expr(&exprs[i], scopes, user_def_functions, user_functions, functions) &&
+                    matches!(err.data, Error::EvalError(EvalError::LoopControl { .. }))
+                {
+                    // Errors are skipped, but `break` and `continue` reach their loop.
+                    return Err(err);
                 }
             }
             Err(Spanned { span: expr.span, data: Error::UnexpectedError })
         }
+        Expr::FunctionDef { name, params, body } => {
+            if functions.contains_key(name) {
+                return Err(Spanned {
+                    span: expr.span,
+                    data: Error::EvalError(EvalError::BuiltinRedefined { name: name.clone() }),
+                });
+            }
+            let f = Arc::new(UserFunction::new(params.clone(), body.clone()));
+            user_functions.insert(name.clone(), f.clone());
+            Ok(EvalResult::Value(Value::Function(Arc::new(Lambda::of_function(f)))))
+        }
+        Expr::Let { bindings, body } => {
+            scopes.push(ScopeKind::Let);
+            let result = evaluate_let(bindings, body, scopes, user_def_functions, user_functions, functions);
+            scopes.pop();
+            Ok(EvalResult::Value(result?))
+        }
+        Expr::If { cond, then_branch, else_branch } => {
+            let branch = if evaluate_condition(cond, scopes, user_def_functions, user_functions, functions)? {
+                Some(then_branch)
+            } else {
+                else_branch.as_ref()
+            };
+            match branch {
+                Some(e) => {
+                    let v = evaluate_value(e, scopes, user_def_functions, user_functions, functions)?;
+                    Ok(EvalResult::Value(v))
+                }
+                None => Ok(EvalResult::Value(Value::Boolean(false))),
+            }
+        }
+        Expr::While { cond, body } => {
+            let mut last = Value::Boolean(false);
+            while evaluate_condition(cond, scopes, user_def_functions, user_functions, functions)? {
+                if !loop_iteration(body, &mut last, scopes, user_def_functions, user_functions, functions)? {
+                    break;
+                }
+            }
+            Ok(EvalResult::Value(last))
+        }
+        Expr::For { var, start, end, body } => {
+            let s = evaluate_value(start, scopes, user_def_functions, user_functions, functions)?;
+            let e = evaluate_value(end, scopes, user_def_functions, user_functions, functions)?;
+            let (Value::Int(from), Value::Int(to)) = (&s, &e) else {
+                return Err(Spanned {
+                    span: Span::merge(&start.span, &end.span),
+                    data: Error::EvalError(EvalError::InvalidRange { start: s, end: e }),
+                });
+            };
+
+            scopes.push(ScopeKind::For);
+            let result = evaluate_for(var, *from..*to, body, scopes, user_def_functions, user_functions, functions);
+            scopes.pop();
+            Ok(EvalResult::Value(result?))
+        }
+        Expr::Break | Expr::Continue => {
+            let keyword = if expr.data == Expr::Break { Keyword::Break } else { Keyword::Continue };
+            Err(Spanned {
+                span: expr.span,
+                data: Error::EvalError(EvalError::LoopControl { keyword }),
+            })
+        }
+        // Only reachable through `parse_recovering`, whose errors say why.
+        Expr::Error => Err(Spanned { span: expr.span, data: Error::UnexpectedError }),
+        Expr::Lambda { params, body } => {
+            let f = Arc::new(UserFunction::new(params.clone(), body.clone()));
+            Ok(EvalResult::Value(Value::Function(Arc::new(Lambda::new(f, |name| scopes.get(name).cloned())))))
+        }
+        Expr::List { items } => {
+            let mut v = Vec::with_capacity(items.len());
+            for item in items {
+                v.push(evaluate_value(item, scopes, user_def_functions, user_functions, functions)?);
+            }
+            Ok(EvalResult::Value(Value::from_list(v)))
+        }
+        Expr::Index { target, index } => {
+            let t = evaluate_value(target, scopes, user_def_functions, user_functions, functions)?;
+            let i = evaluate_value(index, scopes, user_def_functions, user_functions, functions)?;
+
+            let v = t.index(&i).map_err(|err| {
+                match err {
+                    Error::EvalError(EvalError::NotIndexable { .. }) =>
+                        Spanned { span: target.span, data: err },
+                    _ => Spanned { span: index.span, data: err },
+                }
+            })?;
+            Ok(EvalResult::Value(v))
+        }
+        Expr::Slice { target, start, end } => {
+            let t = evaluate_value(target, scopes, user_def_functions, user_functions, functions)?;
+            let mut bounds = [None, None];
+            for (bound, e) in bounds.iter_mut().zip([start, end]) {
+                if let Some(e) = e {
+                    *bound = Some(evaluate_value(e, scopes, user_def_functions, user_functions, functions)?);
+                }
+            }
+
+            let v = t.slice(bounds[0].as_ref(), bounds[1].as_ref()).map_err(|err| {
+                match err {
+                    Error::EvalError(EvalError::NotIndexable { .. }) =>
+                        Spanned { span: target.span, data: err },
+                    _ => Spanned { span: expr.span, data: err },
+                }
+            })?;
+            Ok(EvalResult::Value(v))
+        }
     }
 }
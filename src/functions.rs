@@ -1,63 +1,407 @@
-use crate::{ error::{ Error, EvalError }, value::{ Value, ValueType, unify_ret_type, unify_to } };
+use std::collections::HashMap;
 
-pub fn to_bool(input: &Vec<Value>) -> Result<Value, Error> {
-    if input.len() != 1 {
-        Err(
-            Error::EvalError(EvalError::ArityMismatch {
-                func: "to_bool".to_string(),
-                expected: 1,
-                found: input.len(),
-            })
-        )
-    } else {
-        let value = input[0].promote(ValueType::Boolean).unwrap();
-        Ok(value)
-    }
+use crate::{
+    bigint::BigInt,
+    complex::Complex,
+    error::{ Error, EvalError },
+    matrix::{ Matrix, dimension_mismatch },
+    native_function::{ Caller, FnArity, NativeFunction, ParamType },
+    rational::Rational,
+    value::{ Value, ValueType, unify_ret_type },
+};
+
+pub fn builtins() -> HashMap<String, NativeFunction> {
+    HashMap::from([
+        (
+            "to_bool".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Boolean)],
+                "Converts a value to a boolean.",
+                to_bool
+            ),
+        ),
+        (
+            "to_int".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Any],
+                "Converts a value to an integer, truncating floats.",
+                to_int
+            ),
+        ),
+        (
+            "factorial".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Int)],
+                "factorial(n) returns n!, exactly.",
+                factorial
+            ),
+        ),
+        (
+            "to_float".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Float)],
+                "Converts a value to a float.",
+                to_float
+            ),
+        ),
+        (
+            "rationalize".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(2),
+                vec![ParamType::Of(ValueType::Float), ParamType::Of(ValueType::Int)],
+                "rationalize(x, max_den) returns the fraction closest to x with a denominator of at most max_den.",
+                rationalize
+            ),
+        ),
+        (
+            "any".to_string(),
+            NativeFunction::new(
+                FnArity::Variadic(0),
+                vec![ParamType::Of(ValueType::Boolean)],
+                "True if any argument is truthy.",
+                any
+            ),
+        ),
+        (
+            "all".to_string(),
+            NativeFunction::new(
+                FnArity::Variadic(0),
+                vec![ParamType::Of(ValueType::Boolean)],
+                "True if every argument is truthy.",
+                all
+            ),
+        ),
+        (
+            "max".to_string(),
+            NativeFunction::new(
+                FnArity::Variadic(1),
+                vec![ParamType::Any],
+                "Largest argument, after promoting all arguments to a common type.",
+                max
+            ),
+        ),
+        (
+            "min".to_string(),
+            NativeFunction::new(
+                FnArity::Variadic(1),
+                vec![ParamType::Any],
+                "Smallest argument, after promoting all arguments to a common type.",
+                min
+            ),
+        ),
+        (
+            "clamp".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(3),
+                vec![ParamType::Any],
+                "clamp(min, max, value) limits value to the range [min, max].",
+                clamp
+            ),
+        ),
+        (
+            "sqrt".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Complex)],
+                "Principal square root. Negative and complex arguments give a complex result.",
+                sqrt
+            ),
+        ),
+        (
+            "abs".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Any],
+                "Absolute value, or the modulus of a complex number.",
+                abs
+            ),
+        ),
+        (
+            "re".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Complex)],
+                "Real part of a number.",
+                re
+            ),
+        ),
+        (
+            "im".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Complex)],
+                "Imaginary part of a number.",
+                im
+            ),
+        ),
+        (
+            "arg".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Complex)],
+                "Angle of a number in the complex plane, in radians.",
+                arg
+            ),
+        ),
+        (
+            "conj".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Complex)],
+                "Complex conjugate.",
+                conj
+            ),
+        ),
+        (
+            "polar".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(2),
+                vec![ParamType::Of(ValueType::Float)],
+                "polar(r, theta) returns the complex number with modulus r and angle theta.",
+                polar
+            ),
+        ),
+        (
+            "rect".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(2),
+                vec![ParamType::Of(ValueType::Float)],
+                "rect(x, y) returns the complex number x + yi.",
+                rect
+            ),
+        ),
+        (
+            "to_polar".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Complex)],
+                "to_polar(z) returns [r, theta], the modulus and angle of z; polar(r, theta) converts back.",
+                to_polar
+            ),
+        ),
+        (
+            "to_rect".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Complex)],
+                "to_rect(z) returns [x, y], the real and imaginary parts of z; rect(x, y) converts back.",
+                to_rect
+            ),
+        ),
+        (
+            "len".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Any],
+                "Number of elements of a list or characters of a string.",
+                len
+            ),
+        ),
+        (
+            "map".to_string(),
+            NativeFunction::with_caller(
+                FnArity::Fixed(2),
+                vec![ParamType::Of(ValueType::Function), ParamType::Of(ValueType::List)],
+                "map(f, list) returns the list of f(x) for each element x of list.",
+                map
+            ),
+        ),
+        (
+            "transpose".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Matrix)],
+                "Swaps the rows and columns of a matrix.",
+                transpose
+            ),
+        ),
+        (
+            "det".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Matrix)],
+                "Determinant of a square matrix.",
+                det
+            ),
+        ),
+        (
+            "inv".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Matrix)],
+                "Inverse of a square matrix.",
+                inv
+            ),
+        ),
+        (
+            "rank".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Matrix)],
+                "Number of linearly independent rows of a matrix.",
+                rank
+            ),
+        ),
+        (
+            "solve".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(2),
+                vec![ParamType::Of(ValueType::Matrix), ParamType::Any],
+                "solve(A, b) returns x such that A * x = b, for a square matrix A and a list or matrix b.",
+                solve
+            ),
+        ),
+        (
+            "identity".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Int)],
+                "identity(n) returns the n by n identity matrix.",
+                identity
+            ),
+        ),
+        (
+            "eig".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Matrix)],
+                "Eigenvalues of a symmetric matrix, in ascending order.",
+                eig
+            ),
+        ),
+        (
+            "substr".to_string(),
+            NativeFunction::new(
+                FnArity::Range(2, 3),
+                vec![
+                    ParamType::Of(ValueType::Str),
+                    ParamType::Of(ValueType::Int),
+                    ParamType::Of(ValueType::Int)
+                ],
+                "substr(s, start, count) returns count characters of s from start (default: to the end).",
+                substr
+            ),
+        ),
+        (
+            "upper".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Str)],
+                "Converts a string to upper case.",
+                upper
+            ),
+        ),
+        (
+            "lower".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Str)],
+                "Converts a string to lower case.",
+                lower
+            ),
+        ),
+        (
+            "str".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Any],
+                "Formats a value as a string.",
+                str
+            ),
+        ),
+        (
+            "parse_num".to_string(),
+            NativeFunction::new(
+                FnArity::Fixed(1),
+                vec![ParamType::Of(ValueType::Str)],
+                "Parses a string as an integer, or a float if it is not an integer.",
+                parse_num
+            ),
+        ),
+    ])
 }
 
-pub fn to_int(input: &Vec<Value>) -> Result<Value, Error> {
-    if input.len() != 1 {
-        Err(
-            Error::EvalError(EvalError::ArityMismatch {
+pub fn to_bool(input: &[Value]) -> Result<Value, Error> {
+    Ok(input[0].clone())
+}
+
+pub fn to_int(input: &[Value]) -> Result<Value, Error> {
+    // Floats beyond the `i64` range become `BigInt` instead of saturating.
+    input[0]
+        .promote(ValueType::BigInt)
+        .map(|v| Value::from_bigint(v.to_bigint().unwrap()))
+        .ok_or(
+            Error::EvalError(EvalError::InvalidArgument {
                 func: "to_int".to_string(),
-                expected: 1,
-                found: input.len(),
+                arg: input[0].clone(),
             })
         )
-    } else {
-        let value = input[0].promote(ValueType::Int).unwrap();
-        Ok(value)
-    }
 }
 
-pub fn to_float(input: &Vec<Value>) -> Result<Value, Error> {
-    if input.len() != 1 {
-        Err(
-            Error::EvalError(EvalError::ArityMismatch {
-                func: "to_float".to_string(),
-                expected: 1,
-                found: input.len(),
+pub fn factorial(input: &[Value]) -> Result<Value, Error> {
+    let n = input[0].as_int().unwrap();
+
+    // 100000! already has close to half a million digits.
+    if !(0..=100_000).contains(&n) {
+        return Err(
+            Error::EvalError(EvalError::InvalidArgument {
+                func: "factorial".to_string(),
+                arg: input[0].clone(),
             })
-        )
-    } else {
-        let value = input[0].promote(ValueType::Float).unwrap();
-        Ok(value)
+        );
     }
+
+    let v = (2..=n).fold(BigInt::from(1), |acc, k| &acc * &BigInt::from(k));
+    Ok(Value::from_bigint(v))
 }
 
-pub fn any(input: &Vec<Value>) -> Result<Value, Error> {
-    let promoted = unify_to(input, ValueType::Boolean)?;
+pub fn to_float(input: &[Value]) -> Result<Value, Error> {
+    Ok(input[0].clone())
+}
 
-    Ok(Value::Boolean(promoted.iter().any(|v| matches!(v, Value::Boolean(true)))))
+pub fn rationalize(input: &[Value]) -> Result<Value, Error> {
+    let max_den = input[1].as_int().unwrap();
+
+    let invalid = |arg: &Value| {
+        Error::EvalError(EvalError::InvalidArgument {
+            func: "rationalize".to_string(),
+            arg: arg.clone(),
+        })
+    };
+
+    if max_den < 1 {
+        return Err(invalid(&input[1]));
+    }
+    let x = Rational::from_f64(input[0].as_float().unwrap()).ok_or_else(|| invalid(&input[0]))?;
+
+    Ok(Value::from_rational(x.limit_denominator(&BigInt::from(max_den))))
 }
 
-pub fn all(input: &Vec<Value>) -> Result<Value, Error> {
-    let promoted = unify_to(input, ValueType::Boolean)?;
+pub fn any(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Boolean(input.iter().any(|v| matches!(v, Value::Boolean(true)))))
+}
 
-    Ok(Value::Boolean(promoted.iter().all(|v| matches!(v, Value::Boolean(true)))))
+pub fn all(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Boolean(input.iter().all(|v| matches!(v, Value::Boolean(true)))))
 }
 
-pub fn max(input: &Vec<Value>) -> Result<Value, Error> {
+/// Error for an ordering function called with arguments of a type that has
+/// no order, reporting the first of them.
+fn unordered(func: &str, input: &[Value], ty: ValueType) -> Error {
+    let arg = input
+        .iter()
+        .find(|v| v.value_type() == ty)
+        .cloned()
+        .unwrap_or(Value::Boolean(false));
+
+    Error::EvalError(EvalError::InvalidArgument { func: func.to_string(), arg })
+}
+
+pub fn max(input: &[Value]) -> Result<Value, Error> {
     let (promoted, promoted_type) = unify_ret_type(input)?;
 
     match promoted_type {
@@ -77,18 +421,48 @@ pub fn max(input: &Vec<Value>) -> Result<Value, Error> {
                 .ok_or(Error::UnexpectedError)?;
             Ok(Value::Int(v))
         }
+        ValueType::BigInt => {
+            let v = promoted
+                .iter()
+                .map(|v| v.to_bigint().unwrap())
+                .max()
+                .ok_or(Error::UnexpectedError)?;
+            Ok(Value::from_bigint(v))
+        }
+        ValueType::Rational => {
+            let v = promoted
+                .iter()
+                .map(|v| v.to_rational().unwrap())
+                .max()
+                .ok_or(Error::UnexpectedError)?;
+            Ok(Value::from_rational(v))
+        }
+        | ValueType::Complex
+        | ValueType::List
+        | ValueType::Matrix
+        | ValueType::Function => Err(unordered("max", input, promoted_type)),
         ValueType::Float => {
             let v = promoted
                 .iter()
                 .map(|v| v.as_float().unwrap())
-                .max_by(|a, b| a.partial_cmp(b).unwrap())
+                // NaN, which a loaded session may hold, sorts above every
+                // other float instead of panicking.
+                .max_by(|a, b| a.total_cmp(b))
                 .ok_or(Error::UnexpectedError)?;
             Ok(Value::Float(v))
         }
+        ValueType::Str => {
+            let v = promoted
+                .iter()
+                .map(|v| v.as_str().unwrap())
+                .max()
+                .ok_or(Error::UnexpectedError)?;
+            Ok(Value::Str(v.to_string()))
+        }
     }
 }
 
-pub fn min(input: &Vec<Value>) -> Result<Value, Error> {
+pub fn min(input: &[Value]) -> Result<Value, Error> {
     let (promoted, promoted_type) = unify_ret_type(input)?;
 
     match promoted_type {
@@ -108,52 +482,424 @@ pub fn min(input: &Vec<Value>) -> Result<Value, Error> {
                 .ok_or(Error::UnexpectedError)?;
             Ok(Value::Int(v))
         }
+        ValueType::BigInt => {
+            let v = promoted
+                .iter()
+                .map(|v| v.to_bigint().unwrap())
+                .min()
+                .ok_or(Error::UnexpectedError)?;
+            Ok(Value::from_bigint(v))
+        }
+        ValueType::Rational => {
+            let v = promoted
+                .iter()
+                .map(|v| v.to_rational().unwrap())
+                .min()
+                .ok_or(Error::UnexpectedError)?;
+            Ok(Value::from_rational(v))
+        }
+        | ValueType::Complex
+        | ValueType::List
+        | ValueType::Matrix
+        | ValueType::Function => Err(unordered("min", input, promoted_type)),
         ValueType::Float => {
             let v = promoted
                 .iter()
                 .map(|v| v.as_float().unwrap())
-                .min_by(|a, b| a.partial_cmp(b).unwrap())
+                // NaN sorts above every other float, as in `max`.
+                .min_by(|a, b| a.total_cmp(b))
                 .ok_or(Error::UnexpectedError)?;
             Ok(Value::Float(v))
         }
+        ValueType::Str => {
+            let v = promoted
+                .iter()
+                .map(|v| v.as_str().unwrap())
+                .min()
+                .ok_or(Error::UnexpectedError)?;
+            Ok(Value::Str(v.to_string()))
+        }
     }
 }
 
-pub fn clamp(input: &Vec<Value>) -> Result<Value, Error> {
-    if input.len() != 3 {
-        Err(
-            Error::EvalError(EvalError::ArityMismatch {
-                func: "clamp".to_string(),
-                expected: 3,
-                found: input.len(),
-            })
-        )
-    } else {
-        let (promoted, promoted_type) = unify_ret_type(input)?;
-        let (clamp_min, clamp_max, value) = (promoted[0], promoted[1], promoted[2]);
+pub fn clamp(input: &[Value]) -> Result<Value, Error> {
+    let (promoted, promoted_type) = unify_ret_type(input)?;
+    let (clamp_min, clamp_max, value) = (&promoted[0], &promoted[1], &promoted[2]);
 
-        match promoted_type {
-            ValueType::Boolean => {
-                let cmini = clamp_min.promote(ValueType::Int).unwrap().as_int().unwrap();
-                let cmaxi = clamp_max.promote(ValueType::Int).unwrap().as_int().unwrap();
-                let cvali = value.promote(ValueType::Int).unwrap().as_int().unwrap();
+    // `Ord::clamp` and `f64::clamp` panic on an empty range, and the
+    // latter on NaN bounds too.
+    let empty = || {
+        Error::EvalError(EvalError::InvalidArgument {
+            func: "clamp".to_string(),
+            arg: input[1].clone(),
+        })
+    };
 
-                Ok(Value::Int(cvali.clamp(cmini, cmaxi)))
+    match promoted_type {
+        ValueType::Boolean => {
+            let cmini = clamp_min.promote(ValueType::Int).unwrap().as_int().unwrap();
+            let cmaxi = clamp_max.promote(ValueType::Int).unwrap().as_int().unwrap();
+            let cvali = value.promote(ValueType::Int).unwrap().as_int().unwrap();
+
+            if cmini > cmaxi {
+                return Err(empty());
             }
-            ValueType::Int => {
-                let cmini = clamp_min.as_int().unwrap();
-                let cmaxi = clamp_max.as_int().unwrap();
-                let cvali = value.as_int().unwrap();
+            Ok(Value::Int(cvali.clamp(cmini, cmaxi)))
+        }
+        ValueType::Int => {
+            let cmini = clamp_min.as_int().unwrap();
+            let cmaxi = clamp_max.as_int().unwrap();
+            let cvali = value.as_int().unwrap();
 
-                Ok(Value::Int(cvali.clamp(cmini, cmaxi)))
+            if cmini > cmaxi {
+                return Err(empty());
             }
-            ValueType::Float => {
-                let cminf = clamp_min.as_float().unwrap();
-                let cmaxf = clamp_max.as_float().unwrap();
-                let cvalf = value.as_float().unwrap();
+            Ok(Value::Int(cvali.clamp(cmini, cmaxi)))
+        }
+        ValueType::BigInt => {
+            let cminb = clamp_min.to_bigint().unwrap();
+            let cmaxb = clamp_max.to_bigint().unwrap();
+            let cvalb = value.to_bigint().unwrap();
+
+            if cminb > cmaxb {
+                return Err(empty());
+            }
+            Ok(Value::from_bigint(cvalb.clamp(cminb, cmaxb)))
+        }
+        ValueType::Rational => {
+            let cminr = clamp_min.to_rational().unwrap();
+            let cmaxr = clamp_max.to_rational().unwrap();
+            let cvalr = value.to_rational().unwrap();
+
+            if cminr > cmaxr {
+                return Err(empty());
+            }
+            Ok(Value::from_rational(cvalr.clamp(cminr, cmaxr)))
+        }
+        | ValueType::Complex
+        | ValueType::List
+        | ValueType::Matrix
+        | ValueType::Function => Err(unordered("clamp", input, promoted_type)),
+        ValueType::Float => {
+            let cminf = clamp_min.as_float().unwrap();
+            let cmaxf = clamp_max.as_float().unwrap();
+            let cvalf = value.as_float().unwrap();
+
+            if cminf.is_nan() || cmaxf.is_nan() || cminf > cmaxf {
+                return Err(empty());
+            }
+            Ok(Value::Float(cvalf.clamp(cminf, cmaxf)))
+        }
+        ValueType::Str => {
+            let cmins = clamp_min.as_str().unwrap();
+            let cmaxs = clamp_max.as_str().unwrap();
+            let cvals = value.as_str().unwrap();
 
-                Ok(Value::Float(cvalf.clamp(cminf, cmaxf)))
+            if cmins > cmaxs {
+                return Err(empty());
             }
+            Ok(Value::Str(cvals.clamp(cmins, cmaxs).to_string()))
         }
     }
 }
+
+/// Argument `index` of `func` as a complex number. Arguments are promoted
+/// to the declared parameter type before the call, so this only fails if
+/// the function is called with unchecked arguments.
+fn complex_arg(func: &str, input: &[Value], index: usize) -> Result<Complex, Error> {
+    input[index].as_complex().ok_or_else(|| arg_type_mismatch(func, input, index, ValueType::Complex))
+}
+
+/// Argument `index` of `func` as a float. See `complex_arg`.
+fn float_arg(func: &str, input: &[Value], index: usize) -> Result<f64, Error> {
+    input[index].as_float().ok_or_else(|| arg_type_mismatch(func, input, index, ValueType::Float))
+}
+
+fn arg_type_mismatch(func: &str, input: &[Value], index: usize, expected: ValueType) -> Error {
+    Error::EvalError(EvalError::ArgTypeMismatch {
+        func: func.to_string(),
+        index,
+        expected,
+        found: input[index].value_type(),
+    })
+}
+
+pub fn sqrt(input: &[Value]) -> Result<Value, Error> {
+    let z = complex_arg("sqrt", input, 0)?;
+
+    if z.im == 0.0 && z.re >= 0.0 {
+        Ok(Value::Float(z.re.sqrt()))
+    } else {
+        Ok(Value::Complex(z.sqrt()))
+    }
+}
+
+pub fn abs(input: &[Value]) -> Result<Value, Error> {
+    // Exact and real arguments keep their type.
+    match &input[0] {
+        Value::Boolean(b) => Ok(Value::Int(*b as i64)),
+        Value::Int(a) => Ok(Value::from_bigint(BigInt::from(*a).abs())),
+        Value::BigInt(a) => Ok(Value::from_bigint(a.abs())),
+        Value::Rational(a) => Ok(Value::Rational(a.abs())),
+        Value::Float(a) => Ok(Value::Float(a.abs())),
+        Value::Complex(z) => Ok(Value::Float(z.abs())),
+        Value::List(items) => {
+            let items = items
+                .iter()
+                .map(|v| abs(std::slice::from_ref(v)))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::List(items))
+        }
+        Value::Matrix(m) => {
+            let rows = m
+                .to_rows()
+                .iter()
+                .map(|row| abs(std::slice::from_ref(row)))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::from_list(rows))
+        }
+        Value::Str(_) | Value::Function(_) =>
+            Err(
+                Error::EvalError(EvalError::InvalidArgument {
+                    func: "abs".to_string(),
+                    arg: input[0].clone(),
+                })
+            ),
+    }
+}
+
+pub fn re(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Float(complex_arg("re", input, 0)?.re))
+}
+
+pub fn im(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Float(complex_arg("im", input, 0)?.im))
+}
+
+pub fn arg(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Float(complex_arg("arg", input, 0)?.arg()))
+}
+
+pub fn conj(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Complex(complex_arg("conj", input, 0)?.conj()))
+}
+
+pub fn polar(input: &[Value]) -> Result<Value, Error> {
+    let (r, theta) = (float_arg("polar", input, 0)?, float_arg("polar", input, 1)?);
+    Ok(Value::Complex(Complex::from_polar(r, theta)))
+}
+
+pub fn rect(input: &[Value]) -> Result<Value, Error> {
+    let (x, y) = (float_arg("rect", input, 0)?, float_arg("rect", input, 1)?);
+    Ok(Value::Complex(Complex::new(x, y)))
+}
+
+pub fn to_polar(input: &[Value]) -> Result<Value, Error> {
+    let z = complex_arg("to_polar", input, 0)?;
+    Ok(Value::List(vec![Value::Float(z.abs()), Value::Float(z.arg())]))
+}
+
+pub fn to_rect(input: &[Value]) -> Result<Value, Error> {
+    let z = complex_arg("to_rect", input, 0)?;
+    Ok(Value::List(vec![Value::Float(z.re), Value::Float(z.im)]))
+}
+
+pub fn len(input: &[Value]) -> Result<Value, Error> {
+    let n = input[0].length().ok_or(
+        Error::EvalError(EvalError::InvalidArgument {
+            func: "len".to_string(),
+            arg: input[0].clone(),
+        })
+    )?;
+    Ok(Value::Int(n as i64))
+}
+
+pub fn map(input: &[Value], caller: &mut dyn Caller) -> Result<Value, Error> {
+    let f = input[0].as_function().unwrap();
+    let items = input[1]
+        .as_list()
+        .unwrap()
+        .iter()
+        .map(|item| caller.call(f, vec![item.clone()]))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::from_list(items))
+}
+
+/// The matrix argument of `func`, which must be square.
+fn square<'a>(func: &str, input: &'a [Value]) -> Result<&'a Matrix, Error> {
+    let m = input[0].as_matrix().unwrap();
+    if !m.is_square() {
+        return Err(dimension_mismatch(func, &[m.shape()], 0));
+    }
+    Ok(m)
+}
+
+/// Error for a singular matrix passed to `func`.
+fn singular(func: &str, input: &[Value]) -> Error {
+    Error::EvalError(EvalError::InvalidArgument {
+        func: func.to_string(),
+        arg: input[0].clone(),
+    })
+}
+
+pub fn transpose(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Matrix(input[0].as_matrix().unwrap().transpose()))
+}
+
+pub fn det(input: &[Value]) -> Result<Value, Error> {
+    Ok(square("det", input)?.det()?.0)
+}
+
+pub fn inv(input: &[Value]) -> Result<Value, Error> {
+    let (m, _) = square("inv", input)?
+        .inverse()?
+        .ok_or_else(|| singular("inv", input))?;
+    Ok(Value::Matrix(m))
+}
+
+pub fn rank(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Int(input[0].as_matrix().unwrap().rank()? as i64))
+}
+
+pub fn solve(input: &[Value]) -> Result<Value, Error> {
+    let a = square("solve", input)?;
+    let invalid = || {
+        Error::EvalError(EvalError::InvalidArgument {
+            func: "solve".to_string(),
+            arg: input[1].clone(),
+        })
+    };
+
+    // A list is solved as a single column and returned as a list.
+    let (b, column) = match &input[1] {
+        Value::Matrix(b) => (b.clone(), false),
+        Value::List(items) => {
+            let rows = items
+                .iter()
+                .map(|v| Value::List(vec![v.clone()]))
+                .collect::<Vec<_>>();
+            (Matrix::from_rows(&rows).ok_or_else(invalid)?, true)
+        }
+        _ => {
+            return Err(invalid());
+        }
+    };
+
+    if b.rows() != a.rows() {
+        let shape = if column { (b.rows(), 1) } else { b.shape() };
+        return Err(dimension_mismatch("solve", &[a.shape(), shape], 1));
+    }
+
+    let (x, _) = a.solve(&b)?.ok_or_else(|| singular("solve", input))?;
+    if column {
+        Ok(Value::List(x.elements().to_vec()))
+    } else {
+        Ok(Value::Matrix(x))
+    }
+}
+
+pub fn identity(input: &[Value]) -> Result<Value, Error> {
+    let n = input[0].as_int().unwrap();
+
+    // Large enough for any practical use, small enough to allocate.
+    if !(1..=4096).contains(&n) {
+        return Err(
+            Error::EvalError(EvalError::InvalidArgument {
+                func: "identity".to_string(),
+                arg: input[0].clone(),
+            })
+        );
+    }
+    Ok(Value::Matrix(Matrix::identity(n as usize)))
+}
+
+pub fn eig(input: &[Value]) -> Result<Value, Error> {
+    let values = square("eig", input)?
+        .symmetric_eigenvalues()
+        .ok_or(
+            Error::EvalError(EvalError::InvalidArgument {
+                func: "eig".to_string(),
+                arg: input[0].clone(),
+            })
+        )?;
+    Ok(Value::List(values.into_iter().map(Value::Float).collect()))
+}
+
+pub fn substr(input: &[Value]) -> Result<Value, Error> {
+    let s = input[0].as_str().unwrap();
+    let start = input[1].as_int().unwrap();
+    let count = input.get(2).map(|v| v.as_int().unwrap());
+
+    if start < 0 || count.is_some_and(|c| c < 0) {
+        let arg = if start < 0 { input[1].clone() } else { input[2].clone() };
+        return Err(
+            Error::EvalError(EvalError::InvalidArgument {
+                func: "substr".to_string(),
+                arg,
+            })
+        );
+    }
+
+    let chars = s.chars().skip(start as usize);
+    let v = match count {
+        Some(c) => chars.take(c as usize).collect(),
+        None => chars.collect(),
+    };
+    Ok(Value::Str(v))
+}
+
+pub fn upper(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Str(input[0].as_str().unwrap().to_uppercase()))
+}
+
+pub fn lower(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Str(input[0].as_str().unwrap().to_lowercase()))
+}
+
+pub fn str(input: &[Value]) -> Result<Value, Error> {
+    Ok(Value::Str(input[0].to_text()))
+}
+
+pub fn parse_num(input: &[Value]) -> Result<Value, Error> {
+    let s = input[0].as_str().unwrap().trim().replace('_', "");
+
+    if let Ok(i) = s.parse::<i64>() {
+        Ok(Value::Int(i))
+    } else if let Ok(i) = s.parse::<BigInt>() {
+        Ok(Value::BigInt(i))
+    } else if let Ok(f) = s.parse::<f64>() {
+        Ok(Value::Float(f))
+    } else {
+        Err(
+            Error::EvalError(EvalError::InvalidArgument {
+                func: "parse_num".to_string(),
+                arg: input[0].clone(),
+            })
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchecked_arguments_are_type_mismatches() {
+        let s = [Value::Str("a".to_string())];
+        let mismatch = |func: &str, expected| Err(
+            Error::EvalError(EvalError::ArgTypeMismatch {
+                func: func.to_string(),
+                index: 0,
+                expected,
+                found: ValueType::Str,
+            })
+        );
+        assert_eq!(to_polar(&s), mismatch("to_polar", ValueType::Complex));
+        assert_eq!(to_rect(&s), mismatch("to_rect", ValueType::Complex));
+        assert_eq!(polar(&[s[0].clone(), Value::Float(0.0)]), mismatch("polar", ValueType::Float));
+        assert_eq!(
+            to_polar(&[Value::Complex(Complex::new(0.0, 2.0))]),
+            Ok(Value::List(vec![Value::Float(2.0), Value::Float(std::f64::consts::FRAC_PI_2)]))
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::{ collections::HashMap, sync::Arc };
+
+use crate::{
+    compiler::{ Program, compile },
+    debug::print_debug_program,
+    error::Error,
+    evaluater::{ EvalResult, evaluate_expr },
+    functions::builtins,
+    native_function::NativeFunction,
+    parser::{ Expr, parse_recovering, parse_string },
+    scope::{ Globals, Scopes },
+    span::Spanned,
+    user_function::UserFunction,
+    value::Value,
+    vm,
+};
+
+/// Owns the state of one evaluation session: variable scopes, native
+/// functions, user functions and `{macro}` definitions. It is `Send`, so a
+/// service can move each session to whichever thread handles it.
+pub struct Interpreter {
+    scopes: Scopes,
+    functions: HashMap<String, NativeFunction>,
+    user_def_functions: HashMap<String, Box<Spanned<Expr>>>,
+    /// Source text of each macro, as given to `define_macro`.
+    macro_sources: HashMap<String, String>,
+    user_functions: HashMap<String, Arc<UserFunction>>,
+    compiled_macros: HashMap<String, Program>,
+    debug: bool,
+}
+
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Interpreter>();
+};
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// Creates an interpreter with the builtin functions registered.
+    pub fn new() -> Self {
+        Interpreter {
+            scopes: Scopes::new(),
+            functions: builtins(),
+            user_def_functions: HashMap::new(),
+            macro_sources: HashMap::new(),
+            user_functions: HashMap::new(),
+            compiled_macros: HashMap::new(),
+            debug: false,
+        }
+    }
+
+    /// When enabled, tokens and the parsed tree are dumped to stdout.
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+
+    /// Parses and evaluates `src`, returning the value of the expression.
+    pub fn eval(&mut self, src: &str) -> Result<Value, Spanned<Error>> {
+        let expr = self.parse(src)?;
+        self.eval_expr(&expr)
+    }
+
+    /// Parses `src` without evaluating it.
+    pub fn parse(&self, src: &str) -> Result<Box<Spanned<Expr>>, Spanned<Error>> {
+        parse_string(src, self.debug)
+    }
+
+    /// Parses `src` past syntax errors, returning all of them along with the
+    /// partial tree. See `parse_recovering`.
+    pub fn parse_recovering(&self, src: &str) -> (Box<Spanned<Expr>>, Vec<Spanned<Error>>) {
+        parse_recovering(src, self.debug)
+    }
+
+    /// Evaluates an already parsed expression.
+    pub fn eval_expr(&mut self, expr: &Spanned<Expr>) -> Result<Value, Spanned<Error>> {
+        let result = evaluate_expr(
+            expr,
+            &mut self.scopes,
+            &mut self.user_def_functions,
+            &mut self.user_functions,
+            &self.functions
+        )?;
+
+        match result {
+            EvalResult::Value(v) => Ok(v),
+            EvalResult::Ref(r) => Ok(r.clone()),
+        }
+    }
+
+    /// Parses and compiles `src` to bytecode that can be run repeatedly with
+    /// `run`. Its global variables are given slots among the variables of
+    /// this interpreter.
+    pub fn compile(&mut self, src: &str) -> Result<Program, Spanned<Error>> {
+        let expr = parse_string(src, self.debug)?;
+        let program = compile(&expr, self.scopes.globals_mut());
+        if self.debug {
+            print_debug_program(&program);
+        }
+        Ok(program)
+    }
+
+    /// Runs a program produced by `compile` on the bytecode VM. Results and
+    /// errors are identical to `eval` on the same source.
+    pub fn run(&mut self, program: &Program) -> Result<Value, Spanned<Error>> {
+        vm::run(
+            program,
+            &mut self.scopes,
+            &self.compiled_macros,
+            &mut self.user_functions,
+            &self.functions
+        )
+    }
+
+    pub fn set_var(&mut self, name: &str, value: Value) {
+        self.scopes.globals_mut().insert(name, value);
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&Value> {
+        self.scopes.globals().get(name)
+    }
+
+    pub fn remove_var(&mut self, name: &str) -> Option<Value> {
+        self.scopes.globals_mut().remove(name)
+    }
+
+    pub fn clear_vars(&mut self) {
+        self.scopes.globals_mut().clear();
+    }
+
+    /// Global variables.
+    pub fn vars(&self) -> &Globals {
+        self.scopes.globals()
+    }
+
+    /// Parses `src` and stores it as the macro `{name}`, replacing any
+    /// previous definition.
+    pub fn define_macro(&mut self, name: &str, src: &str) -> Result<(), Spanned<Error>> {
+        let expr = parse_string(src, self.debug)?;
+        self.compiled_macros.insert(name.to_string(), compile(&expr, self.scopes.globals_mut()));
+        self.user_def_functions.insert(name.to_string(), expr);
+        self.macro_sources.insert(name.to_string(), src.to_string());
+        Ok(())
+    }
+
+    pub fn macros(&self) -> &HashMap<String, Box<Spanned<Expr>>> {
+        &self.user_def_functions
+    }
+
+    /// Source text the macro `{name}` was defined from.
+    pub fn macro_source(&self, name: &str) -> Option<&str> {
+        self.macro_sources.get(name).map(String::as_str)
+    }
+
+    /// Functions defined with `name(params) = body`.
+    pub fn user_functions(&self) -> &HashMap<String, Arc<UserFunction>> {
+        &self.user_functions
+    }
+
+    /// Registers a native function, replacing any builtin of the same name.
+    pub fn register_function(&mut self, name: &str, func: NativeFunction) {
+        self.functions.insert(name.to_string(), func);
+    }
+
+    pub fn functions(&self) -> &HashMap<String, NativeFunction> {
+        &self.functions
+    }
+}
@@ -1,8 +1,10 @@
 use crate::{
+    bigint::BigInt,
+    complex::Complex,
     error::{ Error, LexingError },
     operator::{ BinaryOp, GroupingOp, Operator, TernaryOp, UnaryOp },
     span::{ Span, Spanned },
-    token::Token,
+    token::{ Keyword, Token },
     value::Value,
 };
 
@@ -20,8 +22,8 @@ impl<'a> Cursor<'a> {
         self.i >= self.src.len()
     }
 
-    fn peek(&self) -> Option<u8> {
-        self.src.as_bytes().get(self.i).copied()
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
     }
 
     fn rest(&self) -> &'a str {
@@ -33,10 +35,10 @@ impl<'a> Cursor<'a> {
         self.is_eof()
     }
 
-    fn skip_while(&mut self, pred: impl Fn(u8) -> bool) {
+    fn skip_while(&mut self, pred: impl Fn(char) -> bool) {
         while let Some(c) = self.peek() {
             if pred(c) {
-                self.advance(1);
+                self.advance(c.len_utf8());
             } else {
                 break;
             }
@@ -82,6 +84,20 @@ fn lex_operator(cursor: &mut Cursor) -> Option<Spanned<Token>> {
             data: Token::Operator(Operator::Binary(BinaryOp::Exponentiation)),
         });
     }
+    if s.starts_with(".*") {
+        cursor.advance(2);
+        return Some(Spanned {
+            span: Span::from(cursor.i - 2, 2),
+            data: Token::Operator(Operator::Binary(BinaryOp::ElementMul)),
+        });
+    }
+    if s.starts_with("..") {
+        cursor.advance(2);
+        return Some(Spanned {
+            span: Span::from(cursor.i - 2, 2),
+            data: Token::Range,
+        });
+    }
     if s.starts_with("==") {
         cursor.advance(2);
         return Some(Spanned {
@@ -89,6 +105,13 @@ fn lex_operator(cursor: &mut Cursor) -> Option<Spanned<Token>> {
             data: Token::Operator(Operator::Binary(BinaryOp::Equal)),
         });
     }
+    if s.starts_with("=>") {
+        cursor.advance(2);
+        return Some(Spanned {
+            span: Span::from(cursor.i - 2, 2),
+            data: Token::Arrow,
+        });
+    }
     if s.starts_with("!=") {
         cursor.advance(2);
         return Some(Spanned {
@@ -168,126 +191,161 @@ fn lex_operator(cursor: &mut Cursor) -> Option<Spanned<Token>> {
     }
 
     match cursor.peek()? {
-        b'!' => {
+        '!' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Unary(UnaryOp::Not)),
             })
         }
-        b'~' => {
+        '~' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Unary(UnaryOp::BitwiseNot)),
             })
         }
-        b'+' => {
+        '+' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::Addition)),
             })
         }
-        b'-' => {
+        '-' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::Subtraction)),
             })
         }
-        b'%' => {
+        '%' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::Modulo)),
             })
         }
-        b'=' => {
+        '=' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::Assign)),
             })
         }
-        b'*' => {
+        '*' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::Multiplication)),
             })
         }
-        b'/' => {
+        '/' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::Division)),
             })
         }
-        b'<' => {
+        '<' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::Less)),
             })
         }
-        b'>' => {
+        '>' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::Greater)),
             })
         }
-        b'&' => {
+        '&' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::BitwiseAnd)),
             })
         }
-        b'|' => {
+        '|' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::BitwiseOr)),
             })
         }
-        b'^' => {
+        '^' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Binary(BinaryOp::BitwiseXor)),
             })
         }
-        b'(' => {
+        '(' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Grouping(GroupingOp::LeftParen)),
             })
         }
-        b')' => {
+        ')' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Grouping(GroupingOp::RightParen)),
             })
         }
-        b',' => {
+        '[' => {
+            cursor.advance(1);
+            Some(Spanned {
+                span: Span::single(cursor.i - 1),
+                data: Token::Operator(Operator::Grouping(GroupingOp::LeftBracket)),
+            })
+        }
+        ']' => {
+            cursor.advance(1);
+            Some(Spanned {
+                span: Span::single(cursor.i - 1),
+                data: Token::Operator(Operator::Grouping(GroupingOp::RightBracket)),
+            })
+        }
+        '{' => {
+            cursor.advance(1);
+            Some(Spanned {
+                span: Span::single(cursor.i - 1),
+                data: Token::Operator(Operator::Grouping(GroupingOp::LeftBrace)),
+            })
+        }
+        '}' => {
+            cursor.advance(1);
+            Some(Spanned {
+                span: Span::single(cursor.i - 1),
+                data: Token::Operator(Operator::Grouping(GroupingOp::RightBrace)),
+            })
+        }
+        ',' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::Grouping(GroupingOp::Comma)),
             })
         }
-        b'?' => {
+        ';' => {
+            cursor.advance(1);
+            Some(Spanned {
+                span: Span::single(cursor.i - 1),
+                data: Token::Operator(Operator::Grouping(GroupingOp::Semicolon)),
+            })
+        }
+        '?' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
                 data: Token::Operator(Operator::TernaryOp(TernaryOp::TernaryCond)),
             })
         }
-        b':' => {
+        ':' => {
             cursor.advance(1);
             Some(Spanned {
                 span: Span::single(cursor.i - 1),
@@ -298,7 +356,7 @@ fn lex_operator(cursor: &mut Cursor) -> Option<Spanned<Token>> {
     }
 }
 
-fn lex_value(cursor: &mut Cursor) -> Option<Spanned<Token>> {
+fn lex_value(cursor: &mut Cursor, allow_sign: bool) -> Option<Spanned<Token>> {
     let sr = cursor.rest();
 
     if sr.starts_with("true") {
@@ -325,12 +383,12 @@ fn lex_value(cursor: &mut Cursor) -> Option<Spanned<Token>> {
     let mut seen_fract = false;
     let mut seen_fpoint = false;
 
-    if s.get(0) == Some(&b'-') {
+    if allow_sign && s.first() == Some(&b'-') {
         num_str.push('-');
         i += 1;
     }
 
-    while s.get(i).is_some_and(|c| (c.is_ascii_digit() || *c == b'_')) {
+    while s.get(i).is_some_and(|c| c.is_ascii_digit() || *c == b'_') {
         if s[i] != b'_' {
             num_str.push(s[i] as char);
         }
@@ -338,7 +396,8 @@ fn lex_value(cursor: &mut Cursor) -> Option<Spanned<Token>> {
         i += 1;
     }
 
-    if s.get(i) == Some(&b'.') {
+    // `2.*x` is `2 .* x`, not `2. * x`, and `0..n` is a range.
+    if s.get(i) == Some(&b'.') && !matches!(s.get(i + 1), Some(b'*' | b'.')) {
         num_str.push('.');
         seen_fpoint = true;
         i += 1;
@@ -356,6 +415,19 @@ fn lex_value(cursor: &mut Cursor) -> Option<Spanned<Token>> {
         return None;
     }
 
+    // A trailing `i` that does not start an identifier marks an imaginary
+    // literal such as `4i` or `2.5i`.
+    let imaginary = s.get(i) == Some(&b'i') && !sr[i + 1..].starts_with(is_identifier_char);
+
+    if imaginary {
+        let v = num_str.parse::<f64>().ok()?;
+        cursor.advance(i + 1);
+        return Some(Spanned {
+            span: Span::from(cursor.i - i - 1, i + 1),
+            data: Token::Value(Value::Complex(Complex::new(0.0, v))),
+        });
+    }
+
     cursor.advance(i);
 
     if seen_fpoint {
@@ -365,90 +437,264 @@ fn lex_value(cursor: &mut Cursor) -> Option<Spanned<Token>> {
             data: Token::Value(Value::Float(v)),
         })
     } else {
-        let v = num_str.parse::<i64>().ok()?;
+        // Literals too large for `i64` become `BigInt`.
+        let v = match num_str.parse::<i64>() {
+            Ok(v) => Value::Int(v),
+            Err(_) => Value::BigInt(num_str.parse::<BigInt>().ok()?),
+        };
         Some(Spanned {
             span: Span::from(cursor.i - i, i),
-            data: Token::Value(Value::Int(v)),
+            data: Token::Value(v),
         })
     }
 }
 
-fn lex_macro(cursor: &mut Cursor) -> Option<Spanned<Token>> {
-    let sr = cursor.rest();
-    let s = sr.as_bytes();
-    let mut i = 0usize;
+fn lex_str(cursor: &mut Cursor) -> Result<Option<Spanned<Token>>, Spanned<Error>> {
+    if cursor.peek() != Some('"') {
+        return Ok(None);
+    }
 
-    if s.get(0) != Some(&b'{') {
-        return None;
+    let start = cursor.i;
+    let sr = cursor.rest();
+    let mut chars = sr.char_indices().skip(1);
+    let mut value = String::new();
+
+    let lexing_error = |span: Span, err: LexingError| Spanned {
+        span,
+        data: Error::LexingError(err),
+    };
+
+    loop {
+        let Some((i, c)) = chars.next() else {
+            return Err(
+                lexing_error(Span::single(start), LexingError::UnterminatedString { index: start })
+            );
+        };
+
+        match c {
+            '"' => {
+                cursor.advance(i + 1);
+                return Ok(
+                    Some(Spanned {
+                        span: Span::from(start, i + 1),
+                        data: Token::Value(Value::Str(value)),
+                    })
+                );
+            }
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 't')) => '\t',
+                    Some((_, 'r')) => '\r',
+                    Some((_, '0')) => '\0',
+                    Some((_, '\\')) => '\\',
+                    Some((_, '"')) => '"',
+                    Some((_, '\'')) => '\'',
+                    Some((_, 'u')) => {
+                        let rest = &sr[i + 2..];
+                        let code = rest
+                            .strip_prefix('{')
+                            .and_then(|r| r.split_once('}'))
+                            .and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok())
+                            .and_then(char::from_u32);
+
+                        match code {
+                            Some(ch) => {
+                                let len = rest.find('}').unwrap();
+                                for _ in 0..len + 1 {
+                                    chars.next();
+                                }
+                                ch
+                            }
+                            None => {
+                                return Err(
+                                    lexing_error(Span::from(start + i, 2), LexingError::InvalidEscape {
+                                        index: start + i,
+                                    })
+                                );
+                            }
+                        }
+                    }
+                    Some(_) => {
+                        return Err(
+                            lexing_error(Span::from(start + i, 2), LexingError::InvalidEscape {
+                                index: start + i,
+                            })
+                        );
+                    }
+                    None => {
+                        return Err(
+                            lexing_error(Span::single(start), LexingError::UnterminatedString {
+                                index: start,
+                            })
+                        );
+                    }
+                };
+                value.push(escaped);
+            }
+            _ => value.push(c),
+        }
     }
+}
 
-    i += 1;
+/// Whether `c` can start an identifier: `_` or a Unicode `XID_Start`
+/// character, which covers letters in any script, as in `Δt` or `θ`.
+pub fn is_identifier_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
 
-    if let Some(&c) = s.get(i) && (c.is_ascii_alphabetic() || c == b'_') {
-        i += 1;
+/// Whether `c` can continue an identifier: a Unicode `XID_Continue`
+/// character, which adds digits, `_` and combining marks such as the accent
+/// of a decomposed `é`.
+pub fn is_identifier_char(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
 
-        while let Some(&c) = s.get(i) && (c.is_ascii_alphanumeric() || c == b'_') {
-            i += 1;
+/// Length in bytes of the identifier at the start of `s`, or 0 if there is
+/// none.
+fn identifier_len(s: &str) -> usize {
+    match s.chars().next() {
+        Some(c) if is_identifier_start(c) => {
+            s.find(|c: char| !is_identifier_char(c)).unwrap_or(s.len())
         }
-    } else {
-        return None;
+        _ => 0,
     }
+}
 
-    if i == 1 || s.get(i) != Some(&b'}') {
+fn lex_macro(cursor: &mut Cursor) -> Option<Spanned<Token>> {
+    let sr = cursor.rest();
+
+    let name = sr.strip_prefix('{')?;
+    let len = identifier_len(name);
+    if len == 0 || !name[len..].starts_with('}') {
         return None;
     }
 
-    i += 1;
+    let i = len + 2;
 
     cursor.advance(i);
 
     Some(Spanned {
-        span: Span::from(cursor.i - i + 1, i - 1),
+        span: Span::from(cursor.i - i, i),
         data: Token::Macro(sr[1..i - 1].to_string()),
     })
 }
 
 fn lex_identifier(cursor: &mut Cursor) -> Option<Spanned<Token>> {
     let sr = cursor.rest();
-    let s = sr.as_bytes();
-    let mut i = 0usize;
-
-    if let Some(&c) = s.get(0) && (c.is_ascii_alphabetic() || c == b'_') {
-        i += 1;
 
-        while let Some(&c) = s.get(i) && (c.is_ascii_alphanumeric() || c == b'_') {
-            i += 1;
-        }
-    } else {
+    let i = identifier_len(sr);
+    if i == 0 {
         return None;
     }
 
     cursor.advance(i);
 
+    let word = &sr[0..i];
     Some(Spanned {
         span: Span::from(cursor.i - i, i),
-        data: Token::Identifier(sr[0..i].to_string()),
+        data: match Keyword::from_word(word) {
+            Some(k) => Token::Keyword(k),
+            None => Token::Identifier(word.to_string()),
+        },
     })
 }
 
+/// Length of the comment at the start of `s`: `#` or `//` up to the end of
+/// the line, or `/* ... */`, which may nest. `None` if `s` does not start
+/// with a comment or starts with a block comment that is never closed.
+pub fn comment_len(s: &str) -> Option<usize> {
+    if s.starts_with('#') || s.starts_with("//") {
+        return Some(s.find('\n').unwrap_or(s.len()));
+    }
+    if !s.starts_with("/*") {
+        return None;
+    }
+
+    let b = s.as_bytes();
+    let mut depth = 0usize;
+    let mut i = 0usize;
+
+    while i + 1 < b.len() {
+        match &b[i..i + 2] {
+            b"/*" => {
+                depth += 1;
+                i += 2;
+            }
+            b"*/" => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+/// Skips whitespace and comments.
+fn skip_trivia(cursor: &mut Cursor) -> Result<(), Spanned<Error>> {
+    loop {
+        cursor.skip_while(char::is_whitespace);
+
+        match comment_len(cursor.rest()) {
+            Some(n) => {
+                cursor.advance(n);
+            }
+            None if cursor.rest().starts_with("/*") => {
+                return Err(Spanned {
+                    span: Span::from(cursor.i, 2),
+                    data: Error::LexingError(LexingError::UnterminatedComment { index: cursor.i }),
+                });
+            }
+            None => {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Whether `token` can end an operand, in which case a following `-` is a
+/// subtraction rather than the sign of a literal: `3-4` is `3 - 4`.
+fn ends_operand(token: Option<&Spanned<Token>>) -> bool {
+    matches!(
+        token.map(|t| &t.data),
+        Some(
+            | Token::Value(_)
+            | Token::Identifier(_)
+            | Token::Macro(_)
+            | Token::Operator(Operator::Grouping(GroupingOp::RightParen))
+            | Token::Operator(Operator::Grouping(GroupingOp::RightBracket))
+            | Token::Operator(Operator::Grouping(GroupingOp::RightBrace))
+        )
+    )
+}
+
 pub fn lex_string(s: &str) -> Result<Vec<Spanned<Token>>, Spanned<Error>> {
     let mut res = Vec::new();
     let mut cursor = Cursor::new(s);
 
-    cursor.skip_while(|c| c.is_ascii_whitespace());
+    skip_trivia(&mut cursor)?;
 
     while !cursor.is_eof() {
-        if let Some(t) = lex_value(&mut cursor) {
+        if let Some(t) = lex_str(&mut cursor)? {
             res.push(t);
-        } else if let Some(t) = lex_operator(&mut cursor) {
+        } else if let Some(t) = lex_value(&mut cursor, !ends_operand(res.last())) {
             res.push(t);
         } else if let Some(t) = lex_macro(&mut cursor) {
             res.push(t);
+        } else if let Some(t) = lex_operator(&mut cursor) {
+            res.push(t);
         } else if let Some(t) = lex_identifier(&mut cursor) {
             res.push(t);
         } else {
+            let len = cursor.peek().map_or(1, char::len_utf8);
             return Err(Spanned {
-                span: Span::single(cursor.i),
+                span: Span::from(cursor.i, len),
                 data: Error::LexingError(LexingError::InvalidToken {
                     src: s.to_string(),
                     index: cursor.i,
@@ -456,8 +702,34 @@ pub fn lex_string(s: &str) -> Result<Vec<Spanned<Token>>, Spanned<Error>> {
             });
         }
 
-        cursor.skip_while(|c| c.is_ascii_whitespace());
+        skip_trivia(&mut cursor)?;
     }
 
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifiers(s: &str) -> Vec<String> {
+        lex_string(s)
+            .unwrap()
+            .into_iter()
+            .filter_map(|t| match t.data {
+                Token::Identifier(name) => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        assert_eq!(identifiers("Δt * θ_1"), ["Δt", "θ_1"]);
+        // `e` followed by U+0301 COMBINING ACUTE ACCENT is one identifier,
+        // like the precomposed `é`.
+        assert_eq!(identifiers("cafe\u{301} + café"), ["cafe\u{301}", "café"]);
+        // A combining mark cannot start one.
+        assert!(lex_string("\u{301}e").is_err());
+    }
+}
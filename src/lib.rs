@@ -0,0 +1,30 @@
+pub mod bigint;
+pub mod compiler;
+pub mod completion;
+pub mod complex;
+pub mod debug;
+pub mod diagnostics;
+pub mod error;
+pub mod evaluater;
+pub mod functions;
+pub mod interpreter;
+pub mod lexer;
+pub mod matrix;
+pub mod native_function;
+pub mod operator;
+pub mod parser;
+pub mod rational;
+pub mod scope;
+pub mod script;
+pub mod session;
+pub mod span;
+pub mod suggestion;
+pub mod token;
+pub mod user_function;
+pub mod value;
+pub mod vm;
+
+pub use error::Error;
+pub use interpreter::Interpreter;
+pub use span::{ Span, Spanned };
+pub use value::{ Value, ValueType };
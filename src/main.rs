@@ -1,133 +1,397 @@
-use std::collections::HashMap;
-use std::io::{ self, Write };
+use std::{ env, fs, io::{ self, IsTerminal, Read }, path::PathBuf };
 
-use crate::debug::{ print_debug_user_def_function, print_debug_vars };
-use crate::error::Error;
-use crate::evaluater::EvalResultType;
-use crate::parser::{ Expr, parse_string };
-use crate::span::Spanned;
-use crate::{ evaluater::evaluate_expr, value::Value };
+use math_interpreter::debug::{
+    print_debug_functions,
+    print_debug_user_def_function,
+    print_debug_vars,
+};
+use math_interpreter::diagnostics::{ Source, describe };
+use math_interpreter::error::{ self, Error, SessionError };
+use math_interpreter::parser::{ Expr, parse_recovering };
+use math_interpreter::script::{ Statement, is_command, is_incomplete, split_statements };
+use math_interpreter::session::{ self, Conflict };
+use math_interpreter::span::{ Span, Spanned, line_col };
+use math_interpreter::{ Interpreter, Value };
+use math_interpreter::completion::{ self, Names };
+use rustyline::{
+    CompletionType,
+    Config,
+    Context,
+    Editor,
+    Helper,
+    completion::{ Completer, Pair },
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+};
 
-mod error;
-mod evaluater;
-mod lexer;
-mod operator;
-mod parser;
-mod span;
-mod token;
-mod value;
-mod debug;
-mod functions;
+/// Exit code for bad arguments or unreadable input.
+const EXIT_USAGE: i32 = 1;
+const EXIT_LEXING: i32 = 2;
+const EXIT_PARSING: i32 = 3;
+const EXIT_EVAL: i32 = 4;
 
-fn print_error(src: &str, err: &span::Spanned<error::Error>) {
-    match &err.data {
-        error::Error::LexingError(err) => {
-            println!("Lexing Error: {}", error::lexing_error::error_to_string(err.clone()));
-        }
-        error::Error::EvalError(err) => {
-            println!("Evaluation Error: {}", error::eval_error::error_to_string(err.clone()));
+const USAGE: &str = "\
+usage: math_interpreter [options] [file]
+
+Runs the statements in `file`, the `-e` expressions or piped stdin, or starts
+an interactive session when none are given.
+
+options:
+  -e <expr>    evaluate <expr> and print the result (repeatable)
+  -q, --quiet  do not print messages from bracket commands such as [def]
+  --json       print results and errors as JSON objects, one per line
+  --history-size <n>
+               keep <n> inputs in the REPL history, 0 to keep none
+               (default 1000, or MATH_INTERPRETER_HISTORY_SIZE)
+  -h, --help   print this help
+
+The REPL supports line editing, Up/Down history and Ctrl-R reverse search.
+History is saved to math_interpreter/history in the user config directory.
+
+exit codes: 1 usage or I/O error, 2 lexing error, 3 parsing error,
+4 evaluation error";
+
+/// How results and errors are printed.
+#[derive(Debug, Clone, Copy, Default)]
+struct Output {
+    quiet: bool,
+    json: bool,
+}
+
+#[derive(Debug)]
+struct Options {
+    exprs: Vec<String>,
+    path: Option<String>,
+    output: Output,
+    history_size: usize,
+    help: bool,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let history_size = match env::var("MATH_INTERPRETER_HISTORY_SIZE") {
+        Ok(size) => {
+            size.trim()
+                .parse()
+                .map_err(|_| format!("invalid MATH_INTERPRETER_HISTORY_SIZE {}", size))?
         }
-        _ => {
-            println!("Unexpected Error");
+        Err(_) => DEFAULT_HISTORY_SIZE,
+    };
+    let mut options = Options {
+        exprs: Vec::new(),
+        path: None,
+        output: Output::default(),
+        history_size,
+        help: false,
+    };
+    let mut args = args.peekable();
+    let mut only_paths = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" if !only_paths => {
+                let expr = args.next().ok_or("option -e requires an expression")?;
+                options.exprs.push(expr);
+            }
+            "-q" | "--quiet" if !only_paths => {
+                options.output.quiet = true;
+            }
+            "--json" if !only_paths => {
+                options.output.json = true;
+            }
+            "--history-size" if !only_paths => {
+                let size = args.next().ok_or("option --history-size requires a number")?;
+                options.history_size = size
+                    .parse()
+                    .map_err(|_| format!("invalid history size {}", size))?;
+            }
+            "-h" | "--help" if !only_paths => {
+                options.help = true;
+            }
+            "--" if !only_paths => {
+                only_paths = true;
+            }
+            _ if !only_paths && arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("unknown option {}", arg));
+            }
+            _ => {
+                if options.path.is_some() {
+                    return Err(format!("unexpected argument {}", arg));
+                }
+                options.path = Some(arg);
+            }
         }
     }
 
-    const MAX_WIDTH: usize = 50;
+    Ok(options)
+}
+
+/// Stage of running source text that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    /// Reading or writing a file, such as a session.
+    Io,
+    Lexing,
+    Parsing,
+    Evaluation,
+}
 
-    let span = err.span;
-    let src_len = src.len();
+impl Stage {
+    /// Stage of `err`, raised while parsing if `parsing` is set.
+    fn of(err: &Error, parsing: bool) -> Stage {
+        match err {
+            Error::SessionError(SessionError::Io { .. }) => Stage::Io,
+            Error::LexingError(_) => Stage::Lexing,
+            Error::ParseError(_) => Stage::Parsing,
+            _ if parsing => Stage::Parsing,
+            _ => Stage::Evaluation,
+        }
+    }
 
-    let span_start = span.start.min(src_len);
-    let span_end = (span.end - 1).min(src_len);
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Io => "io",
+            Stage::Lexing => "lexing",
+            Stage::Parsing => "parsing",
+            Stage::Evaluation => "evaluation",
+        }
+    }
 
-    let mid = (span_start + span_end) / 2;
-    let half = MAX_WIDTH / 2;
+    fn exit_code(self) -> i32 {
+        match self {
+            Stage::Io => EXIT_USAGE,
+            Stage::Lexing => EXIT_LEXING,
+            Stage::Parsing => EXIT_PARSING,
+            Stage::Evaluation => EXIT_EVAL,
+        }
+    }
+}
 
-    let mut win_start = mid.saturating_sub(half);
-    let win_end = (win_start + MAX_WIDTH).min(src_len);
+fn error_text(err: &Error) -> String {
+    match err {
+        Error::LexingError(err) => error::lexing_error::error_to_string(err.clone()),
+        Error::ParseError(err) => error::parse_error::error_to_string(err.clone()),
+        Error::EvalError(err) => error::eval_error::error_to_string(err.clone()),
+        Error::SessionError(err) => error::session_error::error_to_string(err.clone()),
+        _ => "Unexpected Error".to_string(),
+    }
+}
 
-    if win_end - win_start < MAX_WIDTH {
-        win_start = win_end.saturating_sub(MAX_WIDTH);
+/// `s` as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
     }
+    out.push('"');
+    out
+}
 
-    let snippet = &src[win_start..win_end];
+/// `err` as a JSON object. `location` is the file name, line and column
+/// of the error, when it comes from a named source.
+fn json_error(stage: Stage, err: &Spanned<Error>, location: Option<(&str, usize, usize)>) -> String {
+    let mut fields = vec![
+        format!("\"stage\": {}", json_string(stage.name())),
+        format!("\"message\": {}", json_string(&error_text(&err.data))),
+        format!("\"span\": [{}, {}]", err.span.start, err.span.end),
+    ];
+    if let Some((name, line, col)) = location {
+        fields.push(format!("\"file\": {}", json_string(name)));
+        fields.push(format!("\"line\": {}", line));
+        fields.push(format!("\"column\": {}", col));
+    }
+    format!("{{\"error\": {{{}}}}}", fields.join(", "))
+}
 
-    print!("{}", snippet);
+fn print_value(v: &Value, output: Output) {
+    if output.json {
+        println!(
+            "{{\"value\": {}, \"type\": {}}}",
+            json_string(&v.symbol()),
+            json_string(&format!("{:?}", v.value_type()))
+        );
+    } else {
+        println!("{}", v.symbol());
+    }
+}
 
-    let mut marker = String::new();
+/// Prints `err` for an interactive input as a diagnostic over the input,
+/// named `<repl>`.
+fn print_error(src: &str, err: &Spanned<Error>, stage: Stage, interpreter: &Interpreter, output: Output) {
+    if output.json {
+        eprintln!("{}", json_error(stage, err, None));
+        return;
+    }
 
-    let caret_start = span_start.saturating_sub(win_start);
-    let caret_end = span_end.saturating_sub(win_start).min(snippet.len());
+    print_diagnostic("<repl>", src, 0, err, stage, interpreter);
+}
 
-    for i in 0..snippet.len() {
-        if i == caret_start || (i == caret_end && caret_start != caret_end) {
-            marker.push('^');
-        } else {
-            marker.push(' ');
-        }
+/// Prints `err` from the statement at byte `offset` of the source `src`
+/// named `name` as a diagnostic, or as a JSON object with the line and
+/// column of the error.
+fn print_source_error(
+    name: &str,
+    src: &str,
+    offset: usize,
+    err: &Spanned<Error>,
+    stage: Stage,
+    interpreter: &Interpreter,
+    output: Output
+) {
+    if output.json {
+        let (line, col) = line_col(src, offset + err.span.start);
+        eprintln!("{}", json_error(stage, err, Some((name, line, col))));
+        return;
     }
 
-    println!("{}", marker);
+    print_diagnostic(name, src, offset, err, stage, interpreter);
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Renders `err` rustc-style, in colour if both stdout and stderr are
+/// terminals.
+fn print_diagnostic(
+    name: &str,
+    src: &str,
+    offset: usize,
+    err: &Spanned<Error>,
+    stage: Stage,
+    interpreter: &Interpreter
+) {
+    let mut sources = vec![Source { name: name.to_string(), text: src.to_string() }];
+    let diagnostic = describe(err, 0, offset, &mut sources, interpreter).with_code(stage.name());
+    let colour = io::stdout().is_terminal() && io::stderr().is_terminal();
+    eprint!("{}", diagnostic.render(&sources, colour));
+}
+
+#[derive(Debug, Clone, PartialEq)]
 enum CommandResult {
     End,
     Continue,
+    /// The command failed, with the span relative to its input line.
+    Failed(Spanned<Error>),
+    /// The command failed in the file `name` with contents `src`, such as a
+    /// session file being loaded, with the span relative to `src`.
+    FailedIn {
+        name: String,
+        src: String,
+        err: Spanned<Error>,
+    },
     None,
 }
 
-fn eval_command<'a>(
-    input: &String,
-    vars: &mut HashMap<String, Value>,
-    functions: &HashMap<String, fn(&Vec<Value>) -> Result<Value, error::Error>>,
-    user_def_functions: &'a mut HashMap<String, Box<Spanned<Expr>>>,
-    debug: bool
-) -> CommandResult {
+fn eval_command(input: &str, interpreter: &mut Interpreter, output: Output) -> CommandResult {
     if input.to_lowercase().starts_with("[exit]") {
         CommandResult::End
     } else if input.to_lowercase().starts_with("[variables]") {
-        print_debug_vars(&vars);
+        print_debug_vars(interpreter.vars());
         CommandResult::Continue
     } else if input.to_lowercase().starts_with("[clear]") {
-        vars.clear();
+        interpreter.clear_vars();
+        CommandResult::Continue
+    } else if input.to_lowercase().starts_with("[functions]") {
+        print_debug_functions(interpreter.functions());
         CommandResult::Continue
     } else if input.to_lowercase().starts_with("[defs]") {
-        print_debug_user_def_function(&user_def_functions, debug);
+        print_debug_user_def_function(
+            interpreter.macros(),
+            interpreter.user_functions(),
+            interpreter.debug()
+        );
         CommandResult::Continue
     } else if input.to_lowercase().starts_with("[def ") {
         let command_end = input.find("]");
         let names: String;
         let expr_str: String;
+        let expr_start: usize;
         match command_end {
             Some(n) => {
                 names = input[5..n].trim_start().to_string();
-                expr_str = input[n + 1..].trim_start().to_string();
+                expr_str = input[n + 1..].trim().to_string();
+                expr_start = input.len() - input[n + 1..].trim_start().len();
             }
             None => {
                 return CommandResult::None;
             }
         }
 
-        let expr = parse_string(&expr_str, debug);
-
-        if let Ok(expr_ok) = expr {
-            for name in names.split_whitespace() {
-                user_def_functions.insert(name.to_string(), expr_ok.clone());
+        for name in names.split_whitespace() {
+            if let Err(err) = interpreter.define_macro(name, &expr_str) {
+                let span = Span {
+                    start: err.span.start + expr_start,
+                    end: err.span.end + expr_start,
+                };
+                return CommandResult::Failed(Spanned { span, data: err.data });
+            }
+            if !output.quiet && !output.json {
                 println!("  MACRO(s) {{{}}} = {}", name, expr_str);
             }
-        } else if let Err(err) = expr {
-            print_error(&expr_str, &err);
         }
 
         CommandResult::Continue
-    } else if input.to_lowercase().starts_with("[del]") {
-        let v = input.split("]").collect::<Vec<&str>>();
-        if v.len() >= 2 {
-            let s = v[1].strip_suffix("\r\n");
-            if let Some(var_name0) = s && let Some(var_name1) = var_name0.strip_prefix(' ') {
-                vars.remove(var_name1);
+    } else if input.to_lowercase().starts_with("[save ") || input.to_lowercase().starts_with("[load ") {
+        let Some(n) = input.find("]") else {
+            return CommandResult::None;
+        };
+        let path = input[6..n].trim();
+        let path_start = input.len() - input[6..].trim_start().len();
+        let io_error = |err: io::Error| {
+            let span = Span { start: path_start, end: (path_start + path.len()).max(path_start + 1) };
+            let err = error::SessionError::Io { path: path.to_string(), message: err.to_string() };
+            CommandResult::Failed(Spanned { span, data: Error::SessionError(err) })
+        };
+
+        if input.to_lowercase().starts_with("[save ") {
+            if let Err(err) = fs::write(path, session::save(interpreter)) {
+                return io_error(err);
             }
+            if !output.quiet && !output.json {
+                println!(
+                    "  SAVED {} variable(s), {} macro(s) to {}",
+                    interpreter.vars().len(),
+                    interpreter.macros().len(),
+                    path
+                );
+            }
+            return CommandResult::Continue;
+        }
+
+        let src = match fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(err) => {
+                return io_error(err);
+            }
+        };
+        match session::load(interpreter, &src) {
+            Ok(report) => {
+                for conflict in &report.conflicts {
+                    print_conflict(path, conflict, output);
+                }
+                if !output.quiet && !output.json {
+                    println!(
+                        "  LOADED {} variable(s), {} macro(s) from {}",
+                        report.vars,
+                        report.macros,
+                        path
+                    );
+                }
+                CommandResult::Continue
+            }
+            Err(err) => CommandResult::FailedIn { name: path.to_string(), src, err },
+        }
+    } else if input.to_lowercase().starts_with("[del]") {
+        let name = input[5..].trim();
+        if !name.is_empty() {
+            interpreter.remove_var(name);
         }
         CommandResult::Continue
     } else {
@@ -135,71 +399,401 @@ fn eval_command<'a>(
     }
 }
 
-fn main() {
-    let mut vars: HashMap<String, Value> = HashMap::new();
-    type Func = fn(&Vec<Value>) -> Result<Value, Error>;
-    let functions: HashMap<String, fn(&Vec<Value>) -> Result<Value, error::Error>> = HashMap::from([
-        ("to_bool".to_string(), functions::to_bool as Func),
-        ("to_int".to_string(), functions::to_int as Func),
-        ("to_float".to_string(), functions::to_float as Func),
-        ("any".to_string(), functions::any as Func),
-        ("all".to_string(), functions::all as Func),
-        ("max".to_string(), functions::max as Func),
-        ("min".to_string(), functions::min as Func),
-        ("clamp".to_string(), functions::clamp as Func),
-    ]);
-    let mut user_def_functions = HashMap::new();
+/// Reports on stderr that loading the session file `path` replaced a
+/// definition by a different one.
+fn print_conflict(path: &str, conflict: &Conflict, output: Output) {
+    let (kind, name, old, new) = match conflict {
+        Conflict::Variable { name, old, new } => {
+            let describe = |v: &Value| format!("{:?} {}", v.value_type(), v.symbol());
+            ("variable", name.clone(), describe(old), describe(new))
+        }
+        Conflict::Macro { name, old, new } => {
+            ("macro", format!("{{{}}}", name), old.clone(), new.clone())
+        }
+    };
+
+    if output.json {
+        eprintln!(
+            "{{\"conflict\": {{\"file\": {}, \"kind\": {}, \"name\": {}, \"old\": {}, \"new\": {}}}}}",
+            json_string(path),
+            json_string(kind),
+            json_string(&name),
+            json_string(&old),
+            json_string(&new)
+        );
+    } else {
+        eprintln!("{}: conflicting {} {}: {} replaced by {}", path, kind, name, old, new);
+    }
+}
+
+/// Parses and evaluates `src`, returning the value to print, which is none
+/// for a function definition. Fails with every syntax error in `src`, or
+/// else with the error that stopped the evaluation, and the stage of the
+/// first error.
+fn evaluate(
+    interpreter: &mut Interpreter,
+    src: &str
+) -> Result<Option<Value>, (Stage, Vec<Spanned<Error>>)> {
+    let (expr, errors) = interpreter.parse_recovering(src);
+    if let Some(first) = errors.first() {
+        return Err((Stage::of(&first.data, true), errors));
+    }
+    let value = interpreter.eval_expr(&expr).map_err(|err| (Stage::Evaluation, vec![err]))?;
+    Ok(if let Expr::FunctionDef { .. } = expr.data { None } else { Some(value) })
+}
+
+/// Whether `s` starts with `prefix`, ignoring ASCII case.
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.get(..prefix.len()).is_some_and(|p| p.eq_ignore_ascii_case(prefix))
+}
+
+/// Part of an input that holds an expression: the body of `[def]`, the
+/// input after `[debug]`, nothing for other bracket commands.
+fn expression_part(input: &str) -> &str {
+    let mut s = input.trim_start();
+    if starts_with_ignore_case(s, "[debug]") {
+        s = s[7..].trim_start();
+    }
+
+    if starts_with_ignore_case(s, "[def ") {
+        s.find(']').map_or("", |n| &s[n + 1..])
+    } else if is_command(s) {
+        ""
+    } else {
+        s
+    }
+}
+
+/// Number of inputs kept in the REPL history when neither
+/// `--history-size` nor `MATH_INTERPRETER_HISTORY_SIZE` is given.
+const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// File the REPL history is kept in: `math_interpreter/history` under
+/// `$XDG_CONFIG_HOME`, `%APPDATA%` or `~/.config`.
+fn history_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("math_interpreter").join("history"))
+}
+
+/// Tab completion for the REPL over the names known after the last input.
+struct ReplHelper {
+    names: Names,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = completion::complete(&self.names, line, pos);
+        let pairs = candidates
+            .into_iter()
+            .map(|c| Pair { display: c.display, replacement: c.replacement })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+type ReplEditor = Editor<ReplHelper, DefaultHistory>;
+
+/// Reads one input for the REPL, prompting with `... ` for more lines while
+/// its expression is incomplete. A blank continuation line ends the input
+/// as it is, and Ctrl-C discards it. `None` at the end of input.
+fn read_input(editor: &mut ReplEditor) -> Option<String> {
+    let mut input = String::new();
+    let mut prompt = "> ";
 
     loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                prompt = "> ";
+                continue;
+            }
+            Err(_) => {
+                return if input.is_empty() { None } else { Some(input) };
+            }
+        };
+
+        if !input.is_empty() && line.trim().is_empty() {
+            return Some(input);
+        }
+        input.push_str(&line);
+        input.push('\n');
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+        if !is_incomplete(expression_part(&input)) {
+            return Some(input);
+        }
+        prompt = "... ";
+    }
+}
+
+/// Runs the interactive session. Inputs, bracket commands included, are
+/// kept in a history of `history_size` entries that is saved to
+/// `history_path` on exit.
+fn repl(output: Output, history_size: usize) {
+    let mut interpreter = Interpreter::new();
+
+    let config = Config::builder()
+        .max_history_size(history_size.max(1))
+        .and_then(|b| b.history_ignore_dups(true))
+        .map(|b| b.auto_add_history(false).completion_type(CompletionType::List).build())
+        .unwrap_or_default();
+    let mut editor = match ReplEditor::with_config(config) {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("math_interpreter: {}", err);
+            return;
+        }
+    };
+    editor.set_helper(Some(ReplHelper { names: Names::new(&interpreter) }));
+
+    let history = history_path().filter(|_| history_size > 0);
+    if let Some(path) = &history {
+        // A missing file just means there is no history yet.
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            helper.names = Names::new(&interpreter);
+        }
+
+        let Some(mut input) = read_input(&mut editor) else {
+            println!();
+            break;
+        };
+        if input.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input.trim_end());
 
-        let debug: bool;
         if input.to_lowercase().starts_with("[debug]") {
-            debug = true;
+            interpreter.set_debug(true);
             input = input[7..].trim_start().to_string();
         } else {
-            debug = false;
+            interpreter.set_debug(false);
         }
 
-        let command_res = eval_command(
-            &input,
-            &mut vars,
-            &functions,
-            &mut user_def_functions,
-            debug
-        );
+        match eval_command(&input, &mut interpreter, output) {
+            CommandResult::Continue => {
+                continue;
+            }
+            CommandResult::End => {
+                break;
+            }
+            CommandResult::Failed(err) => {
+                print_error(&input, &err, Stage::of(&err.data, true), &interpreter, output);
+                continue;
+            }
+            CommandResult::FailedIn { name, src, err } => {
+                print_source_error(&name, &src, 0, &err, Stage::of(&err.data, true), &interpreter, output);
+                continue;
+            }
+            CommandResult::None => {}
+        }
+
+        match evaluate(&mut interpreter, &input) {
+            Ok(Some(v)) => print_value(&v, output),
+            Ok(None) => {}
+            Err((stage, errors)) => {
+                for err in &errors {
+                    print_error(&input, err, stage, &interpreter, output);
+                }
+            }
+        }
+    }
+
+    if let Some(path) = &history {
+        let saved = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(ReadlineError::from)
+            .and_then(|_| editor.save_history(path));
+        if let Err(err) = saved {
+            eprintln!("math_interpreter: cannot save history to {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// `text` of a statement at byte `start` without a leading `[debug]`, with
+/// its new start and whether it had one.
+fn strip_debug(text: &str, start: usize) -> (&str, usize, bool) {
+    if text.to_lowercase().starts_with("[debug]") {
+        let rest = text[7..].trim_start();
+        (rest, start + text.len() - rest.len(), true)
+    } else {
+        (text, start, false)
+    }
+}
+
+/// The syntax errors of all expression statements, each with the index of
+/// its statement and the byte where that statement starts.
+fn syntax_errors(statements: &[Statement]) -> Vec<(usize, usize, Spanned<Error>)> {
+    let mut found = Vec::new();
 
-        if command_res == CommandResult::Continue {
+    for (index, statement) in statements.iter().enumerate() {
+        let (text, start, _) = strip_debug(statement.text, statement.start);
+        if is_command(text) {
             continue;
-        } else if command_res == CommandResult::End {
-            break;
         }
 
-        let res = parser::parse_string(input.as_str(), debug);
+        let (_, errors) = parse_recovering(text, false);
+        found.extend(errors.into_iter().map(|err| (index, start, err)));
+    }
 
-        if let Ok(t) = res {
-            let result = evaluate_expr(&t, &mut vars, &mut user_def_functions, &functions);
-            if let Ok(mut v) = result {
-                let val = match v.result_type() {
-                    EvalResultType::Value => v.as_value(),
-                    EvalResultType::Ref => v.as_ref().cloned(),
-                };
+    found
+}
 
-                match val {
-                    Some(Value::Boolean(b)) => println!("{}", b),
-                    Some(Value::Int(i)) => println!("{}", i),
-                    Some(Value::Float(f)) => println!("{}", f),
-                    _ => {}
+/// Runs the statements of the source `src` named `name` in order, printing
+/// the value of each one not ended by `;`. The statements before the first
+/// syntax error run as usual, then every syntax error in the source is
+/// reported at once. Returns the exit code if the run has to stop, either at
+/// an error or at `[exit]`.
+fn run_source(name: &str, src: &str, interpreter: &mut Interpreter, output: Output) -> Option<i32> {
+    let statements = split_statements(src);
+    let errors = syntax_errors(&statements);
+    let runnable = errors.first().map_or(statements.len(), |(index, _, _)| *index);
+
+    if let Some(code) = run_statements(name, src, &statements[..runnable], interpreter, output) {
+        return Some(code);
+    }
+
+    let mut code = None;
+    for (_, start, err) in &errors {
+        let stage = Stage::of(&err.data, true);
+        print_source_error(name, src, *start, err, stage, interpreter, output);
+        code.get_or_insert(stage.exit_code());
+    }
+
+    code
+}
+
+/// Runs `statements` of the source `src` named `name`, which all parse.
+/// Returns the exit code if the run has to stop.
+fn run_statements(
+    name: &str,
+    src: &str,
+    statements: &[Statement],
+    interpreter: &mut Interpreter,
+    output: Output
+) -> Option<i32> {
+    for statement in statements {
+        let (text, start, debug) = strip_debug(statement.text, statement.start);
+        interpreter.set_debug(debug);
+
+        if is_command(text) {
+            match eval_command(text, interpreter, output) {
+                CommandResult::End => {
+                    return Some(0);
+                }
+                CommandResult::Failed(err) => {
+                    let stage = Stage::of(&err.data, true);
+                    print_source_error(name, src, start, &err, stage, interpreter, output);
+                    return Some(stage.exit_code());
+                }
+                CommandResult::FailedIn { name, src, err } => {
+                    let stage = Stage::of(&err.data, true);
+                    print_source_error(&name, &src, 0, &err, stage, interpreter, output);
+                    return Some(stage.exit_code());
+                }
+                CommandResult::Continue | CommandResult::None => {
+                    continue;
+                }
+            }
+        }
+
+        match evaluate(interpreter, text) {
+            Ok(v) => {
+                if let Some(v) = v && !statement.silent {
+                    print_value(&v, output);
+                }
+            }
+            Err((stage, errors)) => {
+                for err in &errors {
+                    print_source_error(name, src, start, err, stage, interpreter, output);
                 }
-            } else {
-                print_error(&input, &result.unwrap_err());
+                return Some(stage.exit_code());
             }
-        } else if let Err(err) = res {
-            print_error(&input, &err);
         }
     }
+
+    None
+}
+
+/// Runs the `-e` expressions and then the script, or piped stdin if there
+/// are neither, or else the interactive REPL. Returns the exit code.
+fn run(options: Options) -> i32 {
+    let output = options.output;
+    let mut interpreter = Interpreter::new();
+
+    if options.exprs.is_empty() && options.path.is_none() {
+        if io::stdin().is_terminal() {
+            repl(output, options.history_size);
+            return 0;
+        }
+
+        let mut src = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut src) {
+            eprintln!("<stdin>: {}", err);
+            return EXIT_USAGE;
+        }
+        return run_source("<stdin>", &src, &mut interpreter, output).unwrap_or(0);
+    }
+
+    for expr in &options.exprs {
+        if let Some(code) = run_source("-e", expr, &mut interpreter, output) {
+            return code;
+        }
+    }
+
+    if let Some(path) = &options.path {
+        let src = match fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                return EXIT_USAGE;
+            }
+        };
+        if let Some(code) = run_source(path, &src, &mut interpreter, output) {
+            return code;
+        }
+    }
+
+    0
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("math_interpreter: {}\n\n{}", msg, USAGE);
+            std::process::exit(EXIT_USAGE);
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    std::process::exit(run(options));
 }
@@ -0,0 +1,469 @@
+use crate::{
+    error::{ Error, EvalError },
+    operator::{ add, div, mul, sub },
+    value::{ Value, ValueType },
+};
+
+type BinaryFn = fn(&Value, &Value) -> Result<(Value, bool), Error>;
+type UnaryFn = fn(&Value) -> Result<(Value, bool), Error>;
+
+/// Dense matrix of numbers, stored row by row.
+///
+/// Elements are ordinary scalar values, so integer and rational entries stay
+/// exact through products, determinants and inverses. A matrix always has at
+/// least one row and one column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    cols: usize,
+    data: Vec<Value>,
+}
+
+impl Matrix {
+    /// Builds a matrix from non-empty lists of numbers of equal length, or
+    /// returns `None` if `rows` does not have that shape.
+    pub fn from_rows(rows: &[Value]) -> Option<Matrix> {
+        let cols = rows.first()?.as_list()?.len();
+        if cols == 0 {
+            return None;
+        }
+
+        let mut data = Vec::with_capacity(rows.len() * cols);
+        for row in rows {
+            let row = row.as_list()?;
+            if row.len() != cols || !row.iter().all(|v| v.value_type().is_numeric()) {
+                return None;
+            }
+            data.extend_from_slice(row);
+        }
+        Some(Matrix { cols, data })
+    }
+
+    /// `n` by `n` identity matrix. `n` must be positive.
+    pub fn identity(n: usize) -> Matrix {
+        let data = (0..n * n).map(|i| Value::Int((i % (n + 1) == 0) as i64)).collect();
+        Matrix { cols: n, data }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.data.len() / self.cols
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Rows by columns.
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows(), self.cols)
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.rows() == self.cols
+    }
+
+    /// Elements in row order.
+    pub fn elements(&self) -> &[Value] {
+        &self.data
+    }
+
+    pub fn row(&self, r: usize) -> &[Value] {
+        &self.data[r * self.cols..(r + 1) * self.cols]
+    }
+
+    /// Rows as list values.
+    pub fn to_rows(&self) -> Vec<Value> {
+        self.data
+            .chunks(self.cols)
+            .map(|row| Value::List(row.to_vec()))
+            .collect()
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let rows = self.rows();
+        let data = (0..self.data.len())
+            .map(|i| self.data[(i % rows) * self.cols + i / rows].clone())
+            .collect();
+        Matrix { cols: rows, data }
+    }
+
+    /// Applies `f` to every element.
+    pub fn map(&self, f: UnaryFn) -> Result<(Matrix, bool), Error> {
+        let mut invalid = false;
+        let data = self.data
+            .iter()
+            .map(|v| unary(f, v, &mut invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((Matrix { cols: self.cols, data }, invalid))
+    }
+
+    /// Applies `f` to every element paired with `rhs`, with the element on
+    /// the left when `flip` is false.
+    pub fn map_scalar(&self, rhs: &Value, flip: bool, f: BinaryFn) -> Result<(Matrix, bool), Error> {
+        let mut invalid = false;
+        let data = self.data
+            .iter()
+            .map(|v| if flip { binary(f, rhs, v, &mut invalid) } else { binary(f, v, rhs, &mut invalid) })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((Matrix { cols: self.cols, data }, invalid))
+    }
+
+    /// Applies `f` to pairs of elements at the same position. The shapes
+    /// must match.
+    pub fn zip_with(&self, rhs: &Matrix, f: BinaryFn) -> Result<(Matrix, bool), Error> {
+        let mut invalid = false;
+        let data = self.data
+            .iter()
+            .zip(rhs.data.iter())
+            .map(|(a, b)| binary(f, a, b, &mut invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((Matrix { cols: self.cols, data }, invalid))
+    }
+
+    /// Matrix product. The columns of `self` must match the rows of `rhs`.
+    pub fn product(&self, rhs: &Matrix) -> Result<(Matrix, bool), Error> {
+        let mut invalid = false;
+        let mut data = Vec::with_capacity(self.rows() * rhs.cols);
+        for r in 0..self.rows() {
+            for c in 0..rhs.cols {
+                let terms = self
+                    .row(r)
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| (a, &rhs.data[k * rhs.cols + c]));
+                data.push(dot(terms, &mut invalid)?);
+            }
+        }
+        Ok((Matrix { cols: rhs.cols, data }, invalid))
+    }
+
+    /// Product with a column vector, whose length must match the columns.
+    pub fn apply_to(&self, v: &[Value]) -> Result<(Vec<Value>, bool), Error> {
+        let mut invalid = false;
+        let items = (0..self.rows())
+            .map(|r| dot(self.row(r).iter().zip(v.iter()), &mut invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((items, invalid))
+    }
+
+    /// Determinant of a square matrix.
+    pub fn det(&self) -> Result<(Value, bool), Error> {
+        let mut invalid = false;
+        let mut grid = self.grid();
+        let reduction = reduce(&mut grid, self.cols, &mut invalid)?;
+        if reduction.pivots.len() < self.cols {
+            return Ok((self.zero(), invalid));
+        }
+
+        let mut det = Value::Int(if reduction.odd { -1 } else { 1 });
+        for p in &reduction.pivots {
+            det = binary(mul::apply, &det, p, &mut invalid)?;
+        }
+        Ok((det, invalid))
+    }
+
+    /// Zero of the type the elements promote to: Float or Complex zero if
+    /// any element is one, or else an exact `Int` zero.
+    fn zero(&self) -> Value {
+        let ty = self.data
+            .iter()
+            .map(|v| v.value_type())
+            .max_by_key(|ty| ty.rank())
+            .unwrap_or(ValueType::Int);
+        match ty {
+            ValueType::Float | ValueType::Complex => ty.default_value(),
+            _ => Value::Int(0),
+        }
+    }
+
+    /// Inverse of a square matrix, or `None` if it is singular.
+    pub fn inverse(&self) -> Result<Option<(Matrix, bool)>, Error> {
+        self.solve(&Matrix::identity(self.cols))
+    }
+
+    /// Number of linearly independent rows.
+    pub fn rank(&self) -> Result<usize, Error> {
+        let mut invalid = false;
+        let mut grid = self.grid();
+        Ok(reduce(&mut grid, self.cols, &mut invalid)?.pivots.len())
+    }
+
+    /// Solution `x` of `self * x = rhs` for a square matrix, with one column
+    /// of `x` per column of `rhs`, or `None` if `self` is singular. The rows
+    /// of `rhs` must match the rows of `self`.
+    pub fn solve(&self, rhs: &Matrix) -> Result<Option<(Matrix, bool)>, Error> {
+        let n = self.cols;
+        let mut invalid = false;
+        let mut grid = self.grid();
+        for (row, extra) in grid.iter_mut().zip(rhs.data.chunks(rhs.cols)) {
+            row.extend_from_slice(extra);
+        }
+
+        if reduce(&mut grid, n, &mut invalid)?.pivots.len() < n {
+            return Ok(None);
+        }
+
+        let data = grid.into_iter().flat_map(|row| row.into_iter().skip(n)).collect();
+        Ok(Some((Matrix { cols: rhs.cols, data }, invalid)))
+    }
+
+    /// Eigenvalues in ascending order, found with the Jacobi eigenvalue
+    /// algorithm. Returns `None` unless the matrix is real and symmetric.
+    pub fn symmetric_eigenvalues(&self) -> Option<Vec<f64>> {
+        let n = self.cols;
+        let mut a = self.data
+            .iter()
+            .map(|v| v.promote(ValueType::Float).and_then(|f| f.as_float()))
+            .collect::<Option<Vec<f64>>>()?;
+
+        for i in 0..n {
+            for j in 0..i {
+                if a[i * n + j] != a[j * n + i] {
+                    return None;
+                }
+            }
+        }
+
+        let scale: f64 = a.iter().map(|v| v * v).sum();
+        for _ in 0..100 {
+            let off: f64 = (0..n * n)
+                .filter(|i| i / n != i % n)
+                .map(|i| a[i] * a[i])
+                .sum();
+            if off <= f64::EPSILON * f64::EPSILON * scale {
+                break;
+            }
+
+            for p in 0..n {
+                for q in p + 1..n {
+                    if a[p * n + q] == 0.0 {
+                        continue;
+                    }
+
+                    // Rotation that zeroes a[p][q], applied to the columns
+                    // and then to the rows.
+                    let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * a[p * n + q]);
+                    let t = theta.signum() / (theta.abs() + theta.hypot(1.0));
+                    let c = 1.0 / t.hypot(1.0);
+                    let s = t * c;
+
+                    for k in 0..n {
+                        let (kp, kq) = (a[k * n + p], a[k * n + q]);
+                        a[k * n + p] = c * kp - s * kq;
+                        a[k * n + q] = s * kp + c * kq;
+                    }
+                    for k in 0..n {
+                        let (pk, qk) = (a[p * n + k], a[q * n + k]);
+                        a[p * n + k] = c * pk - s * qk;
+                        a[q * n + k] = s * pk + c * qk;
+                    }
+                }
+            }
+        }
+
+        let mut values = (0..n).map(|i| a[i * n + i]).collect::<Vec<_>>();
+        values.sort_by(|a, b| a.total_cmp(b));
+        Some(values)
+    }
+
+    fn grid(&self) -> Vec<Vec<Value>> {
+        self.data
+            .chunks(self.cols)
+            .map(|row| row.to_vec())
+            .collect()
+    }
+}
+
+/// Pivots found by `reduce`.
+struct Reduction {
+    /// Pivot values in the order they were used, before their rows were
+    /// scaled to 1.
+    pivots: Vec<Value>,
+    /// Whether an odd number of row swaps was made.
+    odd: bool,
+}
+
+/// Gauss-Jordan elimination over the leading `cols` columns of `grid`, with
+/// partial pivoting. Exact entries are only treated as zero when they are
+/// zero; floating point entries are treated as zero below a tolerance scaled
+/// to the largest entry.
+fn reduce(grid: &mut [Vec<Value>], cols: usize, invalid: &mut bool) -> Result<Reduction, Error> {
+    let largest = grid
+        .iter()
+        .flat_map(|row| row[..cols].iter())
+        .map(magnitude)
+        .fold(0.0, f64::max);
+    let tolerance = f64::EPSILON * (grid.len().max(cols) as f64) * largest;
+
+    let mut pivots = Vec::new();
+    let mut odd = false;
+    for c in 0..cols {
+        let row = pivots.len();
+        if row == grid.len() {
+            break;
+        }
+
+        let best = (row..grid.len())
+            .max_by(|a, b| magnitude(&grid[*a][c]).total_cmp(&magnitude(&grid[*b][c])))
+            .unwrap();
+        if is_negligible(&grid[best][c], tolerance) {
+            continue;
+        }
+        if best != row {
+            grid.swap(best, row);
+            odd = !odd;
+        }
+
+        let pivot = grid[row][c].clone();
+        for v in grid[row].iter_mut() {
+            *v = binary(div::apply, v, &pivot, invalid)?;
+        }
+
+        let pivot_row = grid[row].clone();
+        for (r, other) in grid.iter_mut().enumerate() {
+            let factor = other[c].clone();
+            if r == row || factor == Value::Int(0) {
+                continue;
+            }
+            for (v, p) in other.iter_mut().zip(pivot_row.iter()) {
+                let scaled = binary(mul::apply, &factor, p, invalid)?;
+                *v = binary(sub::apply, v, &scaled, invalid)?;
+            }
+        }
+
+        pivots.push(pivot);
+    }
+
+    Ok(Reduction { pivots, odd })
+}
+
+fn magnitude(v: &Value) -> f64 {
+    v.promote(ValueType::Complex)
+        .and_then(|c| c.as_complex())
+        .map_or(0.0, |c| c.abs())
+}
+
+fn is_negligible(v: &Value, tolerance: f64) -> bool {
+    match v {
+        Value::Float(_) | Value::Complex(_) => magnitude(v) <= tolerance,
+        _ => magnitude(v) == 0.0,
+    }
+}
+
+/// Sum of the products of `terms`.
+fn dot<'a>(
+    mut terms: impl Iterator<Item = (&'a Value, &'a Value)>,
+    invalid: &mut bool
+) -> Result<Value, Error> {
+    let (a, b) = terms.next().ok_or(Error::UnexpectedError)?;
+    let mut acc = binary(mul::apply, a, b, invalid)?;
+    for (a, b) in terms {
+        let term = binary(mul::apply, a, b, invalid)?;
+        acc = binary(add::apply, &acc, &term, invalid)?;
+    }
+    Ok(acc)
+}
+
+fn binary(f: BinaryFn, a: &Value, b: &Value, invalid: &mut bool) -> Result<Value, Error> {
+    let (v, bad) = f(a, b)?;
+    *invalid |= bad;
+    Ok(v)
+}
+
+fn unary(f: UnaryFn, v: &Value, invalid: &mut bool) -> Result<Value, Error> {
+    let (v, bad) = f(v)?;
+    *invalid |= bad;
+    Ok(v)
+}
+
+/// `DimensionMismatch` for `op` applied to operands of the given shapes,
+/// blaming the operand at `operand`.
+pub fn dimension_mismatch(op: &str, shapes: &[(usize, usize)], operand: usize) -> Error {
+    Error::EvalError(EvalError::DimensionMismatch {
+        op: op.to_string(),
+        shapes: shapes.to_vec(),
+        operand,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ bigint::BigInt, rational::Rational };
+
+    fn matrix(rows: &[&[Value]]) -> Matrix {
+        let rows: Vec<Value> = rows
+            .iter()
+            .map(|row| Value::List(row.to_vec()))
+            .collect();
+        Matrix::from_rows(&rows).unwrap()
+    }
+
+    fn ints(rows: &[&[i64]]) -> Matrix {
+        let rows: Vec<Value> = rows
+            .iter()
+            .map(|row| Value::List(row.iter().map(|v| Value::Int(*v)).collect()))
+            .collect();
+        Matrix::from_rows(&rows).unwrap()
+    }
+
+    fn ratio(num: i64, den: i64) -> Value {
+        Value::from_rational(Rational::new(BigInt::from(num), BigInt::from(den)).unwrap())
+    }
+
+    #[test]
+    fn from_rows_checks_the_shape() {
+        let row = |items: Vec<Value>| Value::List(items);
+        assert!(Matrix::from_rows(&[]).is_none());
+        assert!(Matrix::from_rows(&[row(vec![])]).is_none());
+        assert!(Matrix::from_rows(&[row(vec![Value::Int(1)]), row(vec![Value::Int(1), Value::Int(2)])]).is_none());
+        assert!(Matrix::from_rows(&[row(vec![Value::Str("a".into())])]).is_none());
+        assert_eq!(ints(&[&[1, 2, 3], &[4, 5, 6]]).transpose().shape(), (3, 2));
+    }
+
+    #[test]
+    fn det_is_exact() {
+        assert_eq!(ints(&[&[2, 1], &[1, 3]]).det().unwrap(), (Value::Int(5), false));
+        // A row swap flips the sign.
+        assert_eq!(ints(&[&[0, 1], &[1, 0]]).det().unwrap(), (Value::Int(-1), false));
+        assert_eq!(ints(&[&[1, 2], &[3, 4]]).det().unwrap(), (Value::Int(-2), false));
+    }
+
+    #[test]
+    fn singular_matrices() {
+        // The determinant is zero in the type of the elements.
+        let singular = [
+            (ints(&[&[1, 2], &[2, 4]]), Value::Int(0)),
+            (ints(&[&[1, 2, 3], &[4, 5, 6], &[7, 8, 9]]), Value::Int(0)),
+            (ints(&[&[0, 0], &[0, 0]]), Value::Int(0)),
+            (
+                matrix(&[&[Value::Float(0.1), Value::Float(0.2)], &[Value::Float(0.3), Value::Float(0.6)]]),
+                Value::Float(0.0),
+            ),
+        ];
+        for (m, zero) in &singular {
+            assert_eq!(&m.det().unwrap().0, zero, "{:?}", m);
+            assert_eq!(m.inverse().unwrap(), None, "{:?}", m);
+            let ones = Matrix::from_rows(&vec![Value::List(vec![Value::Int(1)]); m.rows()]).unwrap();
+            assert_eq!(m.solve(&ones).unwrap(), None, "{:?}", m);
+        }
+        assert_eq!(ints(&[&[1, 2, 3], &[2, 4, 6]]).rank().unwrap(), 1);
+        assert_eq!(ints(&[&[0, 0], &[0, 0]]).rank().unwrap(), 0);
+    }
+
+    #[test]
+    fn inverse_and_solve_are_exact() {
+        let (inv, invalid) = ints(&[&[2, 1], &[1, 1]]).inverse().unwrap().unwrap();
+        assert_eq!(inv, ints(&[&[1, -1], &[-1, 2]]));
+        assert!(!invalid);
+
+        let (inv, _) = ints(&[&[1, 2], &[3, 4]]).inverse().unwrap().unwrap();
+        assert_eq!(inv, matrix(&[&[Value::Int(-2), Value::Int(1)], &[ratio(3, 2), ratio(-1, 2)]]));
+
+        let (x, _) = ints(&[&[2, 1], &[1, 3]]).solve(&ints(&[&[3], &[5]])).unwrap().unwrap();
+        assert_eq!(x, matrix(&[&[ratio(4, 5)], &[ratio(7, 5)]]));
+
+        let m = ints(&[&[1, 2], &[3, 4]]);
+        assert_eq!(m.product(&Matrix::identity(2)).unwrap(), (m.clone(), false));
+        assert_eq!(m.product(&inv).unwrap().0, Matrix::identity(2));
+    }
+}
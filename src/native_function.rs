@@ -0,0 +1,168 @@
+use std::fmt;
+
+use crate::{ error::{ Error, EvalError }, user_function::Lambda, value::{ Value, ValueType } };
+
+pub type NativeFn = Box<dyn Fn(&[Value], &mut dyn Caller) -> Result<Value, Error> + Send + Sync>;
+
+/// Lets a native function call the function values it is given, in the
+/// engine that is running it.
+pub trait Caller {
+    /// Calls `func` with `args`. Errors from its body are reported at the
+    /// call of the native function.
+    fn call(&mut self, func: &Lambda, args: Vec<Value>) -> Result<Value, Error>;
+}
+
+/// Number of arguments a native function accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FnArity {
+    Fixed(usize),
+    /// Inclusive range.
+    Range(usize, usize),
+    /// At least this many arguments.
+    Variadic(usize),
+}
+
+impl FnArity {
+    pub fn accepts(self, n: usize) -> bool {
+        match self {
+            FnArity::Fixed(k) => n == k,
+            FnArity::Range(lo, hi) => lo <= n && n <= hi,
+            FnArity::Variadic(lo) => n >= lo,
+        }
+    }
+}
+
+impl fmt::Display for FnArity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FnArity::Fixed(k) => write!(f, "{}", k),
+            FnArity::Range(lo, hi) => write!(f, "{} to {}", lo, hi),
+            FnArity::Variadic(lo) => write!(f, "at least {}", lo),
+        }
+    }
+}
+
+/// Type accepted by one parameter of a native function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamType {
+    Any,
+    /// The argument must promote to this type and is promoted before the call.
+    Of(ValueType),
+}
+
+pub struct NativeFunction {
+    pub arity: FnArity,
+    /// One entry per positional parameter. Extra variadic arguments use the
+    /// last entry; an empty list accepts anything.
+    pub params: Vec<ParamType>,
+    pub doc: String,
+    func: NativeFn,
+}
+
+impl NativeFunction {
+    pub fn new(
+        arity: FnArity,
+        params: Vec<ParamType>,
+        doc: &str,
+        func: impl Fn(&[Value]) -> Result<Value, Error> + Send + Sync + 'static
+    ) -> Self {
+        NativeFunction::with_caller(arity, params, doc, move |args, _| func(args))
+    }
+
+    /// Like `new`, for a function that calls back into the interpreter, such
+    /// as `map`.
+    pub fn with_caller(
+        arity: FnArity,
+        params: Vec<ParamType>,
+        doc: &str,
+        func: impl Fn(&[Value], &mut dyn Caller) -> Result<Value, Error> + Send + Sync + 'static
+    ) -> Self {
+        NativeFunction {
+            arity,
+            params,
+            doc: doc.to_string(),
+            func: Box::new(func),
+        }
+    }
+
+    pub fn param_type(&self, index: usize) -> ParamType {
+        self.params.get(index).or(self.params.last()).copied().unwrap_or(ParamType::Any)
+    }
+
+    /// Checks `args` against the declared arity and parameter types and
+    /// returns them promoted to the declared types. A variadic function
+    /// called with a single list receives the elements of the list instead;
+    /// type errors then point at the list argument.
+    pub fn check_args(&self, name: &str, args: &[Value]) -> Result<Vec<Value>, Error> {
+        let (args, spread) = match (self.arity, args) {
+            (FnArity::Variadic(_), [Value::List(items)]) => (items.as_slice(), true),
+            _ => (args, false),
+        };
+
+        if !self.arity.accepts(args.len()) {
+            return Err(
+                Error::EvalError(EvalError::ArityMismatch {
+                    func: name.to_string(),
+                    expected: self.arity,
+                    found: args.len(),
+                })
+            );
+        }
+
+        args.iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                match self.param_type(i) {
+                    ParamType::Any => Ok(arg.clone()),
+                    ParamType::Of(ty) =>
+                        arg.promote(ty).ok_or(
+                            Error::EvalError(EvalError::ArgTypeMismatch {
+                                func: name.to_string(),
+                                index: if spread { 0 } else { i },
+                                expected: ty,
+                                found: arg.value_type(),
+                            })
+                        ),
+                }
+            })
+            .collect()
+    }
+
+    /// Calls the function after checking its arguments.
+    pub fn call(&self, name: &str, args: &[Value], caller: &mut dyn Caller) -> Result<Value, Error> {
+        let args = self.check_args(name, args)?;
+        self.call_unchecked(&args, caller)
+    }
+
+    /// Calls the function with arguments already passed through `check_args`.
+    pub fn call_unchecked(&self, args: &[Value], caller: &mut dyn Caller) -> Result<Value, Error> {
+        (self.func)(args, caller)
+    }
+
+    /// Human-readable signature such as `clamp(Any, Any, Any)` or `max(Any...)`.
+    pub fn signature(&self, name: &str) -> String {
+        let fixed = match self.arity {
+            FnArity::Fixed(k) | FnArity::Range(_, k) => k,
+            FnArity::Variadic(k) => k.max(1),
+        };
+
+        let mut params = (0..fixed)
+            .map(|i| {
+                let s = match self.param_type(i) {
+                    ParamType::Any => "Any".to_string(),
+                    ParamType::Of(ty) => format!("{:?}", ty),
+                };
+                match self.arity {
+                    FnArity::Range(lo, _) if i >= lo => format!("{s}?"),
+                    _ => s,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if let FnArity::Variadic(_) = self.arity && let Some(last) = params.last_mut() {
+            last.push_str("...");
+        }
+
+        format!("{}({})", name, params.join(", "))
+    }
+}
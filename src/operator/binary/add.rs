@@ -1,19 +1,35 @@
 use crate::error::Error;
-use crate::value::{Value, unify};
+use crate::operator::{BinaryOp, broadcast};
+use crate::bigint::BigInt;
+use crate::value::{Value, unify_pair};
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => {
+    if let Some(result) = broadcast::binary(BinaryOp::Addition, left, right, apply) {
+        return result;
+    }
+
+    if let (Value::Str(a), Value::Str(b)) = (left, right) {
+        return Ok((Value::Str(format!("{}{}", a, b)), false));
+    }
+
+    match unify_pair(left, right) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => {
                 let ai = if a { 1i64 } else { 0i64 };
                 let bi = if b { 1i64 } else { 0i64 };
                 Ok((Value::Int(ai + bi), false))
             }
-            (Value::Int(a), Value::Int(b)) => {
-                let (v, overflow) = a.overflowing_add(b);
-                Ok((Value::Int(v), overflow))
+            (&Value::Int(a), &Value::Int(b)) => match a.checked_add(b) {
+                Some(v) => Ok((Value::Int(v), false)),
+                None => Ok((Value::from_bigint(&BigInt::from(a) + &BigInt::from(b)), false)),
+            },
+            (Value::BigInt(a), Value::BigInt(b)) => Ok((Value::from_bigint(a + b), false)),
+            (Value::Rational(a), Value::Rational(b)) => Ok((Value::from_rational(a + b), false)),
+            (&Value::Complex(a), &Value::Complex(b)) => {
+                let v = a + b;
+                Ok((Value::Complex(v), !v.is_finite()))
             }
-            (Value::Float(a), Value::Float(b)) => {
+            (&Value::Float(a), &Value::Float(b)) => {
                 let v = a + b;
                 Ok((Value::Float(v), !v.is_finite()))
             }
@@ -1,12 +1,12 @@
 use crate::error::{Error, EvalError};
 use crate::operator::{BinaryOp, Operator};
-use crate::value::{Value, ValueType, unify_to};
+use crate::value::{Value, ValueType, unify_pair_to};
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify_to(&[*left, *right], ValueType::Boolean) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => Ok((Value::Boolean(a && b), false)),
-            (Value::Int(_), Value::Int(_)) | (Value::Float(_), Value::Float(_)) => {
+    match unify_pair_to(left, right, ValueType::Boolean) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => Ok((Value::Boolean(a && b), false)),
+            (&Value::Int(_), &Value::Int(_)) | (&Value::Float(_), &Value::Float(_)) => {
                 Err(Error::EvalError(EvalError::OpNotSupported {
                     op: Operator::Binary(BinaryOp::AddAssign),
                     operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
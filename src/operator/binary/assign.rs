@@ -1,10 +1,26 @@
-use crate::error::Error;
+use crate::error::{Arity, Error, EvalError};
+use crate::operator::{BinaryOp, Operator};
 use crate::value::Value;
 
 pub fn apply(left: &mut Value, right: &Value) -> Result<(Value, bool), Error> {
+    // Int, BigInt and Rational are one exact number type as far as variables
+    // are concerned, and so are List and Matrix one collection type.
+    if
+        (left.value_type().is_exact() && right.value_type().is_exact()) ||
+        (left.value_type().is_collection() && right.value_type().is_collection())
+    {
+        *left = right.clone();
+        return Ok((right.clone(), false));
+    }
+
     let promoted = right
         .promote(left.value_type())
-        .ok_or(Error::UnexpectedError)?;
+        .ok_or(Error::EvalError(EvalError::TypeMismatch {
+            op: Operator::Binary(BinaryOp::Assign),
+            arity: Arity::Binary,
+            found: Vec::from_iter([left.value_type(), right.value_type()]),
+            expected: Vec::from_iter([left.value_type(), left.value_type()]),
+        }))?;
 
     match (left, promoted) {
         (Value::Boolean(a), Value::Boolean(b)) => {
@@ -19,6 +35,34 @@ pub fn apply(left: &mut Value, right: &Value) -> Result<(Value, bool), Error> {
             *a = b;
             Ok((Value::Float(*a), false))
         }
+        (left, Value::BigInt(b)) => {
+            *left = Value::from_bigint(b);
+            Ok((left.clone(), false))
+        }
+        (left, Value::Rational(b)) => {
+            *left = Value::from_rational(b);
+            Ok((left.clone(), false))
+        }
+        (Value::Complex(a), Value::Complex(b)) => {
+            *a = b;
+            Ok((Value::Complex(*a), false))
+        }
+        (Value::Str(a), Value::Str(b)) => {
+            *a = b;
+            Ok((Value::Str(a.clone()), false))
+        }
+        (Value::List(a), Value::List(b)) => {
+            *a = b;
+            Ok((Value::List(a.clone()), false))
+        }
+        (Value::Matrix(a), Value::Matrix(b)) => {
+            *a = b;
+            Ok((Value::Matrix(a.clone()), false))
+        }
+        (Value::Function(a), Value::Function(b)) => {
+            *a = b;
+            Ok((Value::Function(a.clone()), false))
+        }
         _ => Err(Error::UnexpectedError),
     }
 }
@@ -1,22 +1,33 @@
 use crate::error::{Error, EvalError};
-use crate::operator::{BinaryOp, Operator};
-use crate::value::{Value, unify};
+use crate::operator::{BinaryOp, Operator, broadcast};
+use crate::value::{Value, unify_pair};
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => {
+    if let Some(result) = broadcast::binary(BinaryOp::BitwiseXor, left, right, apply) {
+        return result;
+    }
+
+    match unify_pair(left, right) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => {
                 let ai = if a { 1i64 } else { 0i64 };
                 let bi = if b { 1i64 } else { 0i64 };
                 Ok((Value::Int(ai ^ bi), false))
             }
-            (Value::Int(a), Value::Int(b)) => Ok((Value::Int(a ^ b), false)),
-            (Value::Float(_a), Value::Float(_b)) => {
+            (&Value::Int(a), &Value::Int(b)) => Ok((Value::Int(a ^ b), false)),
+            (Value::BigInt(a), Value::BigInt(b)) => Ok((Value::from_bigint(a ^ b), false)),
+            (&Value::Float(_a), &Value::Float(_b)) => {
                 Err(Error::EvalError(EvalError::OpNotSupported {
                     op: Operator::Binary(BinaryOp::AddAssign),
                     operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
                 }))
             }
+            (Value::Str(_), Value::Str(_))
+            | (Value::Rational(_), Value::Rational(_))
+            | (&Value::Complex(_), &Value::Complex(_)) => Err(Error::EvalError(EvalError::OpNotSupported {
+                op: Operator::Binary(BinaryOp::BitwiseXor),
+                operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+            })),
             _ => Err(Error::UnexpectedError),
         },
         Err(err) => Err(err),
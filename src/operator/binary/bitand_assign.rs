@@ -1,26 +1,39 @@
 use crate::error::{Error, EvalError};
-use crate::operator::{BinaryOp, Operator};
-use crate::value::{Value, unify};
+use crate::operator::{BinaryOp, Operator, bit_and, assign_collection, assign_error};
+use crate::value::{Value, unify_pair};
 
 pub fn apply(left: &mut Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => {
-                left.set_boolean(a && b);
-                Ok((Value::Boolean(a && b), false))
-            }
-            (Value::Int(a), Value::Int(b)) => {
-                left.set_int(a & b);
-                Ok((Value::Int(a & b), false))
-            }
-            (Value::Float(_), Value::Float(_)) => {
-                Err(Error::EvalError(EvalError::OpNotSupported {
-                    op: Operator::Binary(BinaryOp::AddAssign),
+    if let Some(result) = assign_collection(bit_and::apply, left, right) {
+        return result;
+    }
+
+    let result = {
+        let (l, r) = unify_pair(left, right)?;
+        match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => Value::Boolean(a && b),
+            (&Value::Int(a), &Value::Int(b)) => Value::Int(a & b),
+            (Value::BigInt(a), Value::BigInt(b)) => Value::from_bigint(a & b),
+            (Value::Float(_), Value::Float(_))
+            | (Value::Str(_), Value::Str(_))
+            | (Value::Rational(_), Value::Rational(_))
+            | (Value::Complex(_), Value::Complex(_)) => {
+                return Err(Error::EvalError(EvalError::OpNotSupported {
+                    op: Operator::Binary(BinaryOp::BitAndAssign),
                     operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
-                }))
+                }));
             }
-            _ => Err(Error::UnexpectedError),
-        },
-        Err(err) => Err(err),
+            _ => return Err(assign_error(BinaryOp::BitAndAssign, bit_and::apply, left, right)),
+        }
+    };
+
+    // A Boolean variable cannot take the integer result of combining it
+    // with a number.
+    let keeps_type = result.value_type() == left.value_type() ||
+        (result.value_type().is_exact() && left.value_type().is_exact());
+    if !keeps_type {
+        return Err(assign_error(BinaryOp::BitAndAssign, bit_and::apply, left, right));
     }
+
+    *left = result.clone();
+    Ok((result, false))
 }
@@ -1,10 +1,25 @@
 use crate::error::{Error, EvalError};
-use crate::value::{Value, unify};
+use crate::operator::{BinaryOp, Operator, broadcast};
+use crate::bigint::BigInt;
+use crate::rational::Rational;
+use crate::value::{Value, unify_pair};
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => {
+    // Matrices can be divided by a scalar, but not the other way round.
+    if matches!(right, Value::Matrix(_)) {
+        return Err(Error::EvalError(EvalError::OpNotSupported {
+            op: Operator::Binary(BinaryOp::Division),
+            operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+        }));
+    }
+
+    if let Some(result) = broadcast::binary(BinaryOp::Division, left, right, apply) {
+        return result;
+    }
+
+    match unify_pair(left, right) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => {
                 let ai = if a { 1i64 } else { 0i64 };
                 let bi = if b { 1i64 } else { 0i64 };
 
@@ -12,33 +27,74 @@ pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
                     Ok((Value::Int(ai / bi), false))
                 } else {
                     Err(Error::EvalError(EvalError::DivideByZero {
-                        lhs: *left,
-                        rhs: *right,
+                        lhs: left.clone(),
+                        rhs: right.clone(),
                     }))
                 }
             }
-            (Value::Int(a), Value::Int(b)) => {
+            (&Value::Int(a), &Value::Int(b)) => {
                 if b != 0 {
-                    let (v, overflow) = a.overflowing_div(b);
-                    Ok((Value::Int(v), overflow))
+                    // Inexact quotients are kept as fractions.
+                    match a.checked_div(b) {
+                        Some(v) if a.wrapping_rem(b) == 0 => Ok((Value::Int(v), false)),
+                        Some(_) => {
+                            let v = Rational::new(BigInt::from(a), BigInt::from(b)).unwrap();
+                            Ok((Value::Rational(v), false))
+                        }
+                        None => Ok((Value::from_bigint(-&BigInt::from(a)), false)),
+                    }
+                } else {
+                    Err(Error::EvalError(EvalError::DivideByZero {
+                        lhs: left.clone(),
+                        rhs: right.clone(),
+                    }))
+                }
+            }
+            (Value::BigInt(a), Value::BigInt(b)) => {
+                if let Some(v) = Rational::new(a.clone(), b.clone()) {
+                    Ok((Value::from_rational(v), false))
+                } else {
+                    Err(Error::EvalError(EvalError::DivideByZero {
+                        lhs: left.clone(),
+                        rhs: right.clone(),
+                    }))
+                }
+            }
+            (Value::Rational(a), Value::Rational(b)) => {
+                if let Some(v) = a.checked_div(b) {
+                    Ok((Value::from_rational(v), false))
+                } else {
+                    Err(Error::EvalError(EvalError::DivideByZero {
+                        lhs: left.clone(),
+                        rhs: right.clone(),
+                    }))
+                }
+            }
+            (&Value::Complex(a), &Value::Complex(b)) => {
+                if let Some(v) = a.checked_div(b) {
+                    Ok((Value::Complex(v), !v.is_finite()))
                 } else {
                     Err(Error::EvalError(EvalError::DivideByZero {
-                        lhs: *left,
-                        rhs: *right,
+                        lhs: left.clone(),
+                        rhs: right.clone(),
                     }))
                 }
             }
-            (Value::Float(a), Value::Float(b)) => {
+            (&Value::Float(a), &Value::Float(b)) => {
                 if b != 0.0 {
                     let v = a / b;
                     Ok((Value::Float(v), !v.is_finite()))
                 } else {
                     Err(Error::EvalError(EvalError::DivideByZero {
-                        lhs: *left,
-                        rhs: *right,
+                        lhs: left.clone(),
+                        rhs: right.clone(),
                     }))
                 }
             }
+            (Value::Str(_), Value::Str(_)) => Err(Error::EvalError(EvalError::OpNotSupported {
+                op: Operator::Binary(BinaryOp::Division),
+                operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+            })),
             _ => Err(Error::UnexpectedError),
         },
         Err(err) => Err(err),
@@ -0,0 +1,13 @@
+use crate::error::Error;
+use crate::operator::{BinaryOp, broadcast, mul};
+use crate::value::Value;
+
+/// `.*` multiplies matrices and lists element by element, where `*` would
+/// take the matrix product. On scalars it is the same as `*`.
+pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
+    if let Some(result) = broadcast::binary(BinaryOp::ElementMul, left, right, apply) {
+        return result;
+    }
+
+    mul::apply(left, right)
+}
@@ -1,14 +1,39 @@
 use crate::error::Error;
-use crate::value::{Value, unify};
+use crate::value::{Value, unify_pair};
+
+/// Whether two lists have the same length and pairwise equal elements.
+pub fn lists_equal(a: &[Value], b: &[Value]) -> Result<bool, Error> {
+    if a.len() != b.len() {
+        return Ok(false);
+    }
+    for (x, y) in a.iter().zip(b.iter()) {
+        if apply(x, y)?.0 != Value::Boolean(true) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => Ok((Value::Boolean(a == b), false)),
-            (Value::Int(a), Value::Int(b)) => Ok((Value::Boolean(a == b), false)),
-            (Value::Float(a), Value::Float(b)) => {
+    match unify_pair(left, right) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => Ok((Value::Boolean(a == b), false)),
+            (&Value::Int(a), &Value::Int(b)) => Ok((Value::Boolean(a == b), false)),
+            (Value::BigInt(a), Value::BigInt(b)) => Ok((Value::Boolean(a == b), false)),
+            (Value::Rational(a), Value::Rational(b)) => Ok((Value::Boolean(a == b), false)),
+            (&Value::Float(a), &Value::Float(b)) => {
                 Ok((Value::Boolean((a - b).abs() < f64::EPSILON), false))
             }
+            (&Value::Complex(a), &Value::Complex(b)) => {
+                Ok((Value::Boolean((a - b).abs() < f64::EPSILON), false))
+            }
+            (Value::List(a), Value::List(b)) => Ok((Value::Boolean(lists_equal(a, b)?), false)),
+            (Value::Matrix(a), Value::Matrix(b)) => {
+                let same = a.shape() == b.shape() && lists_equal(&a.to_rows(), &b.to_rows())?;
+                Ok((Value::Boolean(same), false))
+            }
+            (Value::Str(a), Value::Str(b)) => Ok((Value::Boolean(a == b), false)),
+            (Value::Function(a), Value::Function(b)) => Ok((Value::Boolean(a == b), false)),
             _ => Err(Error::UnexpectedError),
         },
         Err(err) => Err(err),
@@ -1,22 +1,159 @@
-use crate::error::Error;
-use crate::value::{Value, unify};
+use crate::error::{Error, EvalError};
+use crate::operator::{BinaryOp, Operator, broadcast};
+use crate::bigint::BigInt;
+use crate::complex::Complex;
+use crate::matrix::{Matrix, dimension_mismatch};
+use crate::rational::Rational;
+use crate::value::{Value, unify_pair};
+
+/// Results larger than this many bits are reported as invalid instead of
+/// being computed.
+const MAX_RESULT_BITS: usize = 1 << 20;
+
+/// Real power, continuing into the complex plane for a negative base and a
+/// fractional exponent instead of producing NaN.
+fn float_pow(base: f64, exp: f64) -> (Value, bool) {
+    if base < 0.0 && exp.fract() != 0.0 {
+        let v = Complex::from(base).pow(Complex::from(exp));
+        return (Value::Complex(v), !v.is_finite());
+    }
+
+    let v = base.powf(exp);
+    (Value::Float(v), !v.is_finite())
+}
+
+fn rational_pow(base: &Rational, exp: &BigInt) -> (Value, bool) {
+    let as_float = base.to_f64().powf(exp.to_f64());
+    let bits = base.numer().bits().max(base.denom().bits());
+
+    match exp.to_i64() {
+        Some(e) if bits.saturating_mul(e.unsigned_abs() as usize) <= MAX_RESULT_BITS => {
+            match base.pow(e) {
+                Some(v) => (Value::from_rational(v), false),
+                // Zero to a negative power.
+                None => (Value::Float(as_float), true),
+            }
+        }
+        _ => (Value::Float(as_float), true),
+    }
+}
+
+fn int_pow(base: &BigInt, exp: &BigInt) -> (Value, bool) {
+    if exp.is_negative() {
+        // 1 and -1 are their own inverses.
+        if base.bits() == 1 {
+            return int_pow(base, &-exp);
+        }
+        return rational_pow(&Rational::from(base.clone()), exp);
+    }
+
+    let as_float = base.to_f64().powf(exp.to_f64());
+
+    let small_base = base.bits() <= 1;
+    match exp.to_i64().and_then(|e| u32::try_from(e).ok()) {
+        Some(e) if small_base || base.bits().saturating_mul(e as usize) <= MAX_RESULT_BITS => {
+            (Value::from_bigint(base.pow(e)), false)
+        }
+        // 0, 1 and -1 stay small for any exponent.
+        _ if small_base => {
+            let odd = exp.div_rem(&BigInt::from(2)).is_some_and(|(_, r)| !r.is_zero());
+            let v = if base.is_negative() && !odd { BigInt::from(1) } else { base.clone() };
+            (Value::from_bigint(v), false)
+        }
+        _ => (Value::Float(as_float), true),
+    }
+}
+
+fn too_large(m: &Matrix) -> bool {
+    m.elements().iter().any(|v| match v {
+        Value::BigInt(a) => a.bits() > MAX_RESULT_BITS,
+        Value::Rational(a) => a.numer().bits().max(a.denom().bits()) > MAX_RESULT_BITS,
+        _ => false,
+    })
+}
+
+/// Integer power of a square matrix by repeated squaring. Negative powers
+/// raise the inverse.
+fn matrix_pow(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
+    let (Value::Matrix(m), Value::Int(e)) = (left, right) else {
+        return Err(Error::EvalError(EvalError::OpNotSupported {
+            op: Operator::Binary(BinaryOp::Exponentiation),
+            operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+        }));
+    };
+    if !m.is_square() {
+        return Err(dimension_mismatch("**", &[m.shape()], 0));
+    }
+
+    let mut base = if *e < 0 {
+        m.inverse()?
+            .ok_or(Error::EvalError(EvalError::InvalidOperands {
+                op: Operator::Binary(BinaryOp::Exponentiation),
+                operands: Vec::from_iter([left.clone(), right.clone()]),
+            }))?.0
+    } else {
+        m.clone()
+    };
+
+    let mut acc = Matrix::identity(m.cols());
+    let mut invalid = false;
+    let mut e = e.unsigned_abs();
+    while e > 0 {
+        if e & 1 == 1 {
+            let (v, bad) = acc.product(&base)?;
+            acc = v;
+            invalid |= bad;
+        }
+        e >>= 1;
+        if e > 0 {
+            let (v, bad) = base.product(&base)?;
+            base = v;
+            invalid |= bad;
+        }
+        if too_large(&acc) || too_large(&base) {
+            return Ok((Value::Matrix(acc), true));
+        }
+    }
+    Ok((Value::Matrix(acc), invalid))
+}
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => {
+    if matches!(left, Value::Matrix(_)) || matches!(right, Value::Matrix(_)) {
+        return matrix_pow(left, right);
+    }
+
+    if let Some(result) = broadcast::binary(BinaryOp::Exponentiation, left, right, apply) {
+        return result;
+    }
+
+    match unify_pair(left, right) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => {
                 let ai = if a { 1i64 } else { 0i64 };
                 let bi = if b { 1i64 } else { 0i64 };
                 Ok((Value::Int(ai.pow(bi as u32)), false))
             }
-            (Value::Int(a), Value::Int(b)) => {
-                let (v, overflow) = a.overflowing_pow(b as u32);
-                Ok((Value::Int(v), overflow))
+            (&Value::Int(a), &Value::Int(b)) => match u32::try_from(b).ok().and_then(|e| a.checked_pow(e)) {
+                Some(v) => Ok((Value::Int(v), false)),
+                None => Ok(int_pow(&BigInt::from(a), &BigInt::from(b))),
+            },
+            (Value::BigInt(a), Value::BigInt(b)) => Ok(int_pow(a, b)),
+            (Value::Rational(a), Value::Rational(b)) => {
+                if b.is_integer() {
+                    Ok(rational_pow(a, &b.trunc()))
+                } else {
+                    Ok(float_pow(a.to_f64(), b.to_f64()))
+                }
             }
-            (Value::Float(a), Value::Float(b)) => {
-                let v = a.powf(b);
-                Ok((Value::Float(v), !v.is_finite()))
+            (&Value::Float(a), &Value::Float(b)) => Ok(float_pow(a, b)),
+            (&Value::Complex(a), &Value::Complex(b)) => {
+                let v = a.pow(b);
+                Ok((Value::Complex(v), !v.is_finite()))
             }
+            (Value::Str(_), Value::Str(_)) => Err(Error::EvalError(EvalError::OpNotSupported {
+                op: Operator::Binary(BinaryOp::Exponentiation),
+                operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+            })),
             _ => Err(Error::UnexpectedError),
         },
         Err(err) => Err(err),
@@ -1,14 +1,21 @@
 use crate::error::Error;
-use crate::value::{Value, unify};
+use crate::operator::{BinaryOp, not_ordered};
+use crate::value::{Value, unify_pair};
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => Ok((Value::Boolean(a <= b), false)),
-            (Value::Int(a), Value::Int(b)) => Ok((Value::Boolean(a <= b), false)),
-            (Value::Float(a), Value::Float(b)) => {
+    match unify_pair(left, right) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => Ok((Value::Boolean(a <= b), false)),
+            (&Value::Int(a), &Value::Int(b)) => Ok((Value::Boolean(a <= b), false)),
+            (Value::BigInt(a), Value::BigInt(b)) => Ok((Value::Boolean(a <= b), false)),
+            (Value::Rational(a), Value::Rational(b)) => Ok((Value::Boolean(a <= b), false)),
+            (&Value::Float(a), &Value::Float(b)) => {
                 Ok((Value::Boolean(a < b || (a - b).abs() < f64::EPSILON), false))
             }
+            (Value::Str(a), Value::Str(b)) => Ok((Value::Boolean(a <= b), false)),
+            | (&Value::Complex(_), &Value::Complex(_))
+            | (Value::List(_), Value::List(_))
+            | (Value::Matrix(_), Value::Matrix(_)) => Err(not_ordered(BinaryOp::LessEqual, left, right)),
             _ => Err(Error::UnexpectedError),
         },
         Err(err) => Err(err),
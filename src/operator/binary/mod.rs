@@ -1,3 +1,7 @@
+use crate::error::{Arity, Error, EvalError};
+use crate::operator::{BinaryOp, Operator};
+use crate::value::Value;
+
 pub mod add;
 pub mod add_assign;
 pub mod and;
@@ -11,6 +15,7 @@ pub mod bitor_assign;
 pub mod bitxor_assign;
 pub mod div;
 pub mod div_assign;
+pub mod elem_mul;
 pub mod equal;
 pub mod exp;
 pub mod gequal;
@@ -26,3 +31,51 @@ pub mod or;
 pub mod or_assign;
 pub mod sub;
 pub mod sub_assign;
+
+type BinaryFn = fn(&Value, &Value) -> Result<(Value, bool), Error>;
+
+/// Error for `left op= right` when the operands cannot be combined in
+/// place. If the binary operator `binary` fails as well, as `"a" += 1`
+/// does, its error is reported, so that both forms fail alike. Otherwise
+/// the result would change the type of the variable, as `x += 2i` would
+/// for an Int `x`, which is a `TypeMismatch` like that of `=`.
+pub fn assign_error(
+    op: BinaryOp,
+    binary: BinaryFn,
+    left: &Value,
+    right: &Value
+) -> Error {
+    match binary(left, right) {
+        Err(err) => err,
+        Ok((result, _)) =>
+            Error::EvalError(EvalError::TypeMismatch {
+                op: Operator::Binary(op),
+                arity: Arity::Binary,
+                found: Vec::from_iter([left.value_type(), result.value_type()]),
+                expected: Vec::from_iter([left.value_type(), left.value_type()]),
+            }),
+    }
+}
+
+/// Result of `left op= right` when either operand is a list or a matrix,
+/// which the binary operator `binary` rebuilds as a whole before it
+/// replaces `left`. `None` for other operands.
+pub fn assign_collection(
+    binary: BinaryFn,
+    left: &mut Value,
+    right: &Value
+) -> Option<Result<(Value, bool), Error>> {
+    if !left.value_type().is_collection() && !right.value_type().is_collection() {
+        return None;
+    }
+    Some(binary(left, right).inspect(|result| *left = result.0.clone()))
+}
+
+/// `OpNotSupported` for the comparison `op` between complex numbers, lists
+/// or matrices, which are not ordered.
+pub fn not_ordered(op: BinaryOp, left: &Value, right: &Value) -> Error {
+    Error::EvalError(EvalError::OpNotSupported {
+        op: Operator::Binary(op),
+        operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+    })
+}
@@ -1,24 +1,58 @@
 use crate::error::{Error, EvalError};
-use crate::operator::{BinaryOp, Operator};
-use crate::value::{Value, unify};
+use crate::operator::{BinaryOp, Operator, broadcast};
+use crate::value::{Value, unify_pair};
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(_a), Value::Boolean(_b)) => {
+    if let Some(result) = broadcast::binary(BinaryOp::Modulo, left, right, apply) {
+        return result;
+    }
+
+    match unify_pair(left, right) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(_a), &Value::Boolean(_b)) => {
                 Err(Error::EvalError(EvalError::OpNotSupported {
                     op: Operator::Binary(BinaryOp::AddAssign),
                     operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
                 }))
             }
-            (Value::Int(a), Value::Int(b)) => {
-                let (v, overflow) = a.overflowing_rem(b);
-                Ok((Value::Int(v), overflow))
+            (&Value::Int(a), &Value::Int(b)) => {
+                if b != 0 {
+                    Ok((Value::Int(a.wrapping_rem(b)), false))
+                } else {
+                    Err(Error::EvalError(EvalError::DivideByZero {
+                        lhs: left.clone(),
+                        rhs: right.clone(),
+                    }))
+                }
+            }
+            (Value::BigInt(a), Value::BigInt(b)) => {
+                if let Some((_, r)) = a.div_rem(b) {
+                    Ok((Value::from_bigint(r), false))
+                } else {
+                    Err(Error::EvalError(EvalError::DivideByZero {
+                        lhs: left.clone(),
+                        rhs: right.clone(),
+                    }))
+                }
+            }
+            (Value::Rational(a), Value::Rational(b)) => {
+                if let Some(v) = a.checked_rem(b) {
+                    Ok((Value::from_rational(v), false))
+                } else {
+                    Err(Error::EvalError(EvalError::DivideByZero {
+                        lhs: left.clone(),
+                        rhs: right.clone(),
+                    }))
+                }
             }
-            (Value::Float(a), Value::Float(b)) => {
+            (&Value::Float(a), &Value::Float(b)) => {
                 let v = a % b;
                 Ok((Value::Float(v), !v.is_finite()))
             }
+            (Value::Str(_), Value::Str(_)) | (&Value::Complex(_), &Value::Complex(_)) => Err(Error::EvalError(EvalError::OpNotSupported {
+                op: Operator::Binary(BinaryOp::Modulo),
+                operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+            })),
             _ => Err(Error::UnexpectedError),
         },
         Err(err) => Err(err),
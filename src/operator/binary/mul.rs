@@ -1,22 +1,73 @@
-use crate::error::Error;
-use crate::value::{Value, unify};
+use crate::error::{Error, EvalError};
+use crate::operator::{BinaryOp, Operator, broadcast};
+use crate::bigint::BigInt;
+use crate::matrix::dimension_mismatch;
+use crate::value::{Value, unify_pair};
+
+/// Matrix product of two matrices, or of a matrix and a list taken as a
+/// column vector on the right or a row vector on the left. Returns `None`
+/// for other operands.
+fn product(left: &Value, right: &Value) -> Option<Result<(Value, bool), Error>> {
+    let result = match (left, right) {
+        (Value::Matrix(a), Value::Matrix(b)) => {
+            if a.cols() != b.rows() {
+                return Some(Err(dimension_mismatch("*", &[a.shape(), b.shape()], 1)));
+            }
+            a.product(b).map(|(m, invalid)| (Value::Matrix(m), invalid))
+        }
+        (Value::Matrix(a), Value::List(v)) => {
+            if a.cols() != v.len() {
+                return Some(Err(dimension_mismatch("*", &[a.shape(), (v.len(), 1)], 1)));
+            }
+            a.apply_to(v).map(|(items, invalid)| (Value::List(items), invalid))
+        }
+        (Value::List(v), Value::Matrix(a)) => {
+            if v.len() != a.rows() {
+                return Some(Err(dimension_mismatch("*", &[(1, v.len()), a.shape()], 1)));
+            }
+            a.transpose().apply_to(v).map(|(items, invalid)| (Value::List(items), invalid))
+        }
+        _ => {
+            return None;
+        }
+    };
+    Some(result)
+}
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => {
+    if let Some(result) = product(left, right) {
+        return result;
+    }
+
+    if let Some(result) = broadcast::binary(BinaryOp::Multiplication, left, right, apply) {
+        return result;
+    }
+
+    match unify_pair(left, right) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => {
                 let ai = if a { 1i64 } else { 0i64 };
                 let bi = if b { 1i64 } else { 0i64 };
                 Ok((Value::Int(ai * bi), false))
             }
-            (Value::Int(a), Value::Int(b)) => {
-                let (v, overflow) = a.overflowing_mul(b);
-                Ok((Value::Int(v), overflow))
+            (&Value::Int(a), &Value::Int(b)) => match a.checked_mul(b) {
+                Some(v) => Ok((Value::Int(v), false)),
+                None => Ok((Value::from_bigint(&BigInt::from(a) * &BigInt::from(b)), false)),
+            },
+            (Value::BigInt(a), Value::BigInt(b)) => Ok((Value::from_bigint(a * b), false)),
+            (Value::Rational(a), Value::Rational(b)) => Ok((Value::from_rational(a * b), false)),
+            (&Value::Complex(a), &Value::Complex(b)) => {
+                let v = a * b;
+                Ok((Value::Complex(v), !v.is_finite()))
             }
-            (Value::Float(a), Value::Float(b)) => {
+            (&Value::Float(a), &Value::Float(b)) => {
                 let v = a * b;
                 Ok((Value::Float(v), !v.is_finite()))
             }
+            (Value::Str(_), Value::Str(_)) => Err(Error::EvalError(EvalError::OpNotSupported {
+                op: Operator::Binary(BinaryOp::Multiplication),
+                operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+            })),
             _ => Err(Error::UnexpectedError),
         },
         Err(err) => Err(err),
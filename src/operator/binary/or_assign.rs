@@ -1,25 +1,25 @@
 use crate::error::{Error, EvalError};
-use crate::operator::{BinaryOp, Operator};
+use crate::operator::{BinaryOp, Operator, assign_error, or};
 use crate::value::Value;
 
 pub fn apply(left: &mut Value, right: &Value) -> Result<(Value, bool), Error> {
     let promoted = right
         .promote(left.value_type())
-        .ok_or(Error::UnexpectedError)?;
+        .ok_or_else(|| assign_error(BinaryOp::OrAssign, or::apply, left, right))?;
 
-    match (*left, promoted) {
-        (Value::Boolean(a), Value::Boolean(b)) => {
+    match (&*left, &promoted) {
+        (&Value::Boolean(a), &Value::Boolean(b)) => {
             left.set_boolean(a && b);
             Ok((Value::Boolean(a && b), false))
         }
         (Value::Int(_), Value::Int(_)) => Err(Error::EvalError(EvalError::OpNotSupported {
-            op: Operator::Binary(BinaryOp::AddAssign),
+            op: Operator::Binary(BinaryOp::OrAssign),
             operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
         })),
         (Value::Float(_), Value::Float(_)) => Err(Error::EvalError(EvalError::OpNotSupported {
-            op: Operator::Binary(BinaryOp::AddAssign),
+            op: Operator::Binary(BinaryOp::OrAssign),
             operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
         })),
-        _ => Err(Error::UnexpectedError),
+        _ => Err(assign_error(BinaryOp::OrAssign, or::apply, left, right)),
     }
 }
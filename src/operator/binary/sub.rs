@@ -1,22 +1,38 @@
-use crate::error::Error;
-use crate::value::{Value, unify};
+use crate::error::{Error, EvalError};
+use crate::operator::{BinaryOp, Operator, broadcast};
+use crate::bigint::BigInt;
+use crate::value::{Value, unify_pair};
 
 pub fn apply(left: &Value, right: &Value) -> Result<(Value, bool), Error> {
-    match unify(&[*left, *right]) {
-        Ok(v) => match (v[0], v[1]) {
-            (Value::Boolean(a), Value::Boolean(b)) => {
+    if let Some(result) = broadcast::binary(BinaryOp::Subtraction, left, right, apply) {
+        return result;
+    }
+
+    match unify_pair(left, right) {
+        Ok((l, r)) => match (l.as_ref(), r.as_ref()) {
+            (&Value::Boolean(a), &Value::Boolean(b)) => {
                 let ai = if a { 1i64 } else { 0i64 };
                 let bi = if b { 1i64 } else { 0i64 };
                 Ok((Value::Int(ai - bi), false))
             }
-            (Value::Int(a), Value::Int(b)) => {
-                let (v, overflow) = a.overflowing_sub(b);
-                Ok((Value::Int(v), overflow))
+            (&Value::Int(a), &Value::Int(b)) => match a.checked_sub(b) {
+                Some(v) => Ok((Value::Int(v), false)),
+                None => Ok((Value::from_bigint(&BigInt::from(a) - &BigInt::from(b)), false)),
+            },
+            (Value::BigInt(a), Value::BigInt(b)) => Ok((Value::from_bigint(a - b), false)),
+            (Value::Rational(a), Value::Rational(b)) => Ok((Value::from_rational(a - b), false)),
+            (&Value::Complex(a), &Value::Complex(b)) => {
+                let v = a - b;
+                Ok((Value::Complex(v), !v.is_finite()))
             }
-            (Value::Float(a), Value::Float(b)) => {
+            (&Value::Float(a), &Value::Float(b)) => {
                 let v = a - b;
                 Ok((Value::Float(v), !v.is_finite()))
             }
+            (Value::Str(_), Value::Str(_)) => Err(Error::EvalError(EvalError::OpNotSupported {
+                op: Operator::Binary(BinaryOp::Subtraction),
+                operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+            })),
             _ => Err(Error::UnexpectedError),
         },
         Err(err) => Err(err),
@@ -1,27 +1,48 @@
+use std::borrow::Cow;
+
 use crate::error::{Error, EvalError};
-use crate::operator::{BinaryOp, Operator};
-use crate::value::Value;
+use crate::operator::{BinaryOp, Operator, sub, assign_collection, assign_error};
+use crate::value::{Value, promote_ref};
 
 pub fn apply(left: &mut Value, right: &Value) -> Result<(Value, bool), Error> {
-    let promoted = right
-        .promote(left.value_type())
-        .ok_or(Error::UnexpectedError)?;
+    if let Some(result) = assign_collection(sub::apply, left, right) {
+        return result;
+    }
 
-    match (*left, promoted) {
+    let promoted = if left.value_type().is_exact() && right.value_type().is_exact() {
+        Cow::Borrowed(right)
+    } else {
+        promote_ref(right, left.value_type()).ok_or_else(|| assign_error(BinaryOp::SubAssign, sub::apply, left, right))?
+    };
+
+    match (&*left, promoted.as_ref()) {
         (Value::Boolean(_), Value::Boolean(_)) => {
             Err(Error::EvalError(EvalError::OpNotSupported {
-                op: Operator::Binary(BinaryOp::AddAssign),
+                op: Operator::Binary(BinaryOp::SubAssign),
                 operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
             }))
         }
-        (Value::Int(a), Value::Int(b)) => {
-            left.set_int(a - b);
-            Ok((Value::Int(a - b), false))
+        (
+            Value::Int(_) | Value::BigInt(_) | Value::Rational(_),
+            b @ (Value::Int(_) | Value::BigInt(_) | Value::Rational(_)),
+        ) => {
+            let result = sub::apply(left, b)?;
+            *left = result.0.clone();
+            Ok(result)
         }
-        (Value::Float(a), Value::Float(b)) => {
+        (&Value::Float(a), &Value::Float(b)) => {
             left.set_float(a - b);
             Ok((Value::Float(a - b), false))
         }
-        _ => Err(Error::UnexpectedError),
+        (Value::Complex(_), b @ Value::Complex(_)) => {
+            let result = sub::apply(left, b)?;
+            *left = result.0.clone();
+            Ok(result)
+        }
+        (Value::Str(_), Value::Str(_)) => Err(Error::EvalError(EvalError::OpNotSupported {
+            op: Operator::Binary(BinaryOp::SubAssign),
+            operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+        })),
+        _ => Err(assign_error(BinaryOp::SubAssign, sub::apply, left, right)),
     }
 }
@@ -0,0 +1,128 @@
+use crate::error::{Error, EvalError};
+use crate::matrix::dimension_mismatch;
+use crate::operator::{BinaryOp, Operator};
+use crate::value::Value;
+
+type BinaryFn = fn(&Value, &Value) -> Result<(Value, bool), Error>;
+type UnaryFn = fn(&Value) -> Result<(Value, bool), Error>;
+
+/// Applies `f` element-wise when either operand is a list or a matrix: two
+/// lists or two matrices of the same shape are paired element by element
+/// and a scalar is paired with every element. Returns `None` when neither
+/// operand is a list or a matrix.
+pub fn binary(
+    op: BinaryOp,
+    left: &Value,
+    right: &Value,
+    f: BinaryFn
+) -> Option<Result<(Value, bool), Error>> {
+    if let Some(result) = matrix(op, left, right, f) {
+        return Some(result);
+    }
+
+    let pairs: Vec<(&Value, &Value)> = match (left, right) {
+        (Value::List(a), Value::List(b)) => {
+            if a.len() != b.len() {
+                return Some(
+                    Err(
+                        Error::EvalError(EvalError::LengthMismatch {
+                            op: Operator::Binary(op),
+                            left: a.len(),
+                            right: b.len(),
+                        })
+                    )
+                );
+            }
+            a.iter().zip(b.iter()).collect()
+        }
+        (Value::List(a), b) =>
+            a
+                .iter()
+                .map(|a| (a, b))
+                .collect(),
+        (a, Value::List(b)) =>
+            b
+                .iter()
+                .map(|b| (a, b))
+                .collect(),
+        _ => {
+            return None;
+        }
+    };
+
+    let mut items = Vec::with_capacity(pairs.len());
+    let mut invalid = false;
+    for (a, b) in pairs {
+        match f(a, b) {
+            Ok((v, bad)) => {
+                items.push(v);
+                invalid |= bad;
+            }
+            Err(err) => {
+                return Some(Err(err));
+            }
+        }
+    }
+
+    Some(Ok((Value::List(items), invalid)))
+}
+
+fn matrix(
+    op: BinaryOp,
+    left: &Value,
+    right: &Value,
+    f: BinaryFn
+) -> Option<Result<(Value, bool), Error>> {
+    let result = match (left, right) {
+        (Value::Matrix(a), Value::Matrix(b)) => {
+            if a.shape() != b.shape() {
+                let symbol = Operator::Binary(op).symbol();
+                return Some(Err(dimension_mismatch(symbol, &[a.shape(), b.shape()], 1)));
+            }
+            a.zip_with(b, f)
+        }
+        (Value::Matrix(_), Value::List(_)) | (Value::List(_), Value::Matrix(_)) => {
+            return Some(
+                Err(
+                    Error::EvalError(EvalError::OpNotSupported {
+                        op: Operator::Binary(op),
+                        operand_types: Vec::from_iter([left.value_type(), right.value_type()]),
+                    })
+                )
+            );
+        }
+        (Value::Matrix(a), b) => a.map_scalar(b, false, f),
+        (a, Value::Matrix(b)) => b.map_scalar(a, true, f),
+        _ => {
+            return None;
+        }
+    };
+
+    Some(result.map(|(m, invalid)| (Value::Matrix(m), invalid)))
+}
+
+/// Applies `f` to every element of a list or matrix operand, or returns
+/// `None` if the operand is neither.
+pub fn unary(value: &Value, f: UnaryFn) -> Option<Result<(Value, bool), Error>> {
+    if let Value::Matrix(m) = value {
+        return Some(m.map(f).map(|(m, invalid)| (Value::Matrix(m), invalid)));
+    }
+
+    let items = value.as_list()?;
+
+    let mut res = Vec::with_capacity(items.len());
+    let mut invalid = false;
+    for v in items {
+        match f(v) {
+            Ok((v, bad)) => {
+                res.push(v);
+                invalid |= bad;
+            }
+            Err(err) => {
+                return Some(Err(err));
+            }
+        }
+    }
+
+    Some(Ok((Value::List(res), invalid)))
+}
@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod operator;
 pub use operator::*;
 
@@ -6,3 +7,5 @@ pub use binary::*;
 
 pub mod unary;
 pub use unary::*;
+
+pub mod broadcast;
@@ -10,6 +10,7 @@ pub enum BinaryOp {
     Addition, // +
     Subtraction, // -
     Multiplication, // *
+    ElementMul, // .*
     Division, // /
     Modulo, // %
     Exponentiation, // **
@@ -49,7 +50,12 @@ pub enum TernaryOp {
 pub enum GroupingOp {
     LeftParen, // (
     RightParen, // )
+    LeftBracket, // [
+    RightBracket, // ]
+    LeftBrace, // {
+    RightBrace, // }
     Comma, // ,
+    Semicolon, // ;
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -70,6 +76,7 @@ impl Operator {
             Operator::Binary(BinaryOp::Addition) => "+",
             Operator::Binary(BinaryOp::Subtraction) => "-",
             Operator::Binary(BinaryOp::Multiplication) => "*",
+            Operator::Binary(BinaryOp::ElementMul) => ".*",
             Operator::Binary(BinaryOp::Division) => "/",
             Operator::Binary(BinaryOp::Modulo) => "%",
             Operator::Binary(BinaryOp::Exponentiation) => "**",
@@ -103,7 +110,12 @@ impl Operator {
 
             Operator::Grouping(GroupingOp::LeftParen) => "(",
             Operator::Grouping(GroupingOp::RightParen) => ")",
+            Operator::Grouping(GroupingOp::LeftBracket) => "[",
+            Operator::Grouping(GroupingOp::RightBracket) => "]",
+            Operator::Grouping(GroupingOp::LeftBrace) => "{",
+            Operator::Grouping(GroupingOp::RightBrace) => "}",
             Operator::Grouping(GroupingOp::Comma) => ",",
+            Operator::Grouping(GroupingOp::Semicolon) => ";",
         }
     }
 }
@@ -1,14 +1,27 @@
 use crate::error::{Error, EvalError};
-use crate::operator::{BinaryOp, Operator};
+use crate::operator::{Operator, UnaryOp, broadcast};
 use crate::value::Value;
 
 pub fn apply(value: &Value) -> Result<(Value, bool), Error> {
+    if let Some(result) = broadcast::unary(value, apply) {
+        return result;
+    }
+
     match value {
         Value::Boolean(a) => Ok((Value::Boolean(!a), false)),
         Value::Int(a) => Ok((Value::Int(!a), false)),
-        Value::Float(_) => Err(Error::EvalError(EvalError::OpNotSupported {
-            op: Operator::Binary(BinaryOp::AddAssign),
-            operand_types: Vec::from_iter([value.value_type()]),
-        })),
+        Value::BigInt(a) => Ok((Value::from_bigint(!a), false)),
+        | Value::Float(_)
+        | Value::Rational(_)
+        | Value::Complex(_)
+        | Value::Str(_)
+        | Value::List(_)
+        | Value::Matrix(_)
+        | Value::Function(_) => {
+            Err(Error::EvalError(EvalError::OpNotSupported {
+                op: Operator::Unary(UnaryOp::BitwiseNot),
+                operand_types: Vec::from_iter([value.value_type()]),
+            }))
+        }
     }
 }
@@ -1,14 +1,27 @@
+use crate::bigint::BigInt;
 use crate::error::{Error, EvalError};
-use crate::operator::{BinaryOp, Operator};
+use crate::operator::{Operator, UnaryOp, broadcast};
 use crate::value::Value;
 
 pub fn apply(value: &Value) -> Result<(Value, bool), Error> {
+    if let Some(result) = broadcast::unary(value, apply) {
+        return result;
+    }
+
     match value {
-        Value::Boolean(_) => Err(Error::EvalError(EvalError::OpNotSupported {
-            op: Operator::Binary(BinaryOp::AddAssign),
-            operand_types: Vec::from_iter([value.value_type()]),
-        })),
-        Value::Int(a) => Ok((Value::Int(-a), false)),
+        Value::Int(a) => match a.checked_neg() {
+            Some(v) => Ok((Value::Int(v), false)),
+            None => Ok((Value::from_bigint(-&BigInt::from(*a)), false)),
+        },
+        Value::BigInt(a) => Ok((Value::from_bigint(-a), false)),
+        Value::Rational(a) => Ok((Value::Rational(-a), false)),
         Value::Float(a) => Ok((Value::Float(-a), false)),
+        Value::Complex(a) => Ok((Value::Complex(-*a), false)),
+        Value::Boolean(_) | Value::Str(_) | Value::List(_) | Value::Matrix(_) | Value::Function(_) => {
+            Err(Error::EvalError(EvalError::OpNotSupported {
+                op: Operator::Unary(UnaryOp::Negation),
+                operand_types: Vec::from_iter([value.value_type()]),
+            }))
+        }
     }
 }
@@ -1,21 +1,33 @@
 use crate::{
     debug::{ print_debug_expr, print_debug_tokens },
-    error::Error,
+    error::{ Error, ParseError },
     lexer::lex_string,
     operator::{ BinaryOp, GroupingOp, Operator, TernaryOp, UnaryOp },
     span::{ Span, Spanned },
-    token::Token,
+    token::{ Keyword, Token },
     value::Value,
 };
 
 struct Cursor<'a> {
     src: &'a [Spanned<Token>],
     i: usize,
+    /// Loops enclosing the current position within the innermost function
+    /// body. `break` and `continue` are only valid when this is non-zero.
+    loops: usize,
+    /// Whether a condition or range of `if`, `while` or `for` is being
+    /// parsed, where a macro token starts the body instead of multiplying.
+    header: bool,
+    /// Errors recovered from so far, in the order they were found.
+    errors: Vec<Spanned<Error>>,
+    /// For each token, whether it starts a statement of a block. See
+    /// `statement_breaks`.
+    breaks: Vec<bool>,
 }
 
 impl<'a> Cursor<'a> {
-    fn new(src: &'a [Spanned<Token>]) -> Self {
-        Cursor { src, i: 0 }
+    fn new(text: &str, src: &'a [Spanned<Token>]) -> Self {
+        let breaks = statement_breaks(text, src);
+        Cursor { src, i: 0, loops: 0, header: false, errors: Vec::new(), breaks }
     }
 
     fn is_eof(&self) -> bool {
@@ -32,6 +44,11 @@ impl<'a> Cursor<'a> {
         Some(t)
     }
 
+    /// Whether a line break before the next token ends the statement.
+    fn at_break(&self) -> bool {
+        self.breaks.get(self.i).copied().unwrap_or(false)
+    }
+
     fn advance(&mut self, n: usize) -> bool {
         self.i += n;
         self.is_eof()
@@ -45,6 +62,115 @@ impl<'a> Cursor<'a> {
             false
         }
     }
+
+    /// Span of the next token, or just past the last one at the end of the
+    /// input.
+    fn here(&self) -> Span {
+        match self.src.get(self.i) {
+            Some(t) => t.span,
+            None => Span::single(self.src.last().map_or(0, |t| t.span.end)),
+        }
+    }
+
+    /// `err` at the next token.
+    fn error(&self, err: ParseError) -> Spanned<Error> {
+        Spanned { span: self.here(), data: Error::ParseError(err) }
+    }
+
+    fn next_token(&mut self) -> Result<Spanned<Token>, Spanned<Error>> {
+        self.next().ok_or_else(|| self.error(ParseError::UnexpectedEnd))
+    }
+
+    /// Consumes `target` and returns its span, or fails if another token
+    /// comes next.
+    fn require(&mut self, target: Token) -> Result<Span, Spanned<Error>> {
+        match self.peek() {
+            Some(t) if t.data == target => {
+                self.advance(1);
+                Ok(t.span)
+            }
+            found => {
+                let found = found.map(|t| t.data);
+                Err(self.error(ParseError::Expected { expected: target, found }))
+            }
+        }
+    }
+
+    /// End of the last consumed token.
+    fn last_end(&self) -> usize {
+        self.src[..self.i.min(self.src.len())].last().map_or(0, |t| t.span.end)
+    }
+
+    /// Consumes the delimiter `close` matching the one at `open` and
+    /// returns where it ends. At the end of the input or another closing
+    /// delimiter the error is recorded and the delimiter taken as closed, so
+    /// that the enclosing expression goes on.
+    fn close(&mut self, close: Token, open: Span) -> Result<usize, Spanned<Error>> {
+        match self.peek() {
+            Some(t) if t.data == close => {
+                self.advance(1);
+                Ok(t.span.end)
+            }
+            Some(t) if !is_closing(&t.data) => Err(self.error(ParseError::Unclosed { close, open })),
+            _ => {
+                let err = self.error(ParseError::Unclosed { close, open });
+                self.errors.push(err);
+                Ok(self.last_end())
+            }
+        }
+    }
+
+    /// Records `err` and skips to the next `,` or closing delimiter outside
+    /// of brackets, or to the end of the input. Returns an error node over
+    /// the input from `start`.
+    fn recover(&mut self, start: usize, err: Spanned<Error>) -> Spanned<Expr> {
+        self.errors.push(err);
+
+        let mut depth = 0usize;
+        while let Some(t) = self.peek() {
+            if depth == 0 && self.at_break() {
+                break;
+            }
+            match t.data {
+                | Token::Operator(Operator::Grouping(GroupingOp::LeftParen))
+                | Token::Operator(Operator::Grouping(GroupingOp::LeftBracket))
+                | Token::Operator(Operator::Grouping(GroupingOp::LeftBrace)) => {
+                    depth += 1;
+                }
+                _ if is_closing(&t.data) => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                Token::Operator(
+                    Operator::Grouping(GroupingOp::Comma | GroupingOp::Semicolon),
+                ) if depth == 0 => {
+                    break;
+                }
+                _ => {}
+            }
+            self.advance(1);
+        }
+
+        Spanned {
+            span: Span { start, end: self.last_end().max(start + 1) },
+            data: Expr::Error,
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, Spanned<Error>> {
+        match self.peek() {
+            Some(Spanned { data: Token::Identifier(name), .. }) => {
+                self.advance(1);
+                Ok(name)
+            }
+            found => {
+                let found = found.map(|t| t.data);
+                Err(self.error(ParseError::ExpectedIdentifier { found }))
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -64,6 +190,12 @@ pub enum Expr {
     Comma {
         exprs: Vec<Spanned<Expr>>,
     },
+    /// Statements of a `{ }` body separated by `;`, `,` or line breaks. Run
+    /// in order, the first error ends the block, and the last one's value is
+    /// its value.
+    Block {
+        exprs: Vec<Spanned<Expr>>,
+    },
     Ternary {
         cond: Box<Spanned<Expr>>,
         statement1: Box<Spanned<Expr>>,
@@ -73,30 +205,303 @@ pub enum Expr {
         func: Box<Spanned<Expr>>,
         args: Vec<Spanned<Expr>>,
     },
+    List {
+        items: Vec<Spanned<Expr>>,
+    },
+    Index {
+        target: Box<Spanned<Expr>>,
+        index: Box<Spanned<Expr>>,
+    },
+    /// `name(params) = body`.
+    FunctionDef {
+        name: String,
+        params: Vec<String>,
+        body: Box<Spanned<Expr>>,
+    },
+    /// `x => body`, `(x, y) => body` or `fn(x, y) body`.
+    Lambda {
+        params: Vec<String>,
+        body: Box<Spanned<Expr>>,
+    },
+    /// `let a = x, b = y in body`, with the bindings in a scope of their own.
+    Let {
+        bindings: Vec<(String, Spanned<Expr>)>,
+        body: Box<Spanned<Expr>>,
+    },
+    /// `if cond { a } else { b }`. Without `else`, a false condition yields
+    /// `false`.
+    If {
+        cond: Box<Spanned<Expr>>,
+        then_branch: Box<Spanned<Expr>>,
+        else_branch: Option<Box<Spanned<Expr>>>,
+    },
+    /// `while cond { body }`. Yields the value of the last iteration that ran
+    /// to the end, or `false` if there was none.
+    While {
+        cond: Box<Spanned<Expr>>,
+        body: Box<Spanned<Expr>>,
+    },
+    /// `for var in start..end { body }` over the integers from `start` up to
+    /// but excluding `end`, with `var` in a scope of its own. Yields like
+    /// `while`.
+    For {
+        var: String,
+        start: Box<Spanned<Expr>>,
+        end: Box<Spanned<Expr>>,
+        body: Box<Spanned<Expr>>,
+    },
+    /// Leaves the innermost loop.
+    Break,
+    /// Skips to the next iteration of the innermost loop.
+    Continue,
+    /// Stands in for a part of the input that failed to parse.
+    Error,
+    /// `target[start:end]`, either bound may be omitted.
+    Slice {
+        target: Box<Spanned<Expr>>,
+        start: Option<Box<Spanned<Expr>>>,
+        end: Option<Box<Spanned<Expr>>>,
+    },
+}
+
+/// Binding power for lambda and `let` bodies. Like the right side of `=`, a
+/// body extends over assignments and ternaries but stops at a comma.
+const BODY_BP: u32 = 2;
+
+type ParseResult = Result<Spanned<Expr>, Spanned<Error>>;
+
+fn error_at(span: Span, err: ParseError) -> Spanned<Error> {
+    Spanned { span, data: Error::ParseError(err) }
+}
+
+fn lambda(start: usize, params: Vec<String>, cursor: &mut Cursor) -> ParseResult {
+    let loops = std::mem::replace(&mut cursor.loops, 0);
+    let body = parse_expression(cursor, BODY_BP);
+    cursor.loops = loops;
+
+    Ok(Spanned {
+        span: Span { start, end: body.span.end },
+        data: Expr::Lambda { params, body: Box::new(body) },
+    })
 }
 
-fn nud(cursor: &mut Cursor, t: &Spanned<Token>) -> Option<Spanned<Expr>> {
+/// Condition or range bound of `if`, `while` or `for`, ending where the body
+/// starts.
+fn header(cursor: &mut Cursor) -> Spanned<Expr> {
+    let header = std::mem::replace(&mut cursor.header, true);
+    let e = parse_expression(cursor, 0);
+    cursor.header = header;
+    e
+}
+
+/// `{ body }` of `if` and loops, with the end of the closing brace. `{name}`
+/// lexes as a macro but is a block holding the identifier `name` here.
+fn block(cursor: &mut Cursor) -> Result<(Spanned<Expr>, usize), Spanned<Error>> {
+    let lbrace = Token::Operator(Operator::Grouping(GroupingOp::LeftBrace));
+    let t = match cursor.peek() {
+        Some(t) if t.data == lbrace || matches!(t.data, Token::Macro(_)) => {
+            cursor.advance(1);
+            t
+        }
+        found => {
+            let found = found.map(|t| t.data);
+            return Err(cursor.error(ParseError::Expected { expected: lbrace, found }));
+        }
+    };
+
+    match t.data {
+        Token::Macro(name) => {
+            let body = Spanned {
+                span: Span::from(t.span.start + 1, name.len()),
+                data: Expr::Identifier(name),
+            };
+            Ok((body, t.span.end))
+        }
+        _ => {
+            let header = std::mem::replace(&mut cursor.header, false);
+            let body = statements(cursor);
+            cursor.header = header;
+
+            let end = cursor.close(Token::Operator(Operator::Grouping(GroupingOp::RightBrace)), t.span)?;
+            Ok((body, end))
+        }
+    }
+}
+
+/// Statements of a block up to its closing brace. A single statement is
+/// the body itself, several form an `Expr::Block`. A separator may follow
+/// the last one.
+fn statements(cursor: &mut Cursor) -> Spanned<Expr> {
+    const COMMA_BP: u32 = 1;
+    let rbrace = Token::Operator(Operator::Grouping(GroupingOp::RightBrace));
+
+    let mut exprs = vec![parse_expression(cursor, COMMA_BP)];
+    loop {
+        let separated =
+            cursor.expect(&Token::Operator(Operator::Grouping(GroupingOp::Semicolon))) ||
+            cursor.expect(&Token::Operator(Operator::Grouping(GroupingOp::Comma))) ||
+            cursor.at_break();
+        if !separated || cursor.peek().is_none_or(|t| t.data == rbrace) {
+            break;
+        }
+        exprs.push(parse_expression(cursor, COMMA_BP));
+    }
+
+    if exprs.len() == 1 {
+        return exprs.pop().unwrap();
+    }
+    Spanned {
+        span: Span { start: exprs[0].span.start, end: exprs.last().unwrap().span.end },
+        data: Expr::Block { exprs },
+    }
+}
+
+/// Body of a loop, in which `break` and `continue` are allowed.
+fn loop_body(cursor: &mut Cursor) -> Result<(Spanned<Expr>, usize), Spanned<Error>> {
+    cursor.loops += 1;
+    let body = block(cursor);
+    cursor.loops -= 1;
+    body
+}
+
+fn nud(cursor: &mut Cursor, t: &Spanned<Token>) -> ParseResult {
     match &t.data {
         Token::Value(v) =>
-            Some(Spanned {
+            Ok(Spanned {
                 span: t.span,
-                data: Expr::Value(*v),
+                data: Expr::Value(v.clone()),
             }),
         Token::Identifier(s) =>
-            Some(Spanned {
+            Ok(Spanned {
                 span: t.span,
                 data: Expr::Identifier(s.clone()),
             }),
 
         Token::Macro(s) =>
-            Some(Spanned {
+            Ok(Spanned {
                 span: t.span,
                 data: Expr::Macro(s.clone()),
             }),
 
+        Token::Keyword(Keyword::Fn) => {
+            let open = cursor.require(Token::Operator(Operator::Grouping(GroupingOp::LeftParen)))?;
+            let rparen = Token::Operator(Operator::Grouping(GroupingOp::RightParen));
+            let mut params = Vec::new();
+
+            if !cursor.expect(&rparen) {
+                loop {
+                    let span = cursor.here();
+                    params.push(Spanned { span, data: cursor.identifier()? });
+
+                    if cursor.expect(&Token::Operator(Operator::Grouping(GroupingOp::Comma))) {
+                        continue;
+                    }
+                    cursor.close(rparen, open)?;
+                    break;
+                }
+            }
+
+            lambda(t.span.start, parameters(params)?, cursor)
+        }
+
+        Token::Keyword(Keyword::Let) => {
+            const COMMA_BP: u32 = 1;
+            let mut bindings = Vec::new();
+
+            loop {
+                let name = cursor.identifier()?;
+                cursor.require(Token::Operator(Operator::Binary(BinaryOp::Assign)))?;
+                bindings.push((name, parse_expression(cursor, COMMA_BP)));
+
+                if cursor.expect(&Token::Operator(Operator::Grouping(GroupingOp::Comma))) {
+                    continue;
+                }
+                break;
+            }
+
+            cursor.require(Token::Keyword(Keyword::In))?;
+            let body = parse_expression(cursor, BODY_BP);
+
+            Ok(Spanned {
+                span: Span { start: t.span.start, end: body.span.end },
+                data: Expr::Let { bindings, body: Box::new(body) },
+            })
+        }
+
+        Token::Keyword(Keyword::If) => {
+            let cond = header(cursor);
+            let (then_branch, mut end) = block(cursor)?;
+
+            let else_branch = if cursor.expect(&Token::Keyword(Keyword::Else)) {
+                let e = if cursor.peek().is_some_and(|t| t.data == Token::Keyword(Keyword::If)) {
+                    let t = cursor.next_token()?;
+                    nud(cursor, &t)?
+                } else {
+                    let (e, block_end) = block(cursor)?;
+                    end = block_end;
+                    e
+                };
+                end = end.max(e.span.end);
+                Some(Box::new(e))
+            } else {
+                None
+            };
+
+            Ok(Spanned {
+                span: Span { start: t.span.start, end },
+                data: Expr::If {
+                    cond: Box::new(cond),
+                    then_branch: Box::new(then_branch),
+                    else_branch,
+                },
+            })
+        }
+
+        Token::Keyword(Keyword::While) => {
+            let cond = header(cursor);
+            let (body, end) = loop_body(cursor)?;
+
+            Ok(Spanned {
+                span: Span { start: t.span.start, end },
+                data: Expr::While { cond: Box::new(cond), body: Box::new(body) },
+            })
+        }
+
+        Token::Keyword(Keyword::For) => {
+            let var = cursor.identifier()?;
+            cursor.require(Token::Keyword(Keyword::In))?;
+            let start = header(cursor);
+            cursor.require(Token::Range)?;
+            let range_end = header(cursor);
+            let (body, end) = loop_body(cursor)?;
+
+            Ok(Spanned {
+                span: Span { start: t.span.start, end },
+                data: Expr::For {
+                    var,
+                    start: Box::new(start),
+                    end: Box::new(range_end),
+                    body: Box::new(body),
+                },
+            })
+        }
+
+        Token::Keyword(keyword @ (Keyword::Break | Keyword::Continue)) => {
+            if cursor.loops == 0 {
+                return Err(
+                    error_at(t.span, ParseError::LoopControlOutsideLoop { keyword: *keyword })
+                );
+            }
+
+            Ok(Spanned {
+                span: t.span,
+                data: if *keyword == Keyword::Break { Expr::Break } else { Expr::Continue },
+            })
+        }
+
         Token::Operator(Operator::Binary(BinaryOp::Subtraction)) => {
-            let rhs = parse_expression(cursor, 70)?;
-            Some(Spanned {
+            let rhs = parse_expression(cursor, 70);
+            Ok(Spanned {
                 span: t.span,
                 data: Expr::Unary {
                     op: Operator::Unary(UnaryOp::Negation),
@@ -106,8 +511,8 @@ fn nud(cursor: &mut Cursor, t: &Spanned<Token>) -> Option<Spanned<Expr>> {
         }
 
         Token::Operator(Operator::Unary(UnaryOp::BitwiseNot)) => {
-            let rhs = parse_expression(cursor, 70)?;
-            Some(Spanned {
+            let rhs = parse_expression(cursor, 70);
+            Ok(Spanned {
                 span: t.span,
                 data: Expr::Unary {
                     op: Operator::Unary(UnaryOp::BitwiseNot),
@@ -117,20 +522,48 @@ fn nud(cursor: &mut Cursor, t: &Spanned<Token>) -> Option<Spanned<Expr>> {
         }
 
         Token::Operator(Operator::Grouping(GroupingOp::LeftParen)) => {
-            let e: Spanned<Expr> = parse_expression(cursor, 0)?;
+            let rparen = Token::Operator(Operator::Grouping(GroupingOp::RightParen));
 
-            if cursor.expect(&Token::Operator(Operator::Grouping(GroupingOp::RightParen))) {
-                Some(e)
-            } else {
-                None
+            // `() => body`
+            if cursor.expect(&rparen) {
+                cursor.require(Token::Arrow)?;
+                return lambda(t.span.start, Vec::new(), cursor);
+            }
+
+            let e: Spanned<Expr> = parse_expression(cursor, 0);
+            cursor.close(rparen, t.span)?;
+            Ok(e)
+        }
+
+        Token::Operator(Operator::Grouping(GroupingOp::LeftBracket)) => {
+            const COMMA_BP: u32 = 1;
+            let rbracket = Token::Operator(Operator::Grouping(GroupingOp::RightBracket));
+            let mut items = Vec::new();
+
+            if cursor.peek().is_some_and(|s| s.data != rbracket) {
+                loop {
+                    items.push(parse_expression(cursor, COMMA_BP + 1));
+
+                    if cursor.expect(&Token::Operator(Operator::Grouping(GroupingOp::Comma))) {
+                        continue;
+                    }
+                    break;
+                }
             }
+
+            let end = cursor.close(rbracket, t.span)?;
+
+            Ok(Spanned {
+                span: Span { start: t.span.start, end },
+                data: Expr::List { items },
+            })
         }
 
-        _ => None,
+        token => Err(error_at(t.span, ParseError::UnexpectedToken { token: token.clone() })),
     }
 }
 
-fn led(cursor: &mut Cursor, left: &Spanned<Expr>, t: &Spanned<Token>) -> Option<Spanned<Expr>> {
+fn led(cursor: &mut Cursor, left: &Spanned<Expr>, t: &Spanned<Token>) -> ParseResult {
     match t.data {
         Token::Operator(Operator::Binary(op)) => {
             let lbp = Token::Operator(Operator::Binary(op)).lbp();
@@ -150,9 +583,24 @@ fn led(cursor: &mut Cursor, left: &Spanned<Expr>, t: &Spanned<Token>) -> Option<
                 _ => lbp,
             };
 
-            let right = parse_expression(cursor, rbp)?;
+            // A function body is not inside the loops around its definition.
+            let head = if op == BinaryOp::Assign { function_head(left) } else { None };
+            let loops = cursor.loops;
+            if head.is_some() {
+                cursor.loops = 0;
+            }
+            let right = parse_expression(cursor, rbp);
+            cursor.loops = loops;
+
+            if let Some((name, params)) = head {
+                let params = parameters(params)?;
+                return Ok(Spanned {
+                    span: Span { start: left.span.start, end: right.span.end },
+                    data: Expr::FunctionDef { name, params, body: Box::new(right) },
+                });
+            }
 
-            Some(Spanned {
+            Ok(Spanned {
                 span: Span { start: left.span.start, end: right.span.end },
                 data: Expr::Binary {
                     op: Operator::Binary(op),
@@ -174,7 +622,7 @@ fn led(cursor: &mut Cursor, left: &Spanned<Expr>, t: &Spanned<Token>) -> Option<
                     })
             {
                 loop {
-                    args.push(parse_expression(cursor, COMMA_BP + 1)?);
+                    args.push(parse_expression(cursor, COMMA_BP + 1));
 
                     if
                         cursor
@@ -190,27 +638,47 @@ fn led(cursor: &mut Cursor, left: &Spanned<Expr>, t: &Spanned<Token>) -> Option<
                 }
             }
 
-            if
-                cursor
-                    .peek()
-                    .is_some_and(|s| {
-                        s.data == Token::Operator(Operator::Grouping(GroupingOp::RightParen))
-                    })
-            {
-                let rparen = cursor.peek().unwrap(); // before advance
-                let end = rparen.span.end;
-                cursor.advance(1);
+            let end = cursor.close(Token::Operator(Operator::Grouping(GroupingOp::RightParen)), t.span)?;
 
-                Some(Spanned {
-                    span: Span { start: left.span.start, end },
-                    data: Expr::Call {
-                        func: Box::new(left.clone()),
-                        args,
-                    },
-                })
-            } else {
+            Ok(Spanned {
+                span: Span { start: left.span.start, end },
+                data: Expr::Call {
+                    func: Box::new(left.clone()),
+                    args,
+                },
+            })
+        }
+
+        Token::Operator(Operator::Grouping(GroupingOp::LeftBracket)) => {
+            const COMMA_BP: u32 = 1;
+            let colon = Token::Operator(Operator::TernaryOp(TernaryOp::TernaryElse));
+            let rbracket = Token::Operator(Operator::Grouping(GroupingOp::RightBracket));
+
+            let start = if cursor.peek().is_some_and(|s| s.data == colon) {
                 None
-            }
+            } else {
+                Some(Box::new(parse_expression(cursor, COMMA_BP)))
+            };
+
+            let data = if cursor.expect(&colon) {
+                let end = if cursor.peek().is_some_and(|s| s.data == rbracket) {
+                    None
+                } else {
+                    Some(Box::new(parse_expression(cursor, COMMA_BP)))
+                };
+                Expr::Slice { target: Box::new(left.clone()), start, end }
+            } else {
+                // Without a start bound the next token is the `:`.
+                let index = start.ok_or_else(|| cursor.error(ParseError::UnexpectedEnd))?;
+                Expr::Index { target: Box::new(left.clone()), index }
+            };
+
+            let end = cursor.close(rbracket, t.span)?;
+
+            Ok(Spanned {
+                span: Span { start: left.span.start, end },
+                data,
+            })
         }
 
         Token::Operator(Operator::Grouping(GroupingOp::Comma)) => {
@@ -221,7 +689,7 @@ fn led(cursor: &mut Cursor, left: &Spanned<Expr>, t: &Spanned<Token>) -> Option<
                 _ => vec![left.clone()],
             };
 
-            exprs.push(parse_expression(cursor, COMMA_BP)?);
+            exprs.push(parse_expression(cursor, COMMA_BP));
 
             while
                 cursor
@@ -231,28 +699,39 @@ fn led(cursor: &mut Cursor, left: &Spanned<Expr>, t: &Spanned<Token>) -> Option<
                     })
             {
                 cursor.advance(1);
-                exprs.push(parse_expression(cursor, COMMA_BP)?);
+                exprs.push(parse_expression(cursor, COMMA_BP));
             }
 
             let end = exprs.last().unwrap().span.end;
 
-            Some(Spanned {
+            Ok(Spanned {
                 span: Span { start: exprs[0].span.start, end },
                 data: Expr::Comma { exprs },
             })
         }
 
+        Token::Arrow => {
+            let params = match &left.data {
+                Expr::Identifier(p) => Some(vec![Spanned { span: left.span, data: p.clone() }]),
+                Expr::Comma { exprs } => identifiers(exprs),
+                _ => None,
+            };
+            let params = params.ok_or_else(|| error_at(left.span, ParseError::InvalidParameters))?;
+            let params = parameters(params)?;
+            lambda(left.span.start, params, cursor)
+        }
+
         Token::Operator(Operator::TernaryOp(TernaryOp::TernaryCond)) => {
             let lbp = Token::Operator(Operator::TernaryOp(TernaryOp::TernaryCond)).lbp();
             let rbp: u32 = lbp - 1;
 
-            let statement1 = parse_expression(cursor, 0)?;
+            let statement1 = parse_expression(cursor, 0);
             if !cursor.expect(&Token::Operator(Operator::TernaryOp(TernaryOp::TernaryElse))) {
-                return None;
+                return Err(cursor.error(ParseError::MissingTernaryElse { question: t.span }));
             }
-            let statement2 = parse_expression(cursor, rbp)?;
+            let statement2 = parse_expression(cursor, rbp);
 
-            Some(Spanned {
+            Ok(Spanned {
                 span: Span {
                     start: left.span.start,
                     end: statement2.span.end,
@@ -265,37 +744,157 @@ fn led(cursor: &mut Cursor, left: &Spanned<Expr>, t: &Spanned<Token>) -> Option<
             })
         }
 
-        _ => None,
+        ref token => Err(error_at(t.span, ParseError::UnexpectedToken { token: token.clone() })),
+    }
+}
+
+/// Name and parameters of `name(params)` on the left of `=`, if every
+/// argument is a plain identifier.
+fn function_head(left: &Spanned<Expr>) -> Option<(String, Vec<Spanned<String>>)> {
+    let Expr::Call { func, args } = &left.data else {
+        return None;
+    };
+    let Expr::Identifier(name) = &func.data else {
+        return None;
+    };
+
+    Some((name.clone(), identifiers(args)?))
+}
+
+/// Names of `exprs` if every one of them is a plain identifier.
+fn identifiers(exprs: &[Spanned<Expr>]) -> Option<Vec<Spanned<String>>> {
+    exprs
+        .iter()
+        .map(|e| {
+            match &e.data {
+                Expr::Identifier(p) => Some(Spanned { span: e.span, data: p.clone() }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Names of the parameters `params`, or an error at the first one that
+/// repeats an earlier name.
+fn parameters(params: Vec<Spanned<String>>) -> Result<Vec<String>, Spanned<Error>> {
+    let mut names: Vec<String> = Vec::with_capacity(params.len());
+    for p in params {
+        if names.contains(&p.data) {
+            return Err(error_at(p.span, ParseError::DuplicateParameter { name: p.data }));
+        }
+        names.push(p.data);
     }
+    Ok(names)
 }
 
 fn starts_expression(t: &Spanned<Token>) -> bool {
-    match t.data {
+    matches!(
+        t.data,
         | Token::Value(_)
         | Token::Identifier(_)
         | Token::Macro(_)
         | Token::Operator(Operator::Unary(UnaryOp::Not))
         | Token::Operator(Operator::Grouping(GroupingOp::LeftParen))
         | Token::Operator(Operator::Unary(UnaryOp::Negation))
-        | Token::Operator(Operator::Unary(UnaryOp::BitwiseNot)) => true,
-        _ => false,
-    }
+        | Token::Operator(Operator::Unary(UnaryOp::BitwiseNot))
+    )
 }
 
-fn parse_expression(cursor: &mut Cursor, min_bp: u32) -> Option<Spanned<Expr>> {
+fn is_closing(t: &Token) -> bool {
+    matches!(
+        t,
+        | Token::Operator(Operator::Grouping(GroupingOp::RightParen))
+        | Token::Operator(Operator::Grouping(GroupingOp::RightBracket))
+        | Token::Operator(Operator::Grouping(GroupingOp::RightBrace))
+    )
+}
+
+/// Tokens that end the operand of an enclosing construct, such as `in`
+/// after a `let` binding. An operand missing before one of them is an error
+/// that leaves the token for the construct.
+fn ends_operand(t: &Token) -> bool {
+    is_closing(t) ||
+        matches!(
+            t,
+            | Token::Operator(Operator::Grouping(GroupingOp::Comma | GroupingOp::Semicolon))
+            | Token::Operator(Operator::TernaryOp(TernaryOp::TernaryElse))
+            | Token::Keyword(Keyword::In | Keyword::Else)
+            | Token::Range
+        )
+}
+
+/// For each token, whether it is the first on its line directly inside
+/// braces, where a line break separates statements. Within parentheses and
+/// brackets, and outside any braces, it does not.
+fn statement_breaks(s: &str, tokens: &[Spanned<Token>]) -> Vec<bool> {
+    // Whether each open delimiter is a brace.
+    let mut open: Vec<bool> = Vec::new();
+    let mut prev_end = 0;
+
+    tokens
+        .iter()
+        .map(|t| {
+            let new_line = s.get(prev_end..t.span.start).is_some_and(|gap| gap.contains('\n'));
+            let starts_statement = new_line && open.last() == Some(&true);
+            match t.data {
+                | Token::Operator(Operator::Grouping(GroupingOp::LeftParen))
+                | Token::Operator(Operator::Grouping(GroupingOp::LeftBracket)) => {
+                    open.push(false);
+                }
+                Token::Operator(Operator::Grouping(GroupingOp::LeftBrace)) => {
+                    open.push(true);
+                }
+                _ if is_closing(&t.data) => {
+                    open.pop();
+                }
+                _ => {}
+            }
+            prev_end = t.span.end;
+            starts_statement
+        })
+        .collect()
+}
+
+/// Parses an expression whose operators bind tighter than `min_bp`. Errors
+/// are recorded in `cursor`, and the broken part of the expression is an
+/// `Expr::Error` node.
+fn parse_expression(cursor: &mut Cursor, min_bp: u32) -> Spanned<Expr> {
     const IMPLICIT_MUL_LBP: u32 = 65;
 
-    let t = cursor.next()?;
+    let t = match cursor.peek() {
+        Some(t) if !ends_operand(&t.data) => {
+            cursor.advance(1);
+            t
+        }
+        found => {
+            let err = match found {
+                Some(t) => error_at(t.span, ParseError::UnexpectedToken { token: t.data }),
+                None => cursor.error(ParseError::UnexpectedEnd),
+            };
+            let span = err.span;
+            cursor.errors.push(err);
+            return Spanned { span, data: Expr::Error };
+        }
+    };
 
-    let mut left = nud(cursor, &t)?;
+    let mut left = match nud(cursor, &t) {
+        Ok(left) => left,
+        Err(err) => cursor.recover(t.span.start, err),
+    };
 
     while let Some(t) = cursor.peek() {
+        if cursor.at_break() {
+            break;
+        }
+        // An identifier or lambda followed by `(` is a call, not a product,
+        // and a macro token after a header is the body of `if` or a loop.
         if
             starts_expression(&t) &&
-            !matches!(left.data, Expr::Identifier(_)) &&
+            !matches!(left.data, Expr::Identifier(_) | Expr::Lambda { .. }) &&
+            !(cursor.header && matches!(t.data, Token::Macro(_))) &&
             IMPLICIT_MUL_LBP > min_bp
         {
-            let rhs = parse_expression(cursor, IMPLICIT_MUL_LBP)?;
+            let rhs = parse_expression(cursor, IMPLICIT_MUL_LBP);
             left = Spanned {
                 span: Span {
                     start: left.span.start,
@@ -314,15 +913,23 @@ fn parse_expression(cursor: &mut Cursor, min_bp: u32) -> Option<Spanned<Expr>> {
             break;
         }
         cursor.advance(1);
-        left = led(cursor, &left, &t)?;
+        left = match led(cursor, &left, &t) {
+            Ok(e) => e,
+            Err(err) => cursor.recover(left.span.start, err),
+        };
     }
 
-    Some(left)
+    left
 }
 
 pub fn recompute_expr_span(expr: &mut Spanned<Expr>) {
     let new_span = match &mut expr.data {
-        Expr::Value(_) | Expr::Identifier(_) | Expr::Macro(_) => expr.span,
+        | Expr::Value(_)
+        | Expr::Identifier(_)
+        | Expr::Macro(_)
+        | Expr::Break
+        | Expr::Continue
+        | Expr::Error => expr.span,
 
         Expr::Unary { rhs, .. } => {
             recompute_expr_span(rhs);
@@ -342,7 +949,7 @@ pub fn recompute_expr_span(expr: &mut Spanned<Expr>) {
 
             Span::merge(&Span::merge(&cond.span, &statement1.span), &statement2.span)
         }
-        Expr::Comma { exprs } => {
+        Expr::Comma { exprs } | Expr::Block { exprs } => {
             let mut span = expr.span;
 
             for e in exprs {
@@ -364,33 +971,129 @@ pub fn recompute_expr_span(expr: &mut Spanned<Expr>) {
 
             span
         }
+
+        Expr::List { items } => {
+            let mut span = expr.span;
+
+            for item in items {
+                recompute_expr_span(item);
+                span = Span::merge(&span, &item.span);
+            }
+
+            span
+        }
+
+        Expr::Index { target, index } => {
+            recompute_expr_span(target);
+            recompute_expr_span(index);
+            Span::merge(&expr.span, &target.span)
+        }
+
+        Expr::Slice { target, start, end } => {
+            recompute_expr_span(target);
+            for bound in [start, end].into_iter().flatten() {
+                recompute_expr_span(bound);
+            }
+            Span::merge(&expr.span, &target.span)
+        }
+
+        Expr::FunctionDef { body, .. } | Expr::Lambda { body, .. } => {
+            recompute_expr_span(body);
+            Span::merge(&expr.span, &body.span)
+        }
+
+        Expr::Let { bindings, body } => {
+            for (_, value) in bindings {
+                recompute_expr_span(value);
+            }
+            recompute_expr_span(body);
+            Span::merge(&expr.span, &body.span)
+        }
+
+        Expr::If { cond, then_branch, else_branch } => {
+            recompute_expr_span(cond);
+            recompute_expr_span(then_branch);
+            let mut span = Span::merge(&expr.span, &then_branch.span);
+            if let Some(e) = else_branch {
+                recompute_expr_span(e);
+                span = Span::merge(&span, &e.span);
+            }
+            span
+        }
+
+        Expr::While { cond, body } => {
+            recompute_expr_span(cond);
+            recompute_expr_span(body);
+            Span::merge(&expr.span, &body.span)
+        }
+
+        Expr::For { start, end, body, .. } => {
+            recompute_expr_span(start);
+            recompute_expr_span(end);
+            recompute_expr_span(body);
+            Span::merge(&expr.span, &body.span)
+        }
     };
 
     expr.span = new_span;
 }
 
-pub fn parse_string(s: &str, debug: bool) -> Result<Box<Spanned<Expr>>, Spanned<Error>> {
-    let tokens = lex_string(s)?;
-    let mut cursor = Cursor::new(&tokens);
+/// Parses `s` as far as possible. Every syntax error is returned in the
+/// order of the input, together with the tree in which `Expr::Error` nodes
+/// stand in for the parts that failed. Lexing stops at its first error, in
+/// which case the whole input is an error node.
+pub fn parse_recovering(s: &str, debug: bool) -> (Box<Spanned<Expr>>, Vec<Spanned<Error>>) {
+    let whole = Span { start: 0, end: s.len() };
+    let tokens = match lex_string(s) {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            return (Box::new(Spanned { span: whole, data: Expr::Error }), vec![err]);
+        }
+    };
 
     if debug {
         print_debug_tokens(&tokens);
     }
 
-    let expr = parse_expression(&mut cursor, 0);
+    if tokens.is_empty() {
+        let err = error_at(whole, ParseError::EmptyInput);
+        return (Box::new(Spanned { span: whole, data: Expr::Error }), vec![err]);
+    }
+
+    let mut cursor = Cursor::new(s, &tokens);
+    let mut e = parse_expression(&mut cursor, 0);
 
-    match expr {
-        Some(mut e) => {
-            recompute_expr_span(&mut e);
-            if debug {
-                print_debug_expr(e.clone(), 0);
-            }
-            Ok(Box::from(e))
+    // Tokens left over are reported, and the rest of the input is parsed to
+    // find any errors in it.
+    while let Some(t) = cursor.peek() {
+        cursor.errors.push(error_at(t.span, ParseError::TrailingInput { token: t.data }));
+        cursor.advance(1);
+        if !cursor.is_eof() {
+            parse_expression(&mut cursor, 0);
         }
-        None =>
-            Err(Spanned {
-                span: Span { start: 0, end: 0 },
-                data: Error::UnexpectedError,
-            }),
+    }
+
+    recompute_expr_span(&mut e);
+    if debug {
+        print_debug_expr(e.clone(), 0);
+    }
+
+    // Errors cascading from one at the same position add nothing, except
+    // for unclosed delimiters, which each point back to their opener.
+    let mut errors = cursor.errors;
+    errors.sort_by_key(|e| e.span.start);
+    errors.dedup_by(|e, prev| {
+        e.span.start == prev.span.start &&
+            !matches!(e.data, Error::ParseError(ParseError::Unclosed { .. }))
+    });
+    (Box::from(e), errors)
+}
+
+/// Parses `s`, failing with the first syntax error.
+pub fn parse_string(s: &str, debug: bool) -> Result<Box<Spanned<Expr>>, Spanned<Error>> {
+    let (expr, errors) = parse_recovering(s, debug);
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(expr),
     }
 }
@@ -0,0 +1,296 @@
+use std::{ cmp::Ordering, fmt, ops };
+
+use crate::bigint::BigInt;
+
+/// Exact fraction of two `BigInt`s.
+///
+/// Always kept in lowest terms with a positive denominator, so equal values
+/// have equal representations. The parts are boxed to keep `Value` small.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Rational(Box<Parts>);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Parts {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Rational {
+    /// `num / den` in lowest terms, or `None` if `den` is zero.
+    pub fn new(num: BigInt, den: BigInt) -> Option<Rational> {
+        if den.is_zero() {
+            return None;
+        }
+
+        let g = num.gcd(&den);
+        let (mut num, mut den) = (num.div_rem(&g)?.0, den.div_rem(&g)?.0);
+        if den.is_negative() {
+            num = -&num;
+            den = -&den;
+        }
+        Some(Rational(Box::new(Parts { num, den })))
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.0.num
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        &self.0.den
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.0.den == BigInt::from(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.num.is_zero()
+    }
+
+    /// Integer part, rounding towards zero.
+    pub fn trunc(&self) -> BigInt {
+        self.0.num.div_rem(&self.0.den).unwrap().0
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // Scale both parts down so that huge fractions do not turn into
+        // `inf / inf`.
+        let shift = self.0.num.bits().max(self.0.den.bits()).saturating_sub(1000);
+        if shift == 0 {
+            return self.0.num.to_f64() / self.0.den.to_f64();
+        }
+
+        let scale = BigInt::from(2).pow(shift as u32);
+        let num = self.0.num.div_rem(&scale).unwrap().0;
+        let den = self.0.den.div_rem(&scale).unwrap().0;
+        num.to_f64() / den.to_f64()
+    }
+
+    /// Exact value of a finite float.
+    pub fn from_f64(f: f64) -> Option<Rational> {
+        if !f.is_finite() {
+            return None;
+        }
+        if f == 0.0 {
+            return Some(Rational::from(BigInt::zero()));
+        }
+
+        let bits = f.abs().to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as i64;
+        let (mant, exp) = if biased == 0 {
+            (bits & ((1u64 << 52) - 1), -1074)
+        } else {
+            ((bits & ((1u64 << 52) - 1)) | (1u64 << 52), biased - 1075)
+        };
+
+        let mant = BigInt::from(if f < 0.0 { -(mant as i64) } else { mant as i64 });
+        let scale = BigInt::from(2).pow(exp.unsigned_abs() as u32);
+        if exp >= 0 {
+            Some(Rational::from(&mant * &scale))
+        } else {
+            Rational::new(mant, scale)
+        }
+    }
+
+    /// Closest fraction whose denominator is at most `max_den`, found from
+    /// the continued fraction expansion. `max_den` must be positive.
+    pub fn limit_denominator(&self, max_den: &BigInt) -> Rational {
+        if self.0.den <= *max_den {
+            return self.clone();
+        }
+
+        let one = BigInt::from(1);
+        let (mut p0, mut q0, mut p1, mut q1) = (BigInt::zero(), one.clone(), one, BigInt::zero());
+        let (mut n, mut d) = (self.0.num.abs(), self.0.den.clone());
+
+        loop {
+            let (a, r) = n.div_rem(&d).unwrap();
+            let q2 = &q0 + &(&a * &q1);
+            if q2 > *max_den {
+                break;
+            }
+            let p2 = &p0 + &(&a * &p1);
+            (p0, q0, p1, q1) = (p1, q1, p2, q2);
+            (n, d) = (d, r);
+        }
+
+        let k = (max_den - &q0).div_rem(&q1).unwrap().0;
+        let lower = Rational::new(&p0 + &(&k * &p1), &q0 + &(&k * &q1)).unwrap();
+        let upper = Rational::new(p1, q1).unwrap();
+
+        let target = self.abs();
+        let best = if (&upper - &target).abs() <= (&lower - &target).abs() { upper } else { lower };
+        if self.0.num.is_negative() { -&best } else { best }
+    }
+
+    pub fn abs(&self) -> Rational {
+        Rational(Box::new(Parts { num: self.0.num.abs(), den: self.0.den.clone() }))
+    }
+
+    /// `self / rhs`, or `None` if `rhs` is zero.
+    pub fn checked_div(&self, rhs: &Rational) -> Option<Rational> {
+        Rational::new(&self.0.num * &rhs.0.den, &self.0.den * &rhs.0.num)
+    }
+
+    /// Remainder of truncating division, with the sign of `self`.
+    pub fn checked_rem(&self, rhs: &Rational) -> Option<Rational> {
+        let q = self.checked_div(rhs)?.trunc();
+        Some(self - &(rhs * &Rational::from(q)))
+    }
+
+    /// Integer power. Negative exponents invert, so `None` means zero was
+    /// raised to a negative power.
+    pub fn pow(&self, exp: i64) -> Option<Rational> {
+        let e = u32::try_from(exp.unsigned_abs()).ok()?;
+        let (num, den) = (self.0.num.pow(e), self.0.den.pow(e));
+        if exp < 0 { Rational::new(den, num) } else { Rational::new(num, den) }
+    }
+}
+
+impl From<BigInt> for Rational {
+    fn from(v: BigInt) -> Rational {
+        Rational(Box::new(Parts { num: v, den: BigInt::from(1) }))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> Ordering {
+        (&self.0.num * &other.0.den).cmp(&(&other.0.num * &self.0.den))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl ops::Neg for &Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational(Box::new(Parts { num: -&self.0.num, den: self.0.den.clone() }))
+    }
+}
+
+impl ops::Add for &Rational {
+    type Output = Rational;
+
+    fn add(self, rhs: &Rational) -> Rational {
+        Rational::new(
+            &(&self.0.num * &rhs.0.den) + &(&rhs.0.num * &self.0.den),
+            &self.0.den * &rhs.0.den
+        ).unwrap()
+    }
+}
+
+impl ops::Sub for &Rational {
+    type Output = Rational;
+
+    fn sub(self, rhs: &Rational) -> Rational {
+        self + &-rhs
+    }
+}
+
+impl ops::Mul for &Rational {
+    type Output = Rational;
+
+    fn mul(self, rhs: &Rational) -> Rational {
+        Rational::new(&self.0.num * &rhs.0.num, &self.0.den * &rhs.0.den).unwrap()
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.0.num)
+        } else {
+            write!(f, "{}/{}", self.0.num, self.0.den)
+        }
+    }
+}
+
+impl fmt::Debug for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ operator::{ add, div, modulo, sub }, value::Value };
+
+    fn ratio(num: i64, den: i64) -> Rational {
+        Rational::new(BigInt::from(num), BigInt::from(den)).unwrap()
+    }
+
+    #[test]
+    fn kept_in_lowest_terms() {
+        let r = ratio(6, -4);
+        assert_eq!((r.numer(), r.denom()), (&BigInt::from(-3), &BigInt::from(2)));
+        assert_eq!(ratio(2, 4), ratio(-1, -2));
+        assert_eq!(ratio(0, -5).denom(), &BigInt::from(1));
+        assert_eq!(Rational::new(BigInt::from(1), BigInt::zero()), None);
+
+        assert_eq!(&ratio(1, 6) + &ratio(1, 3), ratio(1, 2));
+        assert_eq!(&ratio(2, 3) * &ratio(3, 4), ratio(1, 2));
+        assert!((&ratio(1, 3) - &ratio(1, 3)).is_zero());
+    }
+
+    #[test]
+    fn rem_truncates_with_the_sign_of_the_dividend() {
+        assert_eq!(ratio(-7, 2).trunc(), BigInt::from(-3));
+        assert_eq!(ratio(7, 2).checked_rem(&ratio(1, 1)), Some(ratio(1, 2)));
+        assert_eq!(ratio(-7, 2).checked_rem(&ratio(1, 1)), Some(ratio(-1, 2)));
+        assert_eq!(ratio(7, 2).checked_rem(&ratio(-1, 1)), Some(ratio(1, 2)));
+        assert_eq!(ratio(7, 2).checked_rem(&ratio(0, 1)), None);
+        assert_eq!(ratio(1, 2).checked_div(&ratio(0, 1)), None);
+    }
+
+    #[test]
+    fn pow_inverts_for_negative_exponents() {
+        assert_eq!(ratio(2, 3).pow(-2), Some(ratio(9, 4)));
+        assert_eq!(ratio(-2, 3).pow(3), Some(ratio(-8, 27)));
+        assert_eq!(ratio(0, 1).pow(-1), None);
+    }
+
+    #[test]
+    fn float_conversions() {
+        assert_eq!(Rational::from_f64(0.1).unwrap().to_string(), "3602879701896397/36028797018963968");
+        assert_eq!(Rational::from_f64(-0.75), Some(ratio(-3, 4)));
+        assert_eq!(Rational::from_f64(f64::INFINITY), None);
+
+        let pi = Rational::from_f64(std::f64::consts::PI).unwrap();
+        assert_eq!(pi.limit_denominator(&BigInt::from(1000)), ratio(355, 113));
+        assert_eq!((-&pi).limit_denominator(&BigInt::from(1000)), ratio(-355, 113));
+
+        // Parts too large for a float still give the quotient.
+        let huge = BigInt::from(10).pow(400);
+        let r = Rational::new(&huge + &BigInt::from(1), huge).unwrap();
+        assert_eq!(r.to_f64(), 1.0);
+    }
+
+    #[test]
+    fn results_return_to_int() {
+        assert_eq!(Value::from_rational(ratio(4, 2)), Value::Int(2));
+        assert_eq!(Value::from_rational(ratio(1, 2)), Value::Rational(ratio(1, 2)));
+        let big = BigInt::from(2).pow(64);
+        assert_eq!(Value::from_rational(Rational::from(big.clone())), Value::BigInt(big));
+
+        let half = Value::Rational(ratio(1, 2));
+        assert_eq!(add::apply(&half, &half).unwrap().0, Value::Int(1));
+        assert_eq!(modulo::apply(&Value::Rational(ratio(7, 2)), &half).unwrap().0, Value::Int(0));
+        assert_eq!(div::apply(&Value::Int(6), &Value::Int(4)).unwrap().0, Value::Rational(ratio(3, 2)));
+
+        let max = Value::Int(i64::MAX);
+        let over = add::apply(&max, &Value::Int(1)).unwrap().0;
+        assert!(matches!(over, Value::BigInt(_)));
+        assert_eq!(sub::apply(&over, &Value::Int(1)).unwrap().0, max);
+        assert_eq!(
+            div::apply(&Value::Int(i64::MIN), &Value::Int(-1)).unwrap().0,
+            Value::BigInt(-&BigInt::from(i64::MIN))
+        );
+        assert_eq!(modulo::apply(&Value::Int(i64::MIN), &Value::Int(-1)).unwrap().0, Value::Int(0));
+    }
+}
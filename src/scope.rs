@@ -0,0 +1,403 @@
+use std::{ collections::HashMap, fmt, sync::atomic::{ AtomicU64, Ordering } };
+
+use crate::{
+    error::{ Error, EvalError, NameKind },
+    value::Value,
+};
+
+/// What introduced a scope. Reported by `NameNotFound` for the scopes that
+/// were searched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeKind {
+    Global,
+    /// `let name = value in body`.
+    Let,
+    /// Loop variable of `for var in start..end`.
+    For,
+    /// Body of a call to the named user function or lambda.
+    Call(String),
+}
+
+impl fmt::Display for ScopeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScopeKind::Global => write!(f, "global"),
+            ScopeKind::Let => write!(f, "let"),
+            ScopeKind::For => write!(f, "for"),
+            ScopeKind::Call(name) => write!(f, "call to {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Scope {
+    kind: ScopeKind,
+    vars: HashMap<String, Value>,
+}
+
+/// Source of `Globals::id`.
+static NEXT_GLOBALS_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Global variables. A name keeps the slot it was first given even after
+/// its variable is removed, so compiled programs can refer to globals by
+/// slot instead of by name.
+pub struct Globals {
+    /// Identifies the table a program's slots were taken from. A clone gets
+    /// a new one, since its slots may diverge from those of the original.
+    id: u64,
+    slots: HashMap<String, usize>,
+    names: Vec<String>,
+    values: Vec<Option<Value>>,
+    len: usize,
+}
+
+impl Default for Globals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for Globals {
+    fn clone(&self) -> Self {
+        Globals {
+            id: NEXT_GLOBALS_ID.fetch_add(1, Ordering::Relaxed),
+            slots: self.slots.clone(),
+            names: self.names.clone(),
+            values: self.values.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl PartialEq for Globals {
+    /// Slots and ids do not matter, only the variables.
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(name, v)| other.get(name) == Some(v))
+    }
+}
+
+impl fmt::Debug for Globals {
+    /// Formats as a map of the variables.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl Globals {
+    pub fn new() -> Self {
+        Globals {
+            id: NEXT_GLOBALS_ID.fetch_add(1, Ordering::Relaxed),
+            slots: HashMap::new(),
+            names: Vec::new(),
+            values: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Slot of `name`, which is given one if it has none yet.
+    pub fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+        self.slots.insert(name.to_string(), self.names.len());
+        self.names.push(name.to_string());
+        self.values.push(None);
+        self.names.len() - 1
+    }
+
+    /// Variable in `slot`.
+    pub fn at(&self, slot: usize) -> Option<&Value> {
+        self.values.get(slot).and_then(Option::as_ref)
+    }
+
+    pub fn at_mut(&mut self, slot: usize) -> Option<&mut Value> {
+        self.values.get_mut(slot).and_then(Option::as_mut)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.slots.get(name).and_then(|&slot| self.at(slot))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        let slot = *self.slots.get(name)?;
+        self.at_mut(slot)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets the variable `name`, returning its previous value.
+    pub fn insert(&mut self, name: &str, value: Value) -> Option<Value> {
+        let slot = self.slot(name);
+        let old = self.values[slot].replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// The variable `name`, created with `default` if it does not exist.
+    pub fn entry(&mut self, name: &str, default: impl FnOnce() -> Value) -> &mut Value {
+        let slot = self.slot(name);
+        self.entry_at(slot, default)
+    }
+
+    /// Like `entry`, for the variable in `slot`.
+    pub fn entry_at(&mut self, slot: usize, default: impl FnOnce() -> Value) -> &mut Value {
+        if self.values[slot].is_none() {
+            self.len += 1;
+        }
+        self.values[slot].get_or_insert_with(default)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        let slot = *self.slots.get(name)?;
+        let old = self.values[slot].take();
+        if old.is_some() {
+            self.len -= 1;
+        }
+        old
+    }
+
+    pub fn clear(&mut self) {
+        self.values.iter_mut().for_each(|v| *v = None);
+        self.len = 0;
+    }
+
+    /// Number of variables.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Variables with their names, in the order their slots were given.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.names
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(name, v)| v.as_ref().map(|v| (name, v)))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.iter().map(|(name, _)| name)
+    }
+}
+
+/// Chain of variable scopes, searched from the innermost outwards. The
+/// global scope is always at the bottom and is never popped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scopes {
+    globals: Globals,
+    /// Scopes above the global one, innermost last.
+    scopes: Vec<Scope>,
+}
+
+impl Default for Scopes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scopes {
+    pub fn new() -> Self {
+        Scopes { globals: Globals::new(), scopes: Vec::new() }
+    }
+
+    pub fn globals(&self) -> &Globals {
+        &self.globals
+    }
+
+    pub fn globals_mut(&mut self) -> &mut Globals {
+        &mut self.globals
+    }
+
+    /// Number of scopes in the chain, including the global one.
+    pub fn depth(&self) -> usize {
+        self.scopes.len() + 1
+    }
+
+    pub fn push(&mut self, kind: ScopeKind) {
+        self.scopes.push(Scope { kind, vars: HashMap::new() });
+    }
+
+    pub fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Pops scopes until `depth` remain.
+    pub fn truncate(&mut self, depth: usize) {
+        self.scopes.truncate(depth.saturating_sub(1));
+    }
+
+    /// Index in `scopes` of the innermost scope above the global one that
+    /// binds `name`.
+    fn position(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rposition(|s| s.vars.contains_key(name))
+    }
+
+    /// Names bound in any of the scopes.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.globals
+            .keys()
+            .chain(self.scopes.iter().flat_map(|s| s.vars.keys()))
+            .map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self.position(name) {
+            Some(i) => self.scopes[i].vars.get(name),
+            None => self.globals.get(name),
+        }
+    }
+
+    /// Like `get`, for a name whose global is in `slot`.
+    pub fn get_global(&self, name: &str, slot: usize) -> Option<&Value> {
+        match self.position(name) {
+            Some(i) => self.scopes[i].vars.get(name),
+            None => self.globals.at(slot),
+        }
+    }
+
+    /// Innermost binding of `name`, or `NameNotFound` listing the scopes
+    /// that were searched.
+    pub fn lookup(&self, name: &str) -> Result<&Value, Error> {
+        self.get(name).ok_or_else(|| self.not_found(name))
+    }
+
+    /// Like `lookup`, for a name whose global is in `slot`.
+    pub fn lookup_global(&self, name: &str, slot: usize) -> Result<&Value, Error> {
+        self.get_global(name, slot).ok_or_else(|| self.not_found(name))
+    }
+
+    pub fn lookup_mut(&mut self, name: &str) -> Result<&mut Value, Error> {
+        match self.position(name) {
+            Some(i) => self.scopes[i].vars.get_mut(name).ok_or(Error::UnexpectedError),
+            None if self.globals.contains_key(name) =>
+                self.globals.get_mut(name).ok_or(Error::UnexpectedError),
+            None => Err(self.not_found(name)),
+        }
+    }
+
+    /// Like `lookup_mut`, for a name whose global is in `slot`.
+    pub fn lookup_global_mut(&mut self, name: &str, slot: usize) -> Result<&mut Value, Error> {
+        match self.position(name) {
+            Some(i) => self.scopes[i].vars.get_mut(name).ok_or(Error::UnexpectedError),
+            None if self.globals.at(slot).is_some() =>
+                self.globals.at_mut(slot).ok_or(Error::UnexpectedError),
+            None => Err(self.not_found(name)),
+        }
+    }
+
+    /// Index in `scopes` of the innermost call scope. New variables are
+    /// created there, or in the global scope outside of calls, so that they
+    /// outlive the `let` and `for` scopes they are assigned in.
+    fn call_position(&self) -> Option<usize> {
+        self.scopes.iter().rposition(|s| matches!(s.kind, ScopeKind::Call(_)))
+    }
+
+    /// Target of `name = ...`: the innermost existing binding, or a new one
+    /// initialized with `default` in the innermost call scope, or else in the
+    /// global scope.
+    pub fn slot(&mut self, name: &str, default: impl FnOnce() -> Value) -> &mut Value {
+        let i = match self.position(name) {
+            Some(i) => Some(i),
+            None if self.globals.contains_key(name) => None,
+            None => self.call_position(),
+        };
+        match i {
+            Some(i) => self.scopes[i].vars.entry(name.to_string()).or_insert_with(default),
+            None => self.globals.entry(name, default),
+        }
+    }
+
+    /// Like `slot`, for a name whose global is in `global`.
+    pub fn slot_global(
+        &mut self,
+        name: &str,
+        global: usize,
+        default: impl FnOnce() -> Value
+    ) -> &mut Value {
+        let i = match self.position(name) {
+            Some(i) => Some(i),
+            None if self.globals.at(global).is_some() => None,
+            None => self.call_position(),
+        };
+        match i {
+            Some(i) => self.scopes[i].vars.entry(name.to_string()).or_insert_with(default),
+            None => self.globals.entry_at(global, default),
+        }
+    }
+
+    /// Binds `name` in the innermost scope, shadowing outer bindings.
+    pub fn define(&mut self, name: &str, value: Value) {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.vars.insert(name.to_string(), value);
+            }
+            None => {
+                self.globals.insert(name, value);
+            }
+        }
+    }
+
+    /// Binds `name` in the scope at `depth`, counted from the global scope
+    /// at 0.
+    pub fn define_at(&mut self, depth: usize, name: &str, value: Value) {
+        if depth == 0 {
+            self.globals.insert(name, value);
+        } else if let Some(scope) = self.scopes.get_mut(depth - 1) {
+            scope.vars.insert(name.to_string(), value);
+        }
+    }
+
+    /// Removes the binding of `name` from the scope at `depth` and returns
+    /// its value.
+    pub fn take_at(&mut self, depth: usize, name: &str) -> Option<Value> {
+        if depth == 0 {
+            self.globals.remove(name)
+        } else {
+            self.scopes.get_mut(depth - 1).and_then(|scope| scope.vars.remove(name))
+        }
+    }
+
+    /// Runs `f` with the call scope `vars` directly above the global scope.
+    /// The caller's other scopes are hidden during the call and restored
+    /// afterwards, so function bodies only see globals and their own
+    /// bindings.
+    pub fn call<T>(
+        &mut self,
+        name: &str,
+        vars: HashMap<String, Value>,
+        f: impl FnOnce(&mut Scopes) -> T
+    ) -> T {
+        let outer = std::mem::take(&mut self.scopes);
+        self.scopes.push(Scope { kind: ScopeKind::Call(name.to_string()), vars });
+
+        let result = f(self);
+
+        self.scopes = outer;
+        result
+    }
+
+    fn not_found(&self, name: &str) -> Error {
+        Error::EvalError(EvalError::NameNotFound {
+            kind: NameKind::Variable,
+            name: name.to_string(),
+            searched: self.scopes
+                .iter()
+                .rev()
+                .map(|s| s.kind.clone())
+                .chain([ScopeKind::Global])
+                .collect(),
+            suggestions: Vec::new(),
+        })
+    }
+}
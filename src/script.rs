@@ -0,0 +1,284 @@
+use crate::{
+    error::{ Error, LexingError },
+    lexer::{ comment_len, lex_string },
+    operator::{ GroupingOp, Operator, TernaryOp },
+    token::{ Keyword, Token },
+};
+
+/// Bracket commands understood by the front end. A statement starting with
+/// one of them runs to the end of its line.
+pub const COMMANDS: [&str; 10] = [
+    "[exit]",
+    "[variables]",
+    "[clear]",
+    "[functions]",
+    "[defs]",
+    "[def ",
+    "[del]",
+    "[debug]",
+    "[save ",
+    "[load ",
+];
+
+/// Whether `s` starts with a bracket command, ignoring case.
+pub fn is_command(s: &str) -> bool {
+    COMMANDS.iter().any(|c| {
+        s.get(..c.len()).is_some_and(|p| p.eq_ignore_ascii_case(c))
+    })
+}
+
+/// End of the comment starting at byte `i` of `src`, if there is one. An
+/// unterminated block comment runs to the end, where the lexer reports it.
+fn skip_comment(src: &str, i: usize) -> Option<usize> {
+    let rest = src.get(i..)?;
+    match comment_len(rest) {
+        Some(n) => Some(i + n),
+        None if rest.starts_with("/*") => Some(src.len()),
+        None => None,
+    }
+}
+
+/// One statement of a script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Statement<'a> {
+    pub text: &'a str,
+    /// Byte offset of `text` in the script, to map spans back to it.
+    pub start: usize,
+    /// Ended by `;`, so its value is not printed.
+    pub silent: bool,
+}
+
+/// Splits a script into statements separated by newlines or `;`. Separators
+/// inside brackets, braces or string literals do not count, so a statement
+/// may span several lines, and neither do those in comments. Empty
+/// statements are dropped.
+pub fn split_statements(src: &str) -> Vec<Statement<'_>> {
+    let s = src.as_bytes();
+    let mut statements = Vec::new();
+    let mut i = 0usize;
+
+    loop {
+        loop {
+            while s.get(i).is_some_and(|c| c.is_ascii_whitespace() || *c == b';') {
+                i += 1;
+            }
+            // An unterminated block comment starts a statement, so that the
+            // lexer reports it.
+            match src.get(i..).and_then(comment_len) {
+                Some(n) => {
+                    i += n;
+                }
+                None => {
+                    break;
+                }
+            }
+        }
+        if i >= s.len() {
+            break;
+        }
+
+        let start = i;
+        let mut silent = false;
+
+        if is_command(&src[start..]) {
+            while s.get(i).is_some_and(|c| *c != b'\n') {
+                i += 1;
+            }
+        } else {
+            let mut depth = 0usize;
+            let mut in_str = false;
+
+            while let Some(&c) = s.get(i) {
+                if in_str {
+                    match c {
+                        b'\\' => {
+                            i += 1;
+                        }
+                        b'"' => {
+                            in_str = false;
+                        }
+                        _ => {}
+                    }
+                } else if let Some(end) = skip_comment(src, i) {
+                    i = end;
+                    continue;
+                } else {
+                    match c {
+                        b'"' => {
+                            in_str = true;
+                        }
+                        b'(' | b'[' | b'{' => {
+                            depth += 1;
+                        }
+                        b')' | b']' | b'}' => {
+                            depth = depth.saturating_sub(1);
+                        }
+                        b'\n' if depth == 0 => {
+                            break;
+                        }
+                        b';' if depth == 0 => {
+                            silent = true;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                i += 1;
+            }
+        }
+
+        let end = i.min(s.len());
+        statements.push(Statement {
+            text: src[start..end].trim_end(),
+            start,
+            silent,
+        });
+    }
+
+    statements
+}
+
+/// Whether `src` is an unfinished expression that goes on in the next line
+/// of input: it has unclosed brackets, an unterminated string or block
+/// comment, a `?` without its `:`, or it ends with an operator, a comma or a
+/// keyword that needs an operand.
+pub fn is_incomplete(src: &str) -> bool {
+    let tokens = match lex_string(src) {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            return matches!(
+                err.data,
+                Error::LexingError(
+                    LexingError::UnterminatedString { .. } | LexingError::UnterminatedComment { .. }
+                )
+            );
+        }
+    };
+
+    let mut depth = 0i64;
+    let mut ternaries = 0i64;
+
+    for t in &tokens {
+        match t.data {
+            | Token::Operator(Operator::Grouping(GroupingOp::LeftParen))
+            | Token::Operator(Operator::Grouping(GroupingOp::LeftBracket))
+            | Token::Operator(Operator::Grouping(GroupingOp::LeftBrace)) => {
+                depth += 1;
+            }
+            | Token::Operator(Operator::Grouping(GroupingOp::RightParen))
+            | Token::Operator(Operator::Grouping(GroupingOp::RightBracket))
+            | Token::Operator(Operator::Grouping(GroupingOp::RightBrace)) => {
+                depth -= 1;
+            }
+            Token::Operator(Operator::TernaryOp(TernaryOp::TernaryCond)) if depth == 0 => {
+                ternaries += 1;
+            }
+            Token::Operator(Operator::TernaryOp(TernaryOp::TernaryElse)) if depth == 0 => {
+                ternaries -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    let dangling = tokens.last().is_some_and(|t| {
+        match t.data {
+            | Token::Operator(Operator::Binary(_))
+            | Token::Operator(Operator::Unary(_))
+            | Token::Operator(Operator::TernaryOp(_))
+            | Token::Operator(Operator::Grouping(GroupingOp::Comma))
+            | Token::Arrow
+            | Token::Range => true,
+            Token::Keyword(k) => !matches!(k, Keyword::Break | Keyword::Continue),
+            _ => false,
+        }
+    });
+
+    depth > 0 || ternaries > 0 || dangling
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(src: &str) -> Vec<(&str, bool)> {
+        split_statements(src)
+            .iter()
+            .map(|s| (s.text, s.silent))
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_newlines_and_semicolons() {
+        assert_eq!(texts("a = 1\nb = 2; c\n"), [("a = 1", false), ("b = 2", true), ("c", false)]);
+        assert_eq!(texts("\n\n;; x ;\n\n"), [("x", true)]);
+        assert_eq!(texts(""), []);
+
+        let statements = split_statements("  a\r\n  b");
+        assert_eq!(statements[0].text, "a");
+        assert_eq!(statements[1].start, 7);
+        assert_eq!(&"  a\r\n  b"[statements[1].start..], "b");
+    }
+
+    #[test]
+    fn brackets_and_strings_hold_statements_together() {
+        assert_eq!(texts("f(1,\n2)\n[1;\n2]"), [("f(1,\n2)", false), ("[1;\n2]", false)]);
+        assert_eq!(texts("if x {\n  a; b\n}\ny"), [("if x {\n  a; b\n}", false), ("y", false)]);
+        assert_eq!(texts("s = \"a;b\nc\"; t"), [("s = \"a;b\nc\"", true), ("t", false)]);
+        assert_eq!(texts("s = \"\\\";\"\nt"), [("s = \"\\\";\"", false), ("t", false)]);
+    }
+
+    #[test]
+    fn comments_hide_separators() {
+        assert_eq!(texts("a /* ; \n */ + 1\nb"), [("a /* ; \n */ + 1", false), ("b", false)]);
+        assert_eq!(texts("// only a comment\nx // ; \ny"), [("x // ;", false), ("y", false)]);
+        // Left for the lexer to report.
+        assert_eq!(texts("x\n/* never closed"), [("x", false), ("/* never closed", false)]);
+    }
+
+    #[test]
+    fn commands_run_to_the_end_of_the_line() {
+        assert_eq!(texts("[DEF m x; y]\nz"), [("[DEF m x; y]", false), ("z", false)]);
+        assert!(is_command("[Save s.json]"));
+        assert!(!is_command("[1, 2]"));
+    }
+
+    #[test]
+    fn unfinished_input_continues() {
+        for src in [
+            "f(1,",
+            "[1, 2",
+            "if x {",
+            "\"abc",
+            "1 + /* comment",
+            "x ? 1",
+            "1 +",
+            "-",
+            "a = b,",
+            "v =>",
+            "for i in 0..",
+            "for i in",
+            "let a = 1 in",
+        ] {
+            assert!(is_incomplete(src), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn finished_input_runs() {
+        for src in [
+            "",
+            "f(1, 2)",
+            "x ? 1 : 2",
+            "(x ? 1 : 2)",
+            "1 + 2 // trailing +",
+            "while x { break }",
+            "continue",
+            "\"a + \"",
+            // Errors are reported rather than waited on.
+            "1)",
+            "1 $",
+        ] {
+            assert!(!is_incomplete(src), "{:?}", src);
+        }
+    }
+}
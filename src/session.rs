@@ -0,0 +1,601 @@
+use std::sync::Arc;
+
+use crate::{
+    bigint::BigInt,
+    complex::Complex,
+    debug::expr_to_text,
+    error::{ Error, SessionError },
+    interpreter::Interpreter,
+    lexer::lex_string,
+    matrix::Matrix,
+    parser::{ Expr, parse_string },
+    rational::Rational,
+    span::{ Span, Spanned },
+    token::Token,
+    user_function::{ Lambda, UserFunction },
+    value::{ Value, ValueType },
+};
+
+/// First line of every session file, followed by the format version.
+pub const HEADER: &str = "math_interpreter session";
+
+/// Format version written by `save`. Files of this or an older version can
+/// be read.
+pub const VERSION: u32 = 1;
+
+/// Writes the global variables and macros of `interpreter` in the session
+/// format, sorted by name:
+///
+/// ```text
+/// math_interpreter session 1
+///
+/// var r: Rational = 1/3
+/// var m: Matrix = matrix [[1, 0], [0, 1]]
+/// macro twice = x * 2
+/// ```
+///
+/// Each variable line names the exact type of its value. Values are written
+/// so that they read back identically: floats keep every digit and may be
+/// `inf` or `NaN`, complex numbers are `complex(re, im)` and functions are
+/// `lambda("source", captured = value, ...)`. Macros keep the source text
+/// they were defined from when it fits on one line.
+pub fn save(interpreter: &Interpreter) -> String {
+    let mut out = format!("{} {}\n", HEADER, VERSION);
+
+    let mut vars: Vec<_> = interpreter.vars().iter().collect();
+    vars.sort_by_key(|(name, _)| *name);
+    if !vars.is_empty() {
+        out.push('\n');
+    }
+    for (name, value) in vars {
+        out.push_str(&format!("var {}: {:?} = {}\n", name, value.value_type(), value_text(value)));
+    }
+
+    let macros = interpreter.macros();
+    let mut names: Vec<_> = macros.keys().collect();
+    names.sort();
+    if !names.is_empty() {
+        out.push('\n');
+    }
+    for name in names {
+        let source = macro_line(interpreter.macro_source(name), &macros[name]);
+        out.push_str(&format!("macro {} = {}\n", name, source));
+    }
+
+    out
+}
+
+/// Source of a macro as written on its line of a session file. Line breaks
+/// may separate statements in a block, so a source that has any is written
+/// from the parsed `body` instead.
+fn macro_line(source: Option<&str>, body: &Spanned<Expr>) -> String {
+    match source.map(str::trim) {
+        Some(source) if !source.contains(['\r', '\n']) => source.to_string(),
+        _ => expr_to_text(body),
+    }
+}
+
+/// `value` as written in a session file.
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Boolean(b) => b.to_string(),
+        Value::Int(i) => i.to_string(),
+        Value::BigInt(i) => i.to_string(),
+        Value::Rational(r) => r.to_string(),
+        Value::Float(f) => format!("{:?}", f),
+        Value::Complex(c) => format!("complex({:?}, {:?})", c.re, c.im),
+        Value::Str(s) => format!("{:?}", s),
+        Value::List(items) => list_text(items),
+        Value::Matrix(m) => format!("matrix {}", list_text(&m.to_rows())),
+        Value::Function(f) => {
+            let mut parts = vec![format!("{:?}", f.to_string())];
+            parts.extend(f.captured.iter().map(|(n, v)| format!("{} = {}", n, value_text(v))));
+            format!("lambda({})", parts.join(", "))
+        }
+    }
+}
+
+fn list_text(items: &[Value]) -> String {
+    let items = items.iter().map(value_text).collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
+
+fn type_from_name(name: &str) -> Option<ValueType> {
+    match name {
+        "Int" => Some(ValueType::Int),
+        "BigInt" => Some(ValueType::BigInt),
+        "Rational" => Some(ValueType::Rational),
+        "Float" => Some(ValueType::Float),
+        "Complex" => Some(ValueType::Complex),
+        "Boolean" => Some(ValueType::Boolean),
+        "Str" => Some(ValueType::Str),
+        "List" => Some(ValueType::List),
+        "Matrix" => Some(ValueType::Matrix),
+        "Function" => Some(ValueType::Function),
+        _ => None,
+    }
+}
+
+/// Contents of a session file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub vars: Vec<(String, Value)>,
+    /// Macro names with their source text.
+    pub macros: Vec<(String, String)>,
+}
+
+/// Reads a session file written by `save`. Macro sources are parsed to
+/// check them, and every error has its span in `src`.
+pub fn read(src: &str, debug: bool) -> Result<Session, Spanned<Error>> {
+    let mut session = Session { vars: Vec::new(), macros: Vec::new() };
+    let mut header = false;
+    let mut offset = 0usize;
+
+    for raw in src.split_inclusive('\n') {
+        let line_start = offset;
+        offset += raw.len();
+
+        let line = raw.trim_end();
+        let text = line.trim_start();
+        let start = line_start + line.len() - text.len();
+        let end = line_start + line.len();
+
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        if !header {
+            let Some(version) = text.strip_prefix(HEADER).map(str::trim) else {
+                return Err(session_error(Span { start, end }, SessionError::MissingHeader));
+            };
+            if !version.parse::<u32>().is_ok_and(|v| (1..=VERSION).contains(&v)) {
+                let err = SessionError::UnsupportedVersion { version: version.to_string() };
+                return Err(session_error(Span { start: end - version.len(), end }, err));
+            }
+            header = true;
+            continue;
+        }
+
+        let mut reader = Reader { src, i: start, end, debug };
+        if reader.keyword("var") {
+            session.vars.push(reader.var()?);
+        } else if reader.keyword("macro") {
+            session.macros.push(reader.macro_def()?);
+        } else {
+            return Err(session_error(Span { start, end }, SessionError::InvalidLine));
+        }
+    }
+
+    if !header {
+        return Err(session_error(Span::single(0), SessionError::MissingHeader));
+    }
+
+    Ok(session)
+}
+
+/// A name that the loaded file gave a different definition than the
+/// current session. The definition from the file replaces the old one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    Variable {
+        name: String,
+        old: Value,
+        new: Value,
+    },
+    Macro {
+        name: String,
+        old: String,
+        new: String,
+    },
+}
+
+/// What `load` merged into the session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadReport {
+    pub vars: usize,
+    pub macros: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Reads the session file `src` and merges it into `interpreter`. Names the
+/// session does not have yet are added, and names it already has get the
+/// file's definition, listed as conflicts when that differs. Nothing is
+/// merged if the file has an error.
+pub fn load(interpreter: &mut Interpreter, src: &str) -> Result<LoadReport, Spanned<Error>> {
+    let session = read(src, interpreter.debug())?;
+    let mut report = LoadReport {
+        vars: session.vars.len(),
+        macros: session.macros.len(),
+        conflicts: Vec::new(),
+    };
+
+    for (name, value) in session.vars {
+        // Compared as written, so that NaN and functions read back from the
+        // file do not differ from what was saved.
+        if let Some(old) = interpreter.get_var(&name) && value_text(old) != value_text(&value) {
+            report.conflicts.push(Conflict::Variable {
+                name: name.clone(),
+                old: old.clone(),
+                new: value.clone(),
+            });
+        }
+        interpreter.set_var(&name, value);
+    }
+
+    for (name, source) in session.macros {
+        if let Some(body) = interpreter.macros().get(&name) {
+            let old = macro_line(interpreter.macro_source(&name), body);
+            if old != source {
+                report.conflicts.push(Conflict::Macro { name: name.clone(), old, new: source.clone() });
+            }
+        }
+        // `read` has already parsed the source.
+        interpreter.define_macro(&name, &source)?;
+    }
+
+    Ok(report)
+}
+
+fn session_error(span: Span, err: SessionError) -> Spanned<Error> {
+    Spanned { span, data: Error::SessionError(err) }
+}
+
+/// Moves the span of an error in `text` to where `text` starts in the file.
+fn shift(err: Spanned<Error>, offset: usize) -> Spanned<Error> {
+    Spanned {
+        span: Span { start: err.span.start + offset, end: err.span.end + offset },
+        data: err.data,
+    }
+}
+
+/// Reads the line of a session file from byte `i` up to `end`.
+struct Reader<'a> {
+    src: &'a str,
+    i: usize,
+    end: usize,
+    debug: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.i..self.end]
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        self.i += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `c` if it comes next.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_spaces();
+        if self.rest().starts_with(c) {
+            self.i += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes `word` if it comes next as a whole word.
+    fn keyword(&mut self, word: &str) -> bool {
+        let rest = self.rest();
+        let follows = rest.strip_prefix(word).and_then(|r| r.chars().next());
+        if follows.is_some_and(char::is_whitespace) {
+            self.i += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Error for the text from `start` to the end of the next word.
+    fn invalid(&mut self, start: usize) -> Spanned<Error> {
+        if start == self.end {
+            return session_error(Span::single(start), SessionError::UnexpectedEndOfLine);
+        }
+        if self.i == start {
+            self.word();
+        }
+        let end = self.i.max(start + self.rest().chars().next().map_or(0, char::len_utf8));
+        let src = self.src[start..end].to_string();
+        session_error(Span { start, end }, SessionError::InvalidValue { src })
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Spanned<Error>> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            let start = self.i;
+            Err(self.invalid(start))
+        }
+    }
+
+    /// The next run of characters up to a space or punctuation.
+    fn word(&mut self) -> &'a str {
+        self.skip_spaces();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || ",:=()[]\"".contains(c))
+            .unwrap_or(rest.len());
+        self.i += len;
+        &rest[..len]
+    }
+
+    /// Fails unless the line has been read to the end.
+    fn finish(&mut self) -> Result<(), Spanned<Error>> {
+        self.skip_spaces();
+        if self.i == self.end {
+            Ok(())
+        } else {
+            let start = self.i;
+            let src = self.rest().to_string();
+            Err(session_error(Span { start, end: self.end }, SessionError::InvalidValue { src }))
+        }
+    }
+
+    /// `name: Type = value`, after `var`.
+    fn var(&mut self) -> Result<(String, Value), Spanned<Error>> {
+        let name = self.word().to_string();
+        if name.is_empty() {
+            return Err(session_error(Span { start: self.i, end: self.end }, SessionError::InvalidLine));
+        }
+        self.expect(':')?;
+
+        let type_start = self.i;
+        let type_name = self.word();
+        let Some(declared) = type_from_name(type_name) else {
+            let span = Span { start: self.i - type_name.len(), end: self.i.max(type_start + 1) };
+            return Err(session_error(span, SessionError::UnknownType { name: type_name.to_string() }));
+        };
+        self.expect('=')?;
+
+        self.skip_spaces();
+        let value_start = self.i;
+        let value = self.value()?;
+        let found = value.value_type();
+        if found != declared {
+            let span = Span { start: value_start, end: self.i };
+            return Err(session_error(span, SessionError::TypeMismatch { name, declared, found }));
+        }
+        self.finish()?;
+
+        Ok((name, value))
+    }
+
+    /// `name = source`, after `macro`.
+    fn macro_def(&mut self) -> Result<(String, String), Spanned<Error>> {
+        let name = self.word().to_string();
+        if name.is_empty() {
+            return Err(session_error(Span { start: self.i, end: self.end }, SessionError::InvalidLine));
+        }
+        self.expect('=')?;
+
+        self.skip_spaces();
+        let source = self.rest();
+        parse_string(source, self.debug).map_err(|err| shift(err, self.i))?;
+
+        Ok((name, source.to_string()))
+    }
+
+    fn value(&mut self) -> Result<Value, Spanned<Error>> {
+        self.skip_spaces();
+        let start = self.i;
+
+        if self.rest().starts_with('"') {
+            return Ok(Value::Str(self.string()?));
+        }
+        if self.rest().starts_with('[') {
+            return Ok(Value::List(self.list()?));
+        }
+
+        match self.word() {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            "matrix" => {
+                let rows = self.list()?;
+                Matrix::from_rows(&rows).map(Value::Matrix).ok_or_else(|| self.invalid(start))
+            }
+            "complex" => {
+                self.expect('(')?;
+                let re = self.float()?;
+                self.expect(',')?;
+                let im = self.float()?;
+                self.expect(')')?;
+                Ok(Value::Complex(Complex::new(re, im)))
+            }
+            "lambda" => self.lambda(),
+            word => number(word).ok_or_else(|| self.invalid(start)),
+        }
+    }
+
+    fn float(&mut self) -> Result<f64, Spanned<Error>> {
+        self.skip_spaces();
+        let start = self.i;
+        self.word().parse().map_err(|_| self.invalid(start))
+    }
+
+    /// `[value, ...]`.
+    fn list(&mut self) -> Result<Vec<Value>, Spanned<Error>> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        if self.eat(']') {
+            return Ok(items);
+        }
+        loop {
+            items.push(self.value()?);
+            if !self.eat(',') {
+                self.expect(']')?;
+                return Ok(items);
+            }
+        }
+    }
+
+    /// A string literal, with the escapes of the language.
+    fn string(&mut self) -> Result<String, Spanned<Error>> {
+        self.skip_spaces();
+        let start = self.i;
+        let rest = self.rest();
+
+        let mut len = rest.len();
+        let mut escaped = false;
+        for (i, c) in rest.char_indices().skip(1) {
+            match c {
+                _ if escaped => {
+                    escaped = false;
+                }
+                '\\' => {
+                    escaped = true;
+                }
+                '"' => {
+                    len = i + 1;
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        let tokens = lex_string(&rest[..len]).map_err(|err| shift(err, start))?;
+        match tokens.first() {
+            Some(Spanned { span, data: Token::Value(Value::Str(s)) }) => {
+                self.i = start + span.end;
+                Ok(s.clone())
+            }
+            _ => Err(self.invalid(start)),
+        }
+    }
+
+    /// `("params => body", name = value, ...)` after `lambda`.
+    fn lambda(&mut self) -> Result<Value, Spanned<Error>> {
+        self.expect('(')?;
+        self.skip_spaces();
+        let source_start = self.i;
+        let source = self.string()?;
+
+        // The source starts after the opening quote.
+        let expr = parse_string(&source, self.debug).map_err(|err| shift(err, source_start + 1))?;
+        let Expr::Lambda { params, body } = expr.data else {
+            let span = Span { start: source_start, end: self.i };
+            return Err(session_error(span, SessionError::InvalidValue { src: source }));
+        };
+
+        let mut captured = Vec::new();
+        while self.eat(',') {
+            let name = self.word().to_string();
+            self.expect('=')?;
+            captured.push((name, self.value()?));
+        }
+        self.expect(')')?;
+        captured.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let func = Arc::new(UserFunction::new(params, body));
+        Ok(Value::Function(Arc::new(Lambda { func, captured })))
+    }
+}
+
+/// An integer, fraction or float written by `value_text`.
+fn number(word: &str) -> Option<Value> {
+    if let Some((num, den)) = word.split_once('/') {
+        let r = Rational::new(num.parse().ok()?, den.parse().ok()?)?;
+        return Some(Value::from_rational(r));
+    }
+
+    let digits = word.strip_prefix('-').unwrap_or(word);
+    if !digits.is_empty() && digits.bytes().all(|c| c.is_ascii_digit()) {
+        return Some(Value::from_bigint(word.parse::<BigInt>().ok()?));
+    }
+
+    word.parse().ok().map(Value::Float)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_error_of(src: &str) -> (Span, SessionError) {
+        match read(src, false) {
+            Err(Spanned { span, data: Error::SessionError(err) }) => (span, err),
+            other => panic!("expected a session error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_every_value() {
+        let mut a = Interpreter::new();
+        a.eval("i = -7, b = 2**70, r = 1/3, fl = 0.1, c = 1 + 2i").unwrap();
+        a.set_var("ninf", Value::Float(f64::NEG_INFINITY));
+        a.set_var("nan", Value::Float(f64::NAN));
+        a.eval("t = true, s = \"quote \\\" and\nline\", l = [1, [2.5, \"x\"], []], m = [[1, 2], [3, 4]]").unwrap();
+        a.eval("k = 3, g = v => v * k").unwrap();
+        a.define_macro("twice", "x * 2").unwrap();
+        a.define_macro("block", "if true { y = 1\n y + 1 }").unwrap();
+
+        let text = save(&a);
+        let mut b = Interpreter::new();
+        let report = load(&mut b, &text).unwrap();
+        assert_eq!((report.vars, report.macros, report.conflicts), (a.vars().len(), 2, vec![]));
+        assert_eq!(save(&b), text);
+
+        for (name, value) in a.vars().iter() {
+            let loaded = b.get_var(name).unwrap();
+            assert_eq!(loaded.value_type(), value.value_type(), "{}", name);
+            assert_eq!(value_text(loaded), value_text(value), "{}", name);
+        }
+        assert!(b.get_var("nan").and_then(|v| v.as_float()).is_some_and(f64::is_nan));
+        // Loading a session into itself changes nothing.
+        assert_eq!(load(&mut a, &text).unwrap().conflicts, vec![]);
+        assert_eq!(b.eval("g(2)").unwrap(), Value::Int(6));
+        assert_eq!(b.eval("{block}").unwrap(), Value::Int(2));
+    }
+
+    #[test]
+    fn load_reports_conflicts() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_var("same", Value::Int(1));
+        interpreter.set_var("changed", Value::Int(1));
+        interpreter.define_macro("m", "1").unwrap();
+
+        let src = "math_interpreter session 1\n# comment\n\nvar same: Int = 1\nvar changed: Int = 2\nmacro m = 2\n";
+        let report = load(&mut interpreter, src).unwrap();
+        assert_eq!(report.conflicts, [
+            Conflict::Variable { name: "changed".into(), old: Value::Int(1), new: Value::Int(2) },
+            Conflict::Macro { name: "m".into(), old: "1".into(), new: "2".into() },
+        ]);
+        assert_eq!(interpreter.get_var("changed"), Some(&Value::Int(2)));
+    }
+
+    #[test]
+    fn errors_point_into_the_file() {
+        assert_eq!(session_error_of("").1, SessionError::MissingHeader);
+        assert_eq!(session_error_of("var x: Int = 1\n").1, SessionError::MissingHeader);
+        assert_eq!(
+            session_error_of("math_interpreter session 2\n"),
+            (Span { start: 25, end: 26 }, SessionError::UnsupportedVersion { version: "2".into() })
+        );
+
+        let header = "math_interpreter session 1\n";
+        let at = |start: usize, end: usize| Span { start: header.len() + start, end: header.len() + end };
+        assert_eq!(session_error_of(&format!("{header}x = 1")), (at(0, 5), SessionError::InvalidLine));
+        assert_eq!(
+            session_error_of(&format!("{header}var x: Int = 1.5")),
+            (
+                at(13, 16),
+                SessionError::TypeMismatch { name: "x".into(), declared: ValueType::Int, found: ValueType::Float },
+            )
+        );
+        assert_eq!(
+            session_error_of(&format!("{header}var x: Number = 1")),
+            (at(7, 13), SessionError::UnknownType { name: "Number".into() })
+        );
+        assert_eq!(session_error_of(&format!("{header}var x: List = [1,")).1, SessionError::UnexpectedEndOfLine);
+    }
+
+    #[test]
+    fn nothing_is_merged_from_a_bad_file() {
+        let mut interpreter = Interpreter::new();
+        let src = "math_interpreter session 1\nvar a: Int = 1\nmacro m = 1 +\n";
+        assert!(load(&mut interpreter, src).is_err());
+        assert!(interpreter.get_var("a").is_none());
+        assert!(interpreter.macros().is_empty());
+    }
+}
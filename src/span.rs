@@ -1,3 +1,5 @@
+use unicode_width::UnicodeWidthChar;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Span {
     pub start: usize,
@@ -32,3 +34,48 @@ impl Span {
         }
     }
 }
+
+/// One-based line and column of byte offset `pos` in `src`. Columns count
+/// characters, and offsets past the end map to the end of the text.
+pub fn line_col(src: &str, pos: usize) -> (usize, usize) {
+    let mut pos = pos.min(src.len());
+    while !src.is_char_boundary(pos) {
+        pos -= 1;
+    }
+
+    let before = &src[..pos];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Columns taken by a tab when source text is displayed.
+pub const TAB_WIDTH: usize = 4;
+
+/// Width of `s` in terminal columns: wide characters such as `中` take two,
+/// combining marks none and tabs `TAB_WIDTH`.
+pub fn display_width(s: &str) -> usize {
+    s.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { c.width().unwrap_or(0) })
+        .sum()
+}
+
+/// Zero-based display columns where `span` of `src` starts and ends on the
+/// line holding its start. A span running past that line ends at the end of
+/// the line.
+pub fn display_cols(src: &str, span: Span) -> (usize, usize) {
+    let floor = |mut pos: usize| {
+        pos = pos.min(src.len());
+        while !src.is_char_boundary(pos) {
+            pos -= 1;
+        }
+        pos
+    };
+    let start = floor(span.start);
+    let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+    let end = floor(span.end).clamp(start, line_end);
+
+    let col = display_width(&src[line_start..start]);
+    (col, col + display_width(&src[start..end]))
+}
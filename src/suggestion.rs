@@ -0,0 +1,129 @@
+use crate::error::NameKind;
+
+/// Most suggestions given for one unknown name.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Names visible where a lookup failed, by namespace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Namespaces<'a> {
+    pub variables: Vec<&'a str>,
+    /// Native and user functions.
+    pub functions: Vec<&'a str>,
+    pub macros: Vec<&'a str>,
+}
+
+/// Edit distance between `a` and `b` in characters, counting a swap of two
+/// neighbouring characters as one edit.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Rows of the table for the previous two prefixes of `a`.
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (previous[j] + 1).min(row[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, row);
+    }
+
+    previous[b.len()]
+}
+
+/// Names close to the unknown `name` of `kind`, written as they would be
+/// used: `x` for a variable, `f()` for a function and `{m}` for a macro.
+/// All namespaces are searched, so a name that exists in another one is
+/// suggested as well. A candidate may differ in a third of the characters
+/// but never in all of them. Closest first, and names of `kind` before the
+/// others at the same distance.
+pub fn suggest(kind: NameKind, name: &str, namespaces: &Namespaces) -> Vec<String> {
+    let len = name.chars().count();
+    let limit = (len / 3).max(1).min(len.saturating_sub(1));
+
+    let mut found: Vec<(usize, bool, String)> = Vec::new();
+    let groups = [
+        (NameKind::Variable, &namespaces.variables),
+        (NameKind::Function, &namespaces.functions),
+        (NameKind::Macro, &namespaces.macros),
+    ];
+    for (group, names) in groups {
+        for candidate in names.iter() {
+            if group == kind && *candidate == name {
+                continue;
+            }
+            let distance = edit_distance(name, candidate);
+            if distance <= limit {
+                let text = match group {
+                    NameKind::Variable => candidate.to_string(),
+                    NameKind::Function => format!("{}()", candidate),
+                    NameKind::Macro => format!("{{{}}}", candidate),
+                };
+                found.push((distance, group != kind, text));
+            }
+        }
+    }
+
+    found.sort();
+    found.dedup_by(|a, b| a.2 == b.2);
+    found
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, _, text)| text)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_characters() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("héllo", "hello"), 1);
+        assert_eq!(edit_distance("中文", "中"), 1);
+    }
+
+    #[test]
+    fn a_swap_is_one_edit() {
+        assert_eq!(edit_distance("ab", "ba"), 1);
+        assert_eq!(edit_distance("lenght", "length"), 1);
+        assert_eq!(edit_distance("abcd", "badc"), 2);
+    }
+
+    #[test]
+    fn suggests_close_names_from_every_namespace() {
+        let namespaces = Namespaces {
+            variables: vec!["count", "total", "sqr"],
+            functions: vec!["sqrt", "sqrt", "cos", "length"],
+            macros: vec!["sq"],
+        };
+        assert_eq!(suggest(NameKind::Function, "lenght", &namespaces), ["length()"]);
+        assert_eq!(suggest(NameKind::Variable, "cont", &namespaces), ["count"]);
+        // Names of the kind looked up come first at the same distance, and
+        // a name found twice is suggested once.
+        assert_eq!(suggest(NameKind::Function, "sqt", &namespaces), ["sqrt()", "sqr", "{sq}"]);
+        // The same name in another namespace is the closest.
+        assert_eq!(suggest(NameKind::Macro, "sqr", &namespaces), ["sqr", "{sq}", "sqrt()"]);
+        assert!(suggest(NameKind::Variable, "zzz", &namespaces).is_empty());
+    }
+
+    #[test]
+    fn short_names_need_a_character_in_common() {
+        let namespaces = Namespaces { variables: vec!["x", "y", "ab"], functions: vec!["x"], ..Default::default() };
+        // Only the same name in another namespace is close to a single letter.
+        assert_eq!(suggest(NameKind::Function, "y", &namespaces), ["y"]);
+        assert_eq!(suggest(NameKind::Variable, "x", &namespaces), ["x()"]);
+        assert_eq!(suggest(NameKind::Variable, "ac", &namespaces), ["ab"]);
+        assert!(suggest(NameKind::Variable, "cd", &namespaces).is_empty());
+    }
+}
@@ -1,12 +1,62 @@
 use crate::operator::{ BinaryOp, GroupingOp, Operator, TernaryOp };
 use crate::value::Value;
 
+/// Reserved words, which cannot be used as names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyword {
+    Fn,
+    Let,
+    In,
+    If,
+    Else,
+    While,
+    For,
+    Break,
+    Continue,
+}
+
+impl Keyword {
+    pub fn from_word(word: &str) -> Option<Keyword> {
+        match word {
+            "fn" => Some(Keyword::Fn),
+            "let" => Some(Keyword::Let),
+            "in" => Some(Keyword::In),
+            "if" => Some(Keyword::If),
+            "else" => Some(Keyword::Else),
+            "while" => Some(Keyword::While),
+            "for" => Some(Keyword::For),
+            "break" => Some(Keyword::Break),
+            "continue" => Some(Keyword::Continue),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Keyword::Fn => "fn",
+            Keyword::Let => "let",
+            Keyword::In => "in",
+            Keyword::If => "if",
+            Keyword::Else => "else",
+            Keyword::While => "while",
+            Keyword::For => "for",
+            Keyword::Break => "break",
+            Keyword::Continue => "continue",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Operator(Operator),
     Value(Value),
     Identifier(String),
     Macro(String),
+    /// `=>` between the parameters and the body of a lambda.
+    Arrow,
+    /// `..` between the bounds of a `for` range.
+    Range,
+    Keyword(Keyword),
 }
 
 impl Token {
@@ -16,6 +66,9 @@ impl Token {
             Token::Value(v) => v.symbol(),
             Token::Identifier(s) => s.clone(),
             Token::Macro(s) => format!("{{{}}}", s.clone()),
+            Token::Arrow => String::from("=>"),
+            Token::Range => String::from(".."),
+            Token::Keyword(k) => String::from(k.symbol()),
         }
     }
 
@@ -27,7 +80,10 @@ impl Token {
                         match v {
                             BinaryOp::Exponentiation => 80,
 
-                            BinaryOp::Multiplication | BinaryOp::Division | BinaryOp::Modulo => 60,
+                            | BinaryOp::Multiplication
+                            | BinaryOp::ElementMul
+                            | BinaryOp::Division
+                            | BinaryOp::Modulo => 60,
 
                             BinaryOp::Addition | BinaryOp::Subtraction => 50,
 
@@ -57,12 +113,21 @@ impl Token {
                         match v {
                             GroupingOp::LeftParen => 90,
                             GroupingOp::RightParen => 0,
+                            GroupingOp::LeftBracket => 90,
+                            GroupingOp::RightBracket => 0,
+                            GroupingOp::LeftBrace => 0,
+                            GroupingOp::RightBrace => 0,
                             GroupingOp::Comma => 1,
+                            GroupingOp::Semicolon => 0,
                         }
                 }
             Token::Value(_) => 0,
             Token::Identifier(_) => 0,
             Token::Macro(_) => 0,
+            // Above `=` so that `f = x => ...` assigns the lambda.
+            Token::Arrow => 4,
+            Token::Range => 0,
+            Token::Keyword(_) => 0,
         }
     }
 }
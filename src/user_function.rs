@@ -0,0 +1,257 @@
+use std::{ cell::Cell, collections::HashMap, fmt, sync::{ Arc, OnceLock } };
+
+use crate::{
+    compiler::{ Program, compile_function },
+    debug::expr_to_text,
+    error::{ Error, EvalError, NameKind },
+    native_function::FnArity,
+    parser::Expr,
+    scope::Globals,
+    span::Spanned,
+    value::Value,
+};
+
+/// User function calls nested deeper than this fail with `RecursionLimit`.
+pub const MAX_CALL_DEPTH: usize = 5000;
+
+/// Stack a call must have left before it runs on a new segment.
+const STACK_RED_ZONE: usize = 1024 * 1024;
+
+/// Size of each stack segment allocated for deep recursion.
+const STACK_SEGMENT: usize = 16 * 1024 * 1024;
+
+thread_local! {
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Function defined with `name(params) = body`.
+#[derive(Debug, Clone)]
+pub struct UserFunction {
+    pub params: Vec<String>,
+    pub body: Box<Spanned<Expr>>,
+    /// `body` compiled for `vm::run`, once the VM first calls it.
+    program: OnceLock<Program>,
+}
+
+impl PartialEq for UserFunction {
+    /// Whether the body has been compiled yet does not matter.
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params && self.body == other.body
+    }
+}
+
+impl UserFunction {
+    pub fn new(params: Vec<String>, body: Box<Spanned<Expr>>) -> Self {
+        UserFunction { params, body, program: OnceLock::new() }
+    }
+
+    /// `body` compiled for `vm::run`, with its global variables given slots
+    /// in `globals`. Only the first call compiles it, so functions the VM
+    /// never calls are not compiled at all.
+    pub fn program(&self, globals: &mut Globals) -> &Program {
+        self.program.get_or_init(|| compile_function(&self.params, &self.body, globals))
+    }
+
+    /// Fails unless `args` holds one value per parameter.
+    pub fn check_arity(&self, name: &str, args: &[Value]) -> Result<(), Error> {
+        if args.len() != self.params.len() {
+            return Err(
+                Error::EvalError(EvalError::ArityMismatch {
+                    func: name.to_string(),
+                    expected: FnArity::Fixed(self.params.len()),
+                    found: args.len(),
+                })
+            );
+        }
+        Ok(())
+    }
+
+    /// Variables of the call scope: the parameters bound to `args`.
+    pub fn bind(&self, name: &str, args: Vec<Value>) -> Result<HashMap<String, Value>, Error> {
+        self.check_arity(name, &args)?;
+        Ok(self.params.iter().cloned().zip(args).collect())
+    }
+
+    /// Signature such as `f(x, y)`.
+    pub fn signature(&self, name: &str) -> String {
+        format!("{}({})", name, self.params.join(", "))
+    }
+}
+
+/// Anonymous function such as `x => x**2 + 1` or `fn(x) x*2`. The
+/// variables the body references are copied when the lambda is created, so
+/// later assignments to them do not change its result.
+#[derive(Clone, PartialEq)]
+pub struct Lambda {
+    pub func: Arc<UserFunction>,
+    /// Captured variables, sorted by name.
+    pub captured: Vec<(String, Value)>,
+}
+
+/// Name used for lambdas in errors when they are not called through a
+/// variable.
+pub const LAMBDA_NAME: &str = "<lambda>";
+
+impl Lambda {
+    /// Creates a lambda capturing the variables referenced by the body that
+    /// `lookup` finds, which gives the value of a visible variable.
+    pub fn new(func: Arc<UserFunction>, lookup: impl Fn(&str) -> Option<Value>) -> Self {
+        let mut names = Vec::new();
+        referenced_names(&func.body, &mut names);
+        names.sort();
+        names.dedup();
+
+        let captured = names
+            .into_iter()
+            .filter(|n| !func.params.contains(n))
+            .filter_map(|n| lookup(&n).map(|v| (n, v)))
+            .collect();
+        Lambda { func, captured }
+    }
+
+    /// Lambda calling the user function `func`, which is the value of its
+    /// definition. It captures nothing, since the function only sees the
+    /// globals at the time of the call.
+    pub fn of_function(func: Arc<UserFunction>) -> Self {
+        Lambda { func, captured: Vec::new() }
+    }
+
+    /// Variables of the call scope: the captured variables and the
+    /// parameters.
+    pub fn bind(&self, name: &str, args: Vec<Value>) -> Result<HashMap<String, Value>, Error> {
+        // Parameters are never captured, so nothing is overwritten.
+        let mut locals = self.func.bind(name, args)?;
+        locals.extend(self.captured.iter().cloned());
+        Ok(locals)
+    }
+
+    /// The captured variables, as the variables of a call scope.
+    pub fn captured_vars(&self) -> HashMap<String, Value> {
+        self.captured.iter().cloned().collect()
+    }
+}
+
+impl fmt::Display for Lambda {
+    /// Formats as the `=>` form of the literal.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expr = Spanned {
+            span: self.func.body.span,
+            data: Expr::Lambda { params: self.func.params.clone(), body: self.func.body.clone() },
+        };
+        write!(f, "{}", expr_to_text(&expr))
+    }
+}
+
+impl fmt::Debug for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Lambda({})", self)?;
+        if !self.captured.is_empty() {
+            let captured = self.captured
+                .iter()
+                .map(|(n, v)| format!("{} = {}", n, v.symbol()))
+                .collect::<Vec<_>>();
+            write!(f, " with {{{}}}", captured.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects every identifier in `expr`.
+fn referenced_names(expr: &Spanned<Expr>, names: &mut Vec<String>) {
+    match &expr.data {
+        Expr::Value(_) | Expr::Macro(_) | Expr::Break | Expr::Continue | Expr::Error => {}
+        Expr::Identifier(name) => names.push(name.clone()),
+        Expr::Unary { rhs, .. } => referenced_names(rhs, names),
+        Expr::Binary { lhs, rhs, .. } => {
+            referenced_names(lhs, names);
+            referenced_names(rhs, names);
+        }
+        Expr::Ternary { cond, statement1, statement2 } => {
+            for e in [cond, statement1, statement2] {
+                referenced_names(e, names);
+            }
+        }
+        Expr::Call { func, args } => {
+            referenced_names(func, names);
+            for arg in args {
+                referenced_names(arg, names);
+            }
+        }
+        Expr::Comma { exprs: items } | Expr::Block { exprs: items } | Expr::List { items } => {
+            for item in items {
+                referenced_names(item, names);
+            }
+        }
+        Expr::Index { target, index } => {
+            referenced_names(target, names);
+            referenced_names(index, names);
+        }
+        Expr::Slice { target, start, end } => {
+            referenced_names(target, names);
+            for bound in [start, end].into_iter().flatten() {
+                referenced_names(bound, names);
+            }
+        }
+        Expr::FunctionDef { body, .. } | Expr::Lambda { body, .. } => {
+            referenced_names(body, names);
+        }
+        Expr::Let { bindings, body } => {
+            for (_, value) in bindings {
+                referenced_names(value, names);
+            }
+            referenced_names(body, names);
+        }
+        Expr::If { cond, then_branch, else_branch } => {
+            referenced_names(cond, names);
+            referenced_names(then_branch, names);
+            if let Some(e) = else_branch {
+                referenced_names(e, names);
+            }
+        }
+        Expr::While { cond, body } => {
+            referenced_names(cond, names);
+            referenced_names(body, names);
+        }
+        Expr::For { start, end, body, .. } => {
+            for e in [start, end, body] {
+                referenced_names(e, names);
+            }
+        }
+    }
+}
+
+/// Marks a user function call or macro expansion in progress. Dropping the
+/// guard ends it.
+pub struct CallGuard(());
+
+impl CallGuard {
+    pub fn enter(kind: NameKind, name: &str) -> Result<CallGuard, Error> {
+        let depth = CALL_DEPTH.with(|d| d.get());
+        if depth >= MAX_CALL_DEPTH {
+            return Err(
+                Error::EvalError(EvalError::RecursionLimit {
+                    kind,
+                    func: name.to_string(),
+                    depth,
+                })
+            );
+        }
+
+        CALL_DEPTH.with(|d| d.set(depth + 1));
+        Ok(CallGuard(()))
+    }
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        CALL_DEPTH.with(|d| d.set(d.get() - 1));
+    }
+}
+
+/// Runs `f`, the body of a call, on a newly allocated stack segment once
+/// the current one is nearly used up. Nested calls therefore only stop at
+/// `MAX_CALL_DEPTH`, whatever the stack size of the thread the interpreter
+/// runs on.
+pub fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, f)
+}
@@ -1,53 +1,230 @@
-use crate::error::{ Error, EvalError };
+use std::{ borrow::Cow, sync::Arc };
+
+use crate::{
+    bigint::BigInt,
+    complex::Complex,
+    error::{ Error, EvalError },
+    matrix::Matrix,
+    parser::Expr,
+    rational::Rational,
+    span::{ Span, Spanned },
+    user_function::{ Lambda, UserFunction },
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ValueType {
     Int,
+    BigInt,
+    Rational,
     Float,
+    Complex,
     Boolean,
+    Str,
+    List,
+    Matrix,
+    Function,
 }
 
 impl ValueType {
+    /// Position on the promotion ladder used by `unify_pair`. `Str`, `List`,
+    /// `Matrix` and `Function` sit above the numeric types so that mixing
+    /// them with a number targets a type numbers never promote to, and
+    /// unification fails instead of coercing.
     pub fn rank(self) -> i8 {
         match self {
             ValueType::Boolean => 0,
             ValueType::Int => 1,
-            ValueType::Float => 2,
+            ValueType::BigInt => 2,
+            ValueType::Rational => 3,
+            ValueType::Float => 4,
+            ValueType::Complex => 5,
+            ValueType::Str => 6,
+            ValueType::List => 7,
+            ValueType::Matrix => 8,
+            ValueType::Function => 9,
+        }
+    }
+
+    pub fn is_numeric(self) -> bool {
+        !matches!(self, ValueType::Str | ValueType::List | ValueType::Matrix | ValueType::Function)
+    }
+
+    pub fn is_integer(self) -> bool {
+        matches!(self, ValueType::Int | ValueType::BigInt)
+    }
+
+    /// Integers and rationals, which variables may switch between freely.
+    pub fn is_exact(self) -> bool {
+        matches!(self, ValueType::Int | ValueType::BigInt | ValueType::Rational)
+    }
+
+    /// Lists and matrices, which variables may also switch between freely,
+    /// since a list literal whose items are rows of equal length is a
+    /// matrix.
+    pub fn is_collection(self) -> bool {
+        matches!(self, ValueType::List | ValueType::Matrix)
+    }
+
+    /// Initial value of a variable created by assigning a value of this type.
+    pub fn default_value(self) -> Value {
+        match self {
+            ValueType::Boolean => Value::Boolean(false),
+            ValueType::Int | ValueType::BigInt | ValueType::Rational => Value::Int(0),
+            ValueType::Float => Value::Float(0.0),
+            ValueType::Complex => Value::Complex(Complex::default()),
+            ValueType::Str => Value::Str(String::new()),
+            ValueType::List => Value::List(Vec::new()),
+            // There is no empty matrix; the assignment replaces this.
+            ValueType::Matrix => Value::Matrix(Matrix::identity(1)),
+            // `() => 0`, likewise replaced by the assignment.
+            ValueType::Function => {
+                let body = Spanned { span: Span::single(0), data: Expr::Value(Value::Int(0)) };
+                let func = Arc::new(UserFunction::new(Vec::new(), Box::new(body)));
+                Value::Function(Arc::new(Lambda { func, captured: Vec::new() }))
+            }
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Int(i64),
+    /// Integer outside the `i64` range. Results that fit are normalized back
+    /// to `Int` by `Value::from_bigint`.
+    BigInt(BigInt),
+    /// Non-integer fraction. Integral results are normalized to `Int` or
+    /// `BigInt` by `Value::from_rational`.
+    Rational(Rational),
     Float(f64),
+    Complex(Complex),
     Boolean(bool),
+    Str(String),
+    List(Vec<Value>),
+    Matrix(Matrix),
+    Function(Arc<Lambda>),
 }
 
 impl Value {
     pub fn value_type(&self) -> ValueType {
         match self {
             Value::Int(_) => ValueType::Int,
+            Value::BigInt(_) => ValueType::BigInt,
+            Value::Rational(_) => ValueType::Rational,
             Value::Float(_) => ValueType::Float,
+            Value::Complex(_) => ValueType::Complex,
             Value::Boolean(_) => ValueType::Boolean,
+            Value::Str(_) => ValueType::Str,
+            Value::List(_) => ValueType::List,
+            Value::Matrix(_) => ValueType::Matrix,
+            Value::Function(_) => ValueType::Function,
         }
     }
 
+    /// Converts to `target`, or `None` if there is no implicit conversion.
+    /// Strings, lists, matrices and functions only promote to themselves.
     pub fn promote(&self, target: ValueType) -> Option<Value> {
         match (self, target) {
+            (v @ Value::Function(_), ValueType::Function) => Some(v.clone()),
+            (Value::Function(_), _) | (_, ValueType::Function) => None,
+
             (Value::Int(v), ValueType::Boolean) => Some(Value::Boolean(*v != 0)),
-            (v @ Value::Int(_), ValueType::Int) => Some(*v),
+            (v @ Value::Int(_), ValueType::Int) => Some(v.clone()),
+            (Value::Int(v), ValueType::BigInt) => Some(Value::BigInt(BigInt::from(*v))),
+            (Value::Int(v), ValueType::Rational) => {
+                Some(Value::Rational(Rational::from(BigInt::from(*v))))
+            }
             (Value::Int(v), ValueType::Float) => Some(Value::Float(*v as f64)),
 
+            (Value::BigInt(v), ValueType::Boolean) => Some(Value::Boolean(!v.is_zero())),
+            (Value::BigInt(v), ValueType::Int) => v.to_i64().map(Value::Int),
+            (v @ Value::BigInt(_), ValueType::BigInt) => Some(v.clone()),
+            (Value::BigInt(v), ValueType::Rational) => Some(Value::Rational(Rational::from(v.clone()))),
+            (Value::BigInt(v), ValueType::Float) => Some(Value::Float(v.to_f64())),
+
+            (Value::Rational(v), ValueType::Boolean) => Some(Value::Boolean(!v.is_zero())),
+            (Value::Rational(v), ValueType::Int) => v.trunc().to_i64().map(Value::Int),
+            (Value::Rational(v), ValueType::BigInt) => Some(Value::BigInt(v.trunc())),
+            (v @ Value::Rational(_), ValueType::Rational) => Some(v.clone()),
+            (Value::Rational(v), ValueType::Float) => Some(Value::Float(v.to_f64())),
+
             (Value::Float(v), ValueType::Boolean) => Some(Value::Boolean(*v != 0.0)),
             (Value::Float(v), ValueType::Int) => Some(Value::Int(*v as i64)),
-            (v @ Value::Float(_), ValueType::Float) => Some(*v),
+            (Value::Float(v), ValueType::BigInt) => BigInt::from_f64(*v).map(Value::BigInt),
+            (Value::Float(v), ValueType::Rational) => Rational::from_f64(*v).map(Value::Rational),
+            (v @ Value::Float(_), ValueType::Float) => Some(v.clone()),
 
-            (v @ Value::Boolean(_), ValueType::Boolean) => Some(*v),
+            (v @ Value::Boolean(_), ValueType::Boolean) => Some(v.clone()),
             (Value::Boolean(v), ValueType::Int) => Some(Value::Int(if *v { 1i64 } else { 0i64 })),
+            (Value::Boolean(v), ValueType::BigInt) => {
+                Some(Value::BigInt(BigInt::from(if *v { 1i64 } else { 0i64 })))
+            }
+            (Value::Boolean(v), ValueType::Rational) => {
+                Some(Value::Rational(Rational::from(BigInt::from(if *v { 1i64 } else { 0i64 }))))
+            }
             (Value::Boolean(v), ValueType::Float) => {
                 Some(Value::Float(if *v { 1f64 } else { 0f64 }))
             }
+
+            (v @ Value::Matrix(_), ValueType::Matrix) => Some(v.clone()),
+            (Value::Matrix(_), _) | (_, ValueType::Matrix) => None,
+
+            (v @ Value::List(_), ValueType::List) => Some(v.clone()),
+            (Value::List(_), _) | (_, ValueType::List) => None,
+
+            (v @ Value::Complex(_), ValueType::Complex) => Some(v.clone()),
+            (Value::Complex(c), ValueType::Boolean) => Some(Value::Boolean(!c.is_zero())),
+            // Only values on the real axis convert to the real types.
+            (Value::Complex(c), target) => {
+                if c.im == 0.0 { Value::Float(c.re).promote(target) } else { None }
+            }
+
+            (v @ Value::Str(_), ValueType::Str) => Some(v.clone()),
+            (Value::Str(_), _) | (_, ValueType::Str) => None,
+
+            (v, ValueType::Complex) => {
+                v.promote(ValueType::Float)
+                    .and_then(|f| f.as_float())
+                    .map(|f| Value::Complex(Complex::from(f)))
+            }
+        }
+    }
+
+    /// Wraps list items, as a `Matrix` when they are rows of numbers of
+    /// equal length.
+    pub fn from_list(items: Vec<Value>) -> Value {
+        match Matrix::from_rows(&items) {
+            Some(m) => Value::Matrix(m),
+            None => Value::List(items),
+        }
+    }
+
+    /// Wraps an integer result, using `Int` whenever it fits.
+    pub fn from_bigint(v: BigInt) -> Value {
+        match v.to_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::BigInt(v),
+        }
+    }
+
+    /// Wraps a fraction, using an integer value when the denominator is 1.
+    pub fn from_rational(v: Rational) -> Value {
+        if v.is_integer() { Value::from_bigint(v.trunc()) } else { Value::Rational(v) }
+    }
+
+    /// Exact value as a `Rational`, for integer and rational values.
+    pub fn to_rational(&self) -> Option<Rational> {
+        match self {
+            Value::Rational(v) => Some(v.clone()),
+            v => v.to_bigint().map(Rational::from),
+        }
+    }
+
+    /// Integer value as a `BigInt`, for `Int` and `BigInt` values.
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Value::Int(v) => Some(BigInt::from(*v)),
+            Value::BigInt(v) => Some(v.clone()),
+            _ => None,
         }
     }
 
@@ -65,6 +242,13 @@ impl Value {
         }
     }
 
+    pub fn as_complex(&self) -> Option<Complex> {
+        match self {
+            Value::Complex(val) => Some(*val),
+            _ => None,
+        }
+    }
+
     pub fn as_boolean(&self) -> Option<bool> {
         match self {
             Value::Boolean(val) => Some(*val),
@@ -72,6 +256,104 @@ impl Value {
         }
     }
 
+    /// Value as the condition of `if`, `while` or `?:`.
+    pub fn as_condition(&self) -> Result<bool, Error> {
+        self.promote(ValueType::Boolean)
+            .and_then(|b| b.as_boolean())
+            .ok_or(Error::EvalError(EvalError::InvalidCondition { found: self.value_type() }))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn as_matrix(&self) -> Option<&Matrix> {
+        match self {
+            Value::Matrix(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn as_function(&self) -> Option<&Arc<Lambda>> {
+        match self {
+            Value::Function(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Number of elements of a list, rows of a matrix or characters of a
+    /// string.
+    pub fn length(&self) -> Option<usize> {
+        match self {
+            Value::List(items) => Some(items.len()),
+            Value::Matrix(m) => Some(m.rows()),
+            Value::Str(s) => Some(s.chars().count()),
+            _ => None,
+        }
+    }
+
+    /// Element `index` of a list or string, or row `index` of a matrix as a
+    /// list. Negative indices count from the end.
+    pub fn index(&self, index: &Value) -> Result<Value, Error> {
+        let len = self.length().ok_or(
+            Error::EvalError(EvalError::NotIndexable { found: self.value_type() })
+        )?;
+        let i = index
+            .as_int()
+            .ok_or(Error::EvalError(EvalError::InvalidIndex { index: index.clone() }))?;
+
+        let pos = if i < 0 { i + (len as i64) } else { i };
+        if pos < 0 || pos >= (len as i64) {
+            return Err(Error::EvalError(EvalError::IndexOutOfRange { index: i, len }));
+        }
+
+        match self {
+            Value::List(items) => Ok(items[pos as usize].clone()),
+            Value::Matrix(m) => Ok(Value::List(m.row(pos as usize).to_vec())),
+            Value::Str(s) => Ok(Value::Str(s.chars().nth(pos as usize).unwrap().to_string())),
+            _ => Err(Error::UnexpectedError),
+        }
+    }
+
+    /// Elements from `start` up to but excluding `end`. Missing bounds
+    /// default to the ends, negative bounds count from the end and bounds
+    /// past either end are clamped. Slicing a matrix selects rows.
+    pub fn slice(&self, start: Option<&Value>, end: Option<&Value>) -> Result<Value, Error> {
+        let len = self.length().ok_or(
+            Error::EvalError(EvalError::NotIndexable { found: self.value_type() })
+        )? as i64;
+
+        let bound = |v: Option<&Value>, default: i64| -> Result<usize, Error> {
+            let i = match v {
+                Some(v) =>
+                    v.as_int().ok_or(Error::EvalError(EvalError::InvalidIndex { index: v.clone() }))?,
+                None => default,
+            };
+            let i = if i < 0 { i + len } else { i };
+            Ok(i.clamp(0, len) as usize)
+        };
+
+        let start = bound(start, 0)?;
+        let end = bound(end, len)?.max(start);
+
+        match self {
+            Value::List(items) => Ok(Value::List(items[start..end].to_vec())),
+            Value::Matrix(m) => Ok(Value::from_list(m.to_rows()[start..end].to_vec())),
+            Value::Str(s) => Ok(Value::Str(s.chars().skip(start).take(end - start).collect())),
+            _ => Err(Error::UnexpectedError),
+        }
+    }
+
     pub fn set_int(&mut self, value: i64) -> bool {
         match self {
             Value::Int(val) => {
@@ -106,61 +388,75 @@ impl Value {
         match self {
             Value::Boolean(b) => b.to_string(),
             Value::Int(i) => i.to_string(),
+            Value::BigInt(i) => i.to_string(),
+            Value::Rational(r) => r.to_string(),
             Value::Float(f) => f.to_string(),
+            Value::Complex(c) => c.to_string(),
+            Value::Str(s) => format!("{:?}", s),
+            Value::List(items) => {
+                let items = items
+                    .iter()
+                    .map(|v| v.symbol())
+                    .collect::<Vec<_>>();
+                format!("[{}]", items.join(", "))
+            }
+            Value::Matrix(m) => Value::List(m.to_rows()).symbol(),
+            Value::Function(f) => f.to_string(),
         }
     }
-}
 
-pub fn unify(values: &[Value]) -> Result<Vec<Value>, Error> {
-    if values.is_empty() {
-        Ok(Vec::new())
-    } else {
-        let target = values
-            .iter()
-            .map(|v| v.value_type())
-            .max_by_key(|v| v.rank())
-            .unwrap();
-
-        let promoted = values
-            .iter()
-            .cloned()
-            .map(|v| v.promote(target))
-            .collect();
-
-        match promoted {
-            Some(v) => Ok(v),
-            None =>
-                Err(
-                    Error::EvalError(EvalError::UnableToUnify {
-                        values: Vec::from_iter(values.iter().map(|v| (*v).clone())),
-                    })
-                ),
+    /// Like `symbol`, but strings are returned without quotes.
+    pub fn to_text(&self) -> String {
+        match self {
+            Value::Str(s) => s.clone(),
+            _ => self.symbol(),
         }
     }
 }
 
-pub fn unify_to(values: &[Value], target: ValueType) -> Result<Vec<Value>, Error> {
-    if values.is_empty() {
-        Ok(Vec::new())
+/// `value` promoted to `target`, borrowed if it already has that type.
+pub fn promote_ref(value: &Value, target: ValueType) -> Option<Cow<'_, Value>> {
+    if value.value_type() == target {
+        Some(Cow::Borrowed(value))
     } else {
-        let promoted = values
-            .iter()
-            .cloned()
-            .map(|v| v.promote(target))
-            .collect();
+        value.promote(target).map(Cow::Owned)
+    }
+}
 
-        match promoted {
-            Some(v) => Ok(v),
-            None =>
-                Err(
-                    Error::EvalError(EvalError::UnableToUnify {
-                        values: Vec::from_iter(values.iter().map(|v| (*v).clone())),
-                    })
-                ),
-        }
+/// Promotes `left` and `right` to `target`. Only an operand of another type
+/// is converted; the other is borrowed.
+pub fn unify_pair_to<'a>(
+    left: &'a Value,
+    right: &'a Value,
+    target: ValueType
+) -> Result<(Cow<'a, Value>, Cow<'a, Value>), Error> {
+    match (promote_ref(left, target), promote_ref(right, target)) {
+        (Some(l), Some(r)) => Ok((l, r)),
+        _ =>
+            Err(
+                Error::EvalError(EvalError::UnableToUnify {
+                    values: Vec::from_iter([left.clone(), right.clone()]),
+                })
+            ),
     }
 }
 
+/// Promotes `left` and `right` to the higher ranked of their two types.
+pub fn unify_pair<'a>(
+    left: &'a Value,
+    right: &'a Value
+) -> Result<(Cow<'a, Value>, Cow<'a, Value>), Error> {
+    if left.value_type() == right.value_type() {
+        return Ok((Cow::Borrowed(left), Cow::Borrowed(right)));
+    }
+    let target = if right.value_type().rank() > left.value_type().rank() {
+        right.value_type()
+    } else {
+        left.value_type()
+    };
+    unify_pair_to(left, right, target)
+}
+
 pub fn unify_ret_type(values: &[Value]) -> Result<(Vec<Value>, ValueType), Error> {
     if values.is_empty() {
         Ok((Vec::new(), ValueType::Boolean)) // dummy
@@ -173,7 +469,6 @@ pub fn unify_ret_type(values: &[Value]) -> Result<(Vec<Value>, ValueType), Error
 
         let promoted = values
             .iter()
-            .cloned()
             .map(|v| v.promote(target))
             .collect();
 
@@ -182,7 +477,7 @@ pub fn unify_ret_type(values: &[Value]) -> Result<(Vec<Value>, ValueType), Error
             None =>
                 Err(
                     Error::EvalError(EvalError::UnableToUnify {
-                        values: Vec::from_iter(values.iter().map(|v| (*v).clone())),
+                        values: values.to_vec(),
                     })
                 ),
         }
@@ -0,0 +1,797 @@
+use std::{ borrow::Cow, collections::HashMap, sync::Arc };
+
+use crate::{
+    compiler::{ Instr, Operand, Program },
+    error::{ Error, EvalError, NameKind },
+    native_function::{ Caller, NativeFunction },
+    operator::*,
+    scope::Scopes,
+    span::{ Span, Spanned },
+    suggestion::{ Namespaces, suggest },
+    token::Keyword,
+    user_function::{ CallGuard, LAMBDA_NAME, Lambda, UserFunction, grow_stack },
+    value::{ Value, ValueType },
+};
+
+/// Moves an error raised in the body of the macro `{name}` to its call site
+/// at `call`, keeping the original inside. A recursion limit error is only
+/// moved, since it would otherwise be wrapped once per nested expansion.
+fn in_macro(name: &str, call: Span, err: Spanned<Error>) -> Spanned<Error> {
+    if let Error::EvalError(EvalError::RecursionLimit { .. }) = err.data {
+        return Spanned { span: call, data: err.data };
+    }
+    Spanned {
+        span: call,
+        data: Error::EvalError(EvalError::InMacro { name: name.to_string(), error: Box::new(err) }),
+    }
+}
+
+/// Adds the names close to the one missing in a `NameNotFound` error,
+/// taken from every namespace visible here.
+fn with_suggestions(
+    err: Error,
+    locals: &[Local],
+    scopes: &Scopes,
+    macros: &HashMap<String, Program>,
+    user_functions: &HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Error {
+    match err {
+        Error::EvalError(EvalError::NameNotFound { kind, name, searched, .. }) => {
+            let namespaces = Namespaces {
+                variables: scopes
+                    .names()
+                    .chain(locals.iter().map(|l| l.name))
+                    .collect(),
+                functions: functions
+                    .keys()
+                    .chain(user_functions.keys())
+                    .map(String::as_str)
+                    .collect(),
+                macros: macros.keys().map(String::as_str).collect(),
+            };
+            let suggestions = suggest(kind, &name, &namespaces);
+            Error::EvalError(EvalError::NameNotFound { kind, name, searched, suggestions })
+        }
+        err => err,
+    }
+}
+
+fn apply_binary(op: BinaryOp, left: &Value, right: &Value) -> Result<(Value, bool), Error> {
+    match op {
+        BinaryOp::Addition => add::apply(left, right),
+        BinaryOp::Subtraction => sub::apply(left, right),
+        BinaryOp::Multiplication => mul::apply(left, right),
+        BinaryOp::ElementMul => elem_mul::apply(left, right),
+        BinaryOp::Division => div::apply(left, right),
+        BinaryOp::Modulo => modulo::apply(left, right),
+        BinaryOp::Exponentiation => exp::apply(left, right),
+        BinaryOp::BitwiseAnd => bit_and::apply(left, right),
+        BinaryOp::BitwiseOr => bit_or::apply(left, right),
+        BinaryOp::BitwiseXor => bit_xor::apply(left, right),
+        BinaryOp::And => and::apply(left, right),
+        BinaryOp::Or => or::apply(left, right),
+        BinaryOp::Equal => equal::apply(left, right),
+        BinaryOp::NotEqual => nequal::apply(left, right),
+        BinaryOp::Less => less::apply(left, right),
+        BinaryOp::LessEqual => lequal::apply(left, right),
+        BinaryOp::Greater => greater::apply(left, right),
+        BinaryOp::GreaterEqual => gequal::apply(left, right),
+        _ => Err(Error::UnexpectedError),
+    }
+}
+
+fn apply_assign(op: BinaryOp, slot: &mut Value, right: &Value) -> Result<(Value, bool), Error> {
+    match op {
+        BinaryOp::Assign => assign::apply(slot, right),
+        BinaryOp::AddAssign => add_assign::apply(slot, right),
+        BinaryOp::SubAssign => sub_assign::apply(slot, right),
+        BinaryOp::MulAssign => mul_assign::apply(slot, right),
+        BinaryOp::DivAssign => div_assign::apply(slot, right),
+        BinaryOp::ModAssign => mod_assign::apply(slot, right),
+        BinaryOp::AndAssign => and_assign::apply(slot, right),
+        BinaryOp::OrAssign => or_assign::apply(slot, right),
+        BinaryOp::BitAndAssign => bitand_assign::apply(slot, right),
+        BinaryOp::BitOrAssign => bitor_assign::apply(slot, right),
+        BinaryOp::BitXorAssign => bitxor_assign::apply(slot, right),
+        _ => Err(Error::UnexpectedError),
+    }
+}
+
+/// Locates an error raised by a binary operator: a dimension mismatch points
+/// at the offending operand, anything else covers both operands.
+fn operand_error(err: Error, operands: &[Span; 2]) -> Spanned<Error> {
+    match err {
+        Error::EvalError(EvalError::DimensionMismatch { operand, .. }) =>
+            Spanned { span: operands[operand], data: err },
+        _ => Spanned { span: operands[0].merge(&operands[1]), data: err },
+    }
+}
+
+enum HandlerKind {
+    /// Discards errors in a non-final operand of a comma.
+    Catch,
+    /// Encloses a loop body: `continue` jumps to `next`, `break` to the
+    /// handler target. `result` is the stack slot of the loop result.
+    Loop {
+        next: usize,
+        result: usize,
+    },
+}
+
+struct Handler {
+    target: usize,
+    depth: usize,
+    scopes: usize,
+    kind: HandlerKind,
+}
+
+fn pop(stack: &mut Vec<Value>, span: Span) -> Result<Value, Spanned<Error>> {
+    stack.pop().ok_or(Spanned { span, data: Error::UnexpectedError })
+}
+
+/// Local variable of a running program, in the slot given by its position.
+/// See `Program`.
+struct Local<'p> {
+    name: &'p str,
+    value: Value,
+    /// Depth of the scope it was bound in, which drops it when closed.
+    scope: usize,
+}
+
+fn local<'a, 'p>(
+    locals: &'a mut [Local<'p>],
+    slot: usize,
+    span: Span
+) -> Result<&'a mut Local<'p>, Spanned<Error>> {
+    locals.get_mut(slot).ok_or(Spanned { span, data: Error::UnexpectedError })
+}
+
+/// Drops the locals of scopes that have been closed.
+fn close_locals(locals: &mut Vec<Local>, scopes: &Scopes) {
+    while locals.last().is_some_and(|l| l.scope >= scopes.depth()) {
+        locals.pop();
+    }
+}
+
+/// Value of the variable `name` that a call of `name` would use: the local
+/// in `slot` if there is one, else the one in the scopes.
+fn variable<'a>(
+    name: &str,
+    slot: Option<usize>,
+    locals: &'a [Local],
+    scopes: &'a Scopes
+) -> Option<&'a Value> {
+    match slot {
+        Some(slot) => locals.get(slot).map(|l| &l.value),
+        None => scopes.get(name),
+    }
+}
+
+/// The global variable `names[name]` of `program`, by its slot if the
+/// program was compiled against the globals of `scopes`.
+fn global<'a>(
+    program: &Program,
+    name: usize,
+    slot: usize,
+    scopes: &'a Scopes
+) -> Result<&'a Value, Error> {
+    let name = &program.names[name];
+    if program.globals == scopes.globals().id() {
+        scopes.lookup_global(name, slot)
+    } else {
+        scopes.lookup(name)
+    }
+}
+
+/// Value of an operand read in place. `Operand::Stack` is popped by the
+/// caller instead.
+fn operand<'a>(
+    program: &'a Program,
+    operand: Operand,
+    locals: &'a [Local],
+    scopes: &'a Scopes
+) -> Result<&'a Value, Error> {
+    match operand {
+        Operand::Const(i) => Ok(&program.constants[i]),
+        Operand::Local(slot) => locals.get(slot).map(|l| &l.value).ok_or(Error::UnexpectedError),
+        Operand::Global { name, slot } => global(program, name, slot, scopes),
+        Operand::Stack => Err(Error::UnexpectedError),
+    }
+}
+
+/// Runs the program of a user function or lambda with its parameters bound
+/// to `args` and a call scope holding `vars`, reporting errors from the body
+/// at `call_span`.
+#[allow(clippy::too_many_arguments)]
+fn call_program(
+    name: &str,
+    program: &Program,
+    args: Vec<Value>,
+    vars: HashMap<String, Value>,
+    call_span: Span,
+    scopes: &mut Scopes,
+    macros: &HashMap<String, Program>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<Value, Spanned<Error>> {
+    let _guard = CallGuard::enter(NameKind::Function, name).map_err(|err| Spanned { span: call_span, data: err })?;
+
+    grow_stack(|| {
+        scopes.call(name, vars, |scopes| {
+            // The parameters are the first names of the program.
+            let scope = scopes.depth() - 1;
+            let locals = program.names
+                .iter()
+                .zip(args)
+                .map(|(name, value)| Local { name, value, scope })
+                .collect();
+            execute(program, locals, scopes, macros, user_functions, functions)
+        })
+    }).map_err(|err| Spanned { span: call_span, data: err.data })
+}
+
+/// Calls the function values passed to a native function, such as the
+/// function given to `map`.
+struct NativeCaller<'a> {
+    /// Call of the native function.
+    span: Span,
+    scopes: &'a mut Scopes,
+    macros: &'a HashMap<String, Program>,
+    user_functions: &'a mut HashMap<String, Arc<UserFunction>>,
+    functions: &'a HashMap<String, NativeFunction>,
+}
+
+impl Caller for NativeCaller<'_> {
+    fn call(&mut self, func: &Lambda, args: Vec<Value>) -> Result<Value, Error> {
+        func.func.check_arity(LAMBDA_NAME, &args)?;
+        call_program(
+            LAMBDA_NAME,
+            func.func.program(self.scopes.globals_mut()),
+            args,
+            func.captured_vars(),
+            self.span,
+            self.scopes,
+            self.macros,
+            self.user_functions,
+            self.functions
+        ).map_err(|err| err.data)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn step<'p>(
+    program: &'p Program,
+    pc: &mut usize,
+    stack: &mut Vec<Value>,
+    handlers: &mut Vec<Handler>,
+    locals: &mut Vec<Local<'p>>,
+    scopes: &mut Scopes,
+    macros: &HashMap<String, Program>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<(), Spanned<Error>> {
+    let span = program.spans[*pc];
+    let instr = &program.code[*pc];
+    *pc += 1;
+
+    match instr {
+        Instr::Const(i) => stack.push(program.constants[*i].clone()),
+        Instr::Load { name, slot } => {
+            let v = global(program, *name, *slot, scopes).map_err(|err| Spanned {
+                span,
+                data: with_suggestions(err, locals, scopes, macros, user_functions, functions),
+            })?;
+            stack.push(v.clone());
+        }
+        Instr::LoadLocal(slot) => {
+            let v = local(locals, *slot, span)?.value.clone();
+            stack.push(v);
+        }
+        Instr::Macro(i) => {
+            let name = &program.names[*i];
+            let Some(body) = macros.get(name) else {
+                let err = Error::EvalError(EvalError::NameNotFound {
+                    kind: NameKind::Macro,
+                    name: name.clone(),
+                    searched: Vec::new(),
+                    suggestions: Vec::new(),
+                });
+                return Err(Spanned {
+                    span,
+                    data: with_suggestions(err, locals, scopes, macros, user_functions, functions),
+                });
+            };
+            // The body sees the variables of the call site, so the locals are
+            // put into their scopes while it runs and read back afterwards.
+            for l in locals.iter() {
+                scopes.define_at(l.scope, l.name, l.value.clone());
+            }
+            let result = CallGuard::enter(NameKind::Macro, name)
+                .map_err(|err| Spanned { span, data: err })
+                .and_then(|_guard| grow_stack(|| run(body, scopes, macros, user_functions, functions)));
+            for l in locals.iter_mut().rev() {
+                if let Some(v) = scopes.take_at(l.scope, l.name) {
+                    l.value = v;
+                }
+            }
+            stack.push(result.map_err(|err| in_macro(name, span, err))?);
+        }
+        Instr::Unary(op) => {
+            let value = pop(stack, span)?;
+            let result = (
+                match op {
+                    UnaryOp::Not => not::apply(&value),
+                    UnaryOp::Negation => neg::apply(&value),
+                    UnaryOp::BitwiseNot => bit_not::apply(&value),
+                }
+            ).map_err(|err| Spanned { span, data: err })?;
+
+            if result.1 {
+                return Err(Spanned {
+                    span,
+                    data: Error::EvalError(EvalError::InvalidResult {
+                        op: Operator::Unary(*op),
+                        operands: Vec::from_iter([value]),
+                        result: result.0,
+                    }),
+                });
+            }
+            stack.push(result.0);
+        }
+        Instr::Binary { op, lhs, rhs, operands } => {
+            // rhs is only on the stack if lhs is too, above it.
+            let right = if *rhs == Operand::Stack { Some(pop(stack, span)?) } else { None };
+            let left = if *lhs == Operand::Stack { Some(pop(stack, span)?) } else { None };
+            let read = |value: Option<Value>, from: Operand, span: Span| {
+                match value {
+                    Some(v) => Ok(Cow::Owned(v)),
+                    None =>
+                        operand(program, from, locals, scopes).map(Cow::Borrowed).map_err(|err| Spanned {
+                            span,
+                            data: with_suggestions(err, locals, scopes, macros, user_functions, functions),
+                        }),
+                }
+            };
+            let left = read(left, *lhs, operands[0])?;
+            let right = read(right, *rhs, operands[1])?;
+            let result = apply_binary(*op, &left, &right).map_err(|err|
+                operand_error(err, operands)
+            )?;
+
+            if result.1 {
+                return Err(Spanned {
+                    span,
+                    data: Error::EvalError(EvalError::InvalidResult {
+                        op: Operator::Binary(*op),
+                        operands: Vec::from_iter([left.into_owned(), right.into_owned()]),
+                        result: result.0,
+                    }),
+                });
+            }
+            stack.push(result.0);
+        }
+        Instr::AndShort(target) | Instr::OrShort(target) => {
+            let short = matches!(instr, Instr::OrShort(_));
+            let left = stack.last().ok_or(Spanned { span, data: Error::UnexpectedError })?;
+
+            if let Some(Value::Boolean(b)) = left.promote(ValueType::Boolean) && b == short {
+                stack.pop();
+                stack.push(Value::Boolean(short));
+                *pc = *target;
+            }
+        }
+        Instr::Assign { op, name: i, slot, operands } => {
+            let right = pop(stack, span)?;
+            let resolved = program.globals == scopes.globals().id();
+            let name = &program.names[*i];
+            let default = || right.value_type().default_value();
+
+            let slot = if *op == BinaryOp::Assign {
+                if resolved { scopes.slot_global(name, *slot, default) } else { scopes.slot(name, default) }
+            } else {
+                if let Err(err) = global(program, *i, *slot, scopes) {
+                    let data = with_suggestions(err, locals, scopes, macros, user_functions, functions);
+                    return Err(Spanned { span, data });
+                }
+                if resolved {
+                    scopes.lookup_global_mut(name, *slot)
+                } else {
+                    scopes.lookup_mut(name)
+                }.map_err(|err| Spanned { span, data: err })?
+            };
+
+            let result = apply_assign(*op, slot, &right).map_err(|err|
+                operand_error(err, operands)
+            )?;
+
+            if result.1 {
+                return Err(Spanned {
+                    span,
+                    data: Error::EvalError(EvalError::InvalidResult {
+                        op: Operator::Binary(*op),
+                        operands: Vec::from_iter([Value::Boolean(false), right]),
+                        result: result.0,
+                    }),
+                });
+            }
+            stack.push(result.0);
+        }
+        Instr::AssignLocal { op, slot, operands } => {
+            let right = pop(stack, span)?;
+            let slot = &mut local(locals, *slot, span)?.value;
+
+            let result = apply_assign(*op, slot, &right).map_err(|err|
+                operand_error(err, operands)
+            )?;
+
+            if result.1 {
+                return Err(Spanned {
+                    span,
+                    data: Error::EvalError(EvalError::InvalidResult {
+                        op: Operator::Binary(*op),
+                        operands: Vec::from_iter([Value::Boolean(false), right]),
+                        result: result.0,
+                    }),
+                });
+            }
+            stack.push(result.0);
+        }
+        Instr::Resolve { name, local } => {
+            let name = &program.names[*name];
+            let variable = variable(name, *local, locals, scopes);
+            if
+                !functions.contains_key(name) &&
+                !user_functions.contains_key(name) &&
+                !matches!(variable, Some(Value::Function(_)))
+            {
+                if let Some(v) = variable {
+                    return Err(Spanned {
+                        span,
+                        data: Error::EvalError(EvalError::NotCallable {
+                            name: Some(name.clone()),
+                            found: v.value_type(),
+                        }),
+                    });
+                }
+                let err = Error::EvalError(EvalError::NameNotFound {
+                    kind: NameKind::Function,
+                    name: name.clone(),
+                    searched: Vec::new(),
+                    suggestions: Vec::new(),
+                });
+                return Err(Spanned {
+                    span,
+                    data: with_suggestions(err, locals, scopes, macros, user_functions, functions),
+                });
+            }
+        }
+        Instr::Call { func, argc, args, local } => {
+            let name = &program.names[*func];
+
+            let at_call = |err: Error| Spanned { span, data: err };
+
+            if let Some(f) = user_functions.get(name).cloned() {
+                let v = stack.split_off(stack.len().saturating_sub(*argc));
+                f.check_arity(name, &v).map_err(at_call)?;
+                let result = call_program(
+                    name,
+                    f.program(scopes.globals_mut()),
+                    v,
+                    HashMap::new(),
+                    span,
+                    scopes,
+                    macros,
+                    user_functions,
+                    functions
+                )?;
+                stack.push(result);
+                return Ok(());
+            }
+
+            if let Some(Value::Function(f)) = variable(name, *local, locals, scopes) {
+                let f = f.clone();
+                let v = stack.split_off(stack.len().saturating_sub(*argc));
+                f.func.check_arity(name, &v).map_err(at_call)?;
+                let result = call_program(
+                    name,
+                    f.func.program(scopes.globals_mut()),
+                    v,
+                    f.captured_vars(),
+                    span,
+                    scopes,
+                    macros,
+                    user_functions,
+                    functions
+                )?;
+                stack.push(result);
+                return Ok(());
+            }
+
+            let f = functions.get(name).ok_or(Spanned { span, data: Error::UnexpectedError })?;
+            let v = stack.split_off(stack.len().saturating_sub(*argc));
+
+            let v = f.check_args(name, &v).map_err(|err| {
+                match err {
+                    Error::EvalError(EvalError::ArgTypeMismatch { index, .. }) =>
+                        Spanned {
+                            span: program.arg_spans[*args][index],
+                            data: err,
+                        },
+                    _ => Spanned { span, data: err },
+                }
+            })?;
+
+            let mut caller = NativeCaller { span, scopes, macros, user_functions, functions };
+            let result = f.call_unchecked(&v, &mut caller).map_err(|err| {
+                match err {
+                    Error::EvalError(EvalError::DimensionMismatch { operand, .. }) =>
+                        Spanned {
+                            span: program.arg_spans[*args][operand],
+                            data: err,
+                        },
+                    _ => Spanned { span, data: err },
+                }
+            })?;
+            stack.push(result);
+        }
+        Instr::CallValue { argc, callee } => {
+            let v = stack.split_off(stack.len().saturating_sub(*argc));
+            let value = pop(stack, span)?;
+            let f = value.as_function().ok_or(Spanned {
+                span: *callee,
+                data: Error::EvalError(EvalError::NotCallable { name: None, found: value.value_type() }),
+            })?;
+
+            f.func.check_arity(LAMBDA_NAME, &v).map_err(|err| Spanned { span, data: err })?;
+            let result = call_program(
+                LAMBDA_NAME,
+                f.func.program(scopes.globals_mut()),
+                v,
+                f.captured_vars(),
+                span,
+                scopes,
+                macros,
+                user_functions,
+                functions
+            )?;
+            stack.push(result);
+        }
+        Instr::Closure(i) => {
+            let f = Lambda::new(program.functions[*i].clone(), |name| {
+                match locals.iter().rfind(|l| l.name == name) {
+                    Some(l) => Some(l.value.clone()),
+                    None => scopes.get(name).cloned(),
+                }
+            });
+            stack.push(Value::Function(Arc::new(f)));
+        }
+        Instr::PushScope(kind) => scopes.push(kind.clone()),
+        Instr::Bind(i) => {
+            let value = pop(stack, span)?;
+            locals.push(Local { name: &program.names[*i], value, scope: scopes.depth() - 1 });
+        }
+        Instr::PopScope => {
+            scopes.pop();
+            close_locals(locals, scopes);
+        }
+        Instr::Define { name, func } => {
+            let name = &program.names[*name];
+            if functions.contains_key(name) {
+                return Err(Spanned {
+                    span,
+                    data: Error::EvalError(EvalError::BuiltinRedefined { name: name.clone() }),
+                });
+            }
+            let f = program.functions[*func].clone();
+            user_functions.insert(name.clone(), f.clone());
+            stack.push(Value::Function(Arc::new(Lambda::of_function(f))));
+        }
+        Instr::List(n) => {
+            let items = stack.split_off(stack.len().saturating_sub(*n));
+            stack.push(Value::from_list(items));
+        }
+        Instr::Index { target, index } => {
+            let i = pop(stack, span)?;
+            let t = pop(stack, span)?;
+            let v = t.index(&i).map_err(|err| {
+                match err {
+                    Error::EvalError(EvalError::NotIndexable { .. }) =>
+                        Spanned { span: *target, data: err },
+                    _ => Spanned { span: *index, data: err },
+                }
+            })?;
+            stack.push(v);
+        }
+        Instr::Slice { start, end, target } => {
+            let end = if *end { Some(pop(stack, span)?) } else { None };
+            let start = if *start { Some(pop(stack, span)?) } else { None };
+            let t = pop(stack, span)?;
+            let v = t.slice(start.as_ref(), end.as_ref()).map_err(|err| {
+                match err {
+                    Error::EvalError(EvalError::NotIndexable { .. }) =>
+                        Spanned { span: *target, data: err },
+                    _ => Spanned { span, data: err },
+                }
+            })?;
+            stack.push(v);
+        }
+        Instr::Jump(target) => {
+            *pc = *target;
+        }
+        Instr::JumpIfFalse(target) => {
+            let cond = pop(stack, span)?;
+            let cond = cond.as_condition().map_err(|err| Spanned { span, data: err })?;
+
+            if !cond {
+                *pc = *target;
+            }
+        }
+        Instr::Pop => {
+            pop(stack, span)?;
+        }
+        Instr::PushHandler(target) => {
+            handlers.push(Handler {
+                target: *target,
+                depth: stack.len(),
+                scopes: scopes.depth(),
+                kind: HandlerKind::Catch,
+            });
+        }
+        Instr::PushLoop { exit, locals } => {
+            let result = stack
+                .len()
+                .checked_sub(locals + 1)
+                .ok_or(Spanned { span, data: Error::UnexpectedError })?;
+            handlers.push(Handler {
+                target: *exit,
+                depth: stack.len(),
+                scopes: scopes.depth(),
+                kind: HandlerKind::Loop { next: *pc, result },
+            });
+        }
+        Instr::PopLoop => {
+            handlers.pop();
+        }
+        Instr::StoreResult => {
+            let value = pop(stack, span)?;
+            let result = handlers
+                .iter()
+                .rev()
+                .find_map(|h| {
+                    match h.kind {
+                        HandlerKind::Loop { result, .. } => Some(result),
+                        HandlerKind::Catch => None,
+                    }
+                })
+                .ok_or(Spanned { span, data: Error::UnexpectedError })?;
+            stack[result] = value;
+        }
+        Instr::ForRange => {
+            let [start, end] = stack.last_chunk::<2>().ok_or(Spanned {
+                span,
+                data: Error::UnexpectedError,
+            })?;
+            if !matches!((start, end), (Value::Int(_), Value::Int(_))) {
+                return Err(Spanned {
+                    span,
+                    data: Error::EvalError(EvalError::InvalidRange {
+                        start: start.clone(),
+                        end: end.clone(),
+                    }),
+                });
+            }
+        }
+        Instr::ForNext { slot, exit } => {
+            let Some([Value::Int(i), Value::Int(end)]) = stack.last_chunk_mut::<2>() else {
+                return Err(Spanned { span, data: Error::UnexpectedError });
+            };
+
+            if *i >= *end {
+                *pc = *exit;
+            } else {
+                local(locals, *slot, span)?.value = Value::Int(*i);
+                *i += 1;
+            }
+        }
+        Instr::PopHandler => {
+            handlers.pop();
+        }
+        Instr::Raise(err) => {
+            return Err(Spanned { span, data: err.clone() });
+        }
+    }
+
+    Ok(())
+}
+
+/// Pops handlers up to the one that handles `err`: the innermost loop for
+/// `break` and `continue`, the innermost comma operand for anything else.
+fn unwind(handlers: &mut Vec<Handler>, err: &Error) -> Option<Handler> {
+    let control = matches!(err, Error::EvalError(EvalError::LoopControl { .. }));
+
+    while let Some(handler) = handlers.pop() {
+        match handler.kind {
+            HandlerKind::Loop { .. } if control => {
+                return Some(handler);
+            }
+            HandlerKind::Catch if !control => {
+                return Some(handler);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Runs a compiled program against the given scopes. Results and
+/// error spans match `evaluater::evaluate_expr` on the source expression.
+pub fn run(
+    program: &Program,
+    scopes: &mut Scopes,
+    macros: &HashMap<String, Program>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<Value, Spanned<Error>> {
+    execute(program, Vec::new(), scopes, macros, user_functions, functions)
+}
+
+/// Runs `program` with the locals `locals` already bound.
+fn execute<'p>(
+    program: &'p Program,
+    mut locals: Vec<Local<'p>>,
+    scopes: &mut Scopes,
+    macros: &HashMap<String, Program>,
+    user_functions: &mut HashMap<String, Arc<UserFunction>>,
+    functions: &HashMap<String, NativeFunction>
+) -> Result<Value, Spanned<Error>> {
+    let mut stack: Vec<Value> = Vec::new();
+    let mut handlers: Vec<Handler> = Vec::new();
+    let mut pc = 0usize;
+    let depth = scopes.depth();
+
+    while pc < program.code.len() {
+        if
+            let Err(err) = step(
+                program,
+                &mut pc,
+                &mut stack,
+                &mut handlers,
+                &mut locals,
+                scopes,
+                macros,
+                user_functions,
+                functions
+            )
+        {
+            match unwind(&mut handlers, &err.data) {
+                Some(handler) => {
+                    stack.truncate(handler.depth);
+                    scopes.truncate(handler.scopes);
+                    close_locals(&mut locals, scopes);
+                    pc = match (&err.data, &handler.kind) {
+                        (
+                            Error::EvalError(EvalError::LoopControl { keyword: Keyword::Continue }),
+                            HandlerKind::Loop { next, .. },
+                        ) => {
+                            let next = *next;
+                            // The loop goes on, so its handler stays.
+                            handlers.push(handler);
+                            next
+                        }
+                        _ => handler.target,
+                    };
+                }
+                None => {
+                    scopes.truncate(depth);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    stack.pop().ok_or(Spanned {
+        span: Span { start: 0, end: 0 },
+        data: Error::UnexpectedError,
+    })
+}
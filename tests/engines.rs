@@ -0,0 +1,438 @@
+//! The tree walker (`Interpreter::eval`) and the bytecode VM
+//! (`Interpreter::compile` and `run`) must agree on every result, every
+//! error and its span, and the variables left behind.
+
+use math_interpreter::{
+    Error,
+    Interpreter,
+    Span,
+    Spanned,
+    Value,
+    ValueType,
+    error::{ EvalError, NameKind },
+    user_function::MAX_CALL_DEPTH,
+};
+
+fn setup() -> Interpreter {
+    let mut i = Interpreter::new();
+    i.set_var("x", Value::Int(3));
+    i.set_var("f", Value::Float(1.5));
+    i.define_macro("sq", "x*x").unwrap();
+    i.define_macro("bad", "y + 1").unwrap();
+    i.define_macro("ml", "lv * 2").unwrap();
+    i.define_macro("ma", "(lv = lv + 1, nv = 7, lv)").unwrap();
+    i
+}
+
+/// Runs `cases` in order through both engines, each with its own
+/// interpreter, and compares the outcomes. Debug output is compared so that
+/// NaN results count as equal.
+fn assert_same(cases: &[&str]) {
+    let mut tree = setup();
+    let mut vm = setup();
+
+    for case in cases {
+        let expected = tree.eval(case);
+        let found = vm.compile(case).and_then(|p| vm.run(&p));
+        assert_eq!(format!("{:?}", found), format!("{:?}", expected), "{}", case);
+    }
+
+    let vars = |i: &Interpreter| {
+        let mut vars: Vec<_> = i.vars().iter().map(|(k, v)| format!("{} = {:?}", k, v)).collect();
+        vars.sort();
+        vars
+    };
+    assert_eq!(vars(&vm), vars(&tree));
+}
+
+#[test]
+fn operators() {
+    assert_same(&[
+        "1+2*3",
+        "x*f",
+        "2**3**2",
+        "-x",
+        "~f",
+        "!0",
+        "true && false",
+        "false || true",
+        "0 && y",
+        "1 || y",
+        "1 && y",
+        "x > 2 ? 10 : 20",
+        "x < 2 ? 10 : y",
+        "1/0",
+        "9223372036854775807 + 1",
+        "3 x",
+        "2(3+4)",
+        "1.0/0.0",
+        "x == 3",
+        "true ^ false",
+        "1/3 + 1/6",
+        "(-8) ** (1/3)",
+        "2 ** 70",
+        "~\"a\"",
+        "-[1, \"a\"]",
+    ]);
+}
+
+#[test]
+fn assignment() {
+    assert_same(&[
+        "x = 5",
+        "x += 2",
+        "z += 1",
+        "a = 2.5",
+        "a",
+        "a %= 2",
+        "x &= 6",
+        "b = true, b ^= false",
+        "b |= 3",
+        "(1+2) = 3",
+        "s = \"a\"",
+        "s += 1",
+        "l = [1,2], l += 1",
+        "a = 1, a = \"s\"",
+        "e = []",
+        "e = [[1,2]]",
+        "e = [3]",
+        "e = [[1],[2]]",
+        "e = 1",
+    ]);
+
+    // A list literal may be a matrix, so variables switch between the two.
+    let mut i = Interpreter::new();
+    assert_eq!(i.eval("e = [], e = [[1,2]], e = [3]"), Ok(Value::List(vec![Value::Int(3)])));
+}
+
+#[test]
+fn comma() {
+    assert_same(&["y, 2", "x = 1, x + 1", "(x=2, y, x)", "1 + {bad}, 5"]);
+}
+
+#[test]
+fn natives() {
+    assert_same(&[
+        "max(1, 2.5, true)",
+        "max()",
+        "nope(1)",
+        "nope(y)",
+        "to_int(y)",
+        "clamp(1, 5, 10)",
+        "clamp(10, 1, 5)",
+        "all(1, 0)",
+        "any()",
+        "max([1, 4])",
+        "any([\"a\"])",
+        "sqrt(-4)",
+        "x = 2",
+        "x(1)",
+        "max(a) = 1",
+    ]);
+}
+
+#[test]
+fn lists_and_matrices() {
+    assert_same(&[
+        "[1, 2] + [3, 4]",
+        "[1,2][5]",
+        "[1,2][x]",
+        "5[0]",
+        "[1,2,3][1:]",
+        "[1,2,3][:y]",
+        "[1,2] + [1]",
+        "1/[2, 0]",
+        "m = [[1,2],[3,4]]",
+        "m * m",
+        "m .* m",
+        "det(m)",
+        "inv(m)",
+        "m * [1, 2, 3]",
+        "[[1,2,3]] + m",
+        "solve(m, [1,2,3])",
+        "det([[1,2,3]])",
+        "m ** -1",
+        "m *= m",
+        "m[1]",
+        "m[0:1]",
+        "m / 2",
+        "1 / m",
+        "eig([[2,1],[1,2]])",
+        "rank(m)",
+        "[1,2] * m",
+    ]);
+}
+
+#[test]
+fn user_functions() {
+    assert_same(&[
+        "g(x, y) = x**2 + y",
+        "g(3, 4)",
+        "g(1)",
+        "g(y, 1)",
+        "fact(n) = n <= 1 ? 1 : n * fact(n - 1)",
+        "fact(25)",
+        "h(t) = (t = t + 1, t * x)",
+        "h(2)",
+        "t",
+        "fw(n) = n + w",
+        "fw(1)",
+        "gx(n) = (x = n, n)",
+        "gx(7)",
+        "fb(n) = break",
+        "hh(n) = n(1)",
+        "hh(v => v + 1)",
+        "hh(3)",
+        "hq(g) = g(1, 2)",
+        "hq(v => v)",
+        "gv = (gd(v) = v * 3)",
+        "gv(2)",
+    ]);
+
+    let mut i = Interpreter::new();
+    let defined = i.eval("sq(v) = v * v").unwrap();
+    assert_eq!(defined.symbol(), "v => v * v");
+}
+
+/// Runs on the 2 MB stack of a test thread, so recursion must reach the
+/// limit without depending on the stack size of the calling thread.
+#[test]
+fn recursion_limit() {
+    let below = format!("depth({})", MAX_CALL_DEPTH - 1);
+    let at = format!("depth({})", MAX_CALL_DEPTH);
+    assert_same(&[
+        "depth(n) = n == 0 ? 0 : 1 + depth(n - 1)",
+        &below,
+        &at,
+        "loop(n) = loop(n + 1)",
+        "loop(0)",
+    ]);
+
+    let mut i = Interpreter::new();
+    i.eval("depth(n) = n == 0 ? 0 : 1 + depth(n - 1)").unwrap();
+    assert_eq!(i.eval(&below), Ok(Value::Int((MAX_CALL_DEPTH - 1) as i64)));
+    assert!(
+        matches!(
+            i.eval(&at).map_err(|e| e.data),
+            Err(Error::EvalError(EvalError::RecursionLimit { .. }))
+        )
+    );
+}
+
+/// A macro that expands itself stops at the call limit with the span of
+/// the outermost reference, instead of overflowing the stack.
+#[test]
+fn macro_recursion() {
+    let mut i = Interpreter::new();
+    i.define_macro("a", "{a}+1").unwrap();
+    let expected = Err(Spanned {
+        span: Span { start: 2, end: 5 },
+        data: Error::EvalError(EvalError::RecursionLimit {
+            kind: NameKind::Macro,
+            func: "a".to_string(),
+            depth: MAX_CALL_DEPTH,
+        }),
+    });
+
+    assert_eq!(i.eval("1+{a}"), expected);
+    let program = i.compile("1+{a}").unwrap();
+    assert_eq!(i.run(&program), expected);
+}
+
+/// Globals are compiled to slots of the interpreter that compiled the
+/// program, and found by name when another interpreter runs it.
+#[test]
+fn global_slots() {
+    let mut a = Interpreter::new();
+    let program = a.compile("gs = gs + 1, gs * x").unwrap();
+    assert!(a.run(&program).is_err());
+    a.set_var("gs", Value::Int(1));
+    a.set_var("x", Value::Int(3));
+    assert_eq!(a.run(&program), Ok(Value::Int(6)));
+    assert_eq!(a.run(&program), Ok(Value::Int(9)));
+
+    let mut b = Interpreter::new();
+    b.set_var("x", Value::Int(10));
+    b.set_var("gs", Value::Int(0));
+    assert_eq!(b.run(&program), Ok(Value::Int(10)));
+    assert_eq!(b.get_var("gs"), Some(&Value::Int(1)));
+    assert_eq!(a.get_var("gs"), Some(&Value::Int(3)));
+}
+
+#[test]
+fn lambdas() {
+    assert_same(&[
+        "sq2 = v => v**2 + 1",
+        "sq2(3)",
+        "kk = 2",
+        "dbl = fn(v) v*kk",
+        "kk = 10",
+        "dbl(4)",
+        "(fn(a, b) a - b)(5, 1)",
+        "(v => v)(1, 2)",
+        "mk(k) = v => v * k",
+        "mk(7)",
+        "zz = () => 42",
+        "zz()",
+        "sq2 == sq2",
+        "sq2 + 1",
+        "(v => v)(y)",
+        "(v => q)(1)",
+        "-sq2",
+        "dbl(1, 2)",
+        "map(sq2, [1, 2, 3])",
+        "map(v => v + \"a\", [1])",
+        "map(v => nope, [1])",
+        "map(mk(2), [1, 2])",
+        "map(v => map(w => w * v, [1, 2]), [1, 2])",
+    ]);
+}
+
+#[test]
+fn let_bindings() {
+    assert_same(&[
+        "let t = x*2 in t + t/2",
+        "t",
+        "let t = 2, u = t * 10 in u + t",
+        "let x = 5 in x",
+        "let q = 1 in (x = 9, zq = 2, zq)",
+        "x",
+        "zq",
+        "let q = 1 in nope",
+        "mk(k) = v => v * k",
+        "let k = 3 in (v => v * k)(2)",
+        "let k = 4 in mk(k)(1)",
+        "let a = 1 in (nope, a)",
+        "(let a = 1 in nope, 5)",
+        "let a = 1 in let b = a + 1 in b * 10",
+        "let a = 1, a = a + 1 in a",
+        "let a = 1 in (a += 2, a)",
+        "let a = 1 in (a = \"s\", a)",
+        "let a = 1 in (let b = 2 in nope, a)",
+        "let lvv = 1 in lv",
+        "let g = 5 in g(1, 2)",
+        "let q = 2 in q(1)",
+        "let lv = 1 in (v => v + lv)(1)",
+    ]);
+}
+
+/// Assigning a name that is not bound creates it in the innermost call
+/// scope, or the global scope outside of calls, not in the `let` or `for`
+/// scope it is assigned in.
+#[test]
+fn assignment_scope() {
+    assert_same(&[
+        "for i in 0..3 { k = i }",
+        "k",
+        "let w = 2 in (u = w)",
+        "u",
+        "fk(n) = (for i in 0..n { kf = i }, kf)",
+        "fk(3)",
+        "kf",
+        "fl(n) = let a = n in (lf = a * 2, lf + 1)",
+        "fl(4)",
+        "lf",
+    ]);
+
+    let mut i = Interpreter::new();
+    assert_eq!(i.eval("for i in 0..3 { k = i }, k"), Ok(Value::Int(2)));
+    assert_eq!(i.eval("let w = 2 in (u = w), u"), Ok(Value::Int(2)));
+    i.eval("fk(n) = (for i in 0..n { kf = i }, kf)").unwrap();
+    assert_eq!(i.eval("fk(3)"), Ok(Value::Int(2)));
+    assert_eq!(i.get_var("kf"), None);
+}
+
+#[test]
+fn control_flow() {
+    assert_same(&[
+        "if x > 2 { 1 } else { 2 }",
+        "if x > 100 { 1 }",
+        "if y { 1 }",
+        "if x < 0 { 1 } else if x < 5 { 2 } else { 3 }",
+        "s = 0",
+        "for i in 0..5 { s += i }",
+        "s",
+        "i",
+        "for i in 0..0 { 1 }",
+        "for i in 3..1 { 1 }",
+        "for i in 0..2.5 { 1 }",
+        "for i in 0..y { 1 }",
+        "n = 0",
+        "while n < 10 { n += 1 }",
+        "while n < 20 { n += 1, n == 15 ? break : n }",
+        "n",
+        "while false { 1 }",
+        "while 1 + [1] { 1 }",
+        "break",
+        "for i in 0..10 { i % 2 == 0 ? continue : i }",
+        "for i in 0..10 { if i == 4 { break }, i * 10 }",
+        "acc = 0, for i in 0..4 { for j in 0..4 { if j > i { break }, acc += 1 } }, acc",
+        "for i in 0..3 { (nope, break), i }",
+        "for i in 0..3 { let q = i in q * 2 }",
+        "for i in 0..3 { let q = i in (q == 1 ? break : q) }",
+        "for i in 0..3 { qq = i }",
+        "qq",
+        "ff = fn(n) for i in 0..n { i * i }",
+        "ff(4)",
+        "if x > 2 { 7 } * 2",
+        "for i in 0..3 { w1 = i * 2 }, w1",
+        "for i in 0..3 { i += 10 }",
+        "for i in 0..3 { let i = i * 2 in i }",
+        "for i in 0..2 { for j in 0..2 { if j == 1 { continue }, let k = i in k } }",
+        "let a = 1 in for i in 0..2 { let b = a in (b == 1 ? break : b) }",
+        "if \"a\" { 2 }",
+        "while [1] { 1 }",
+        "\"a\" ? 1 : 2",
+        "if 2i { 1 } else { 2 }",
+    ]);
+}
+
+#[test]
+fn invalid_condition() {
+    let mut i = Interpreter::new();
+    let expected = |start, end, found| Err(Spanned {
+        span: Span { start, end },
+        data: Error::EvalError(EvalError::InvalidCondition { found }),
+    });
+    assert_eq!(i.eval("if \"a\" { 2 }"), expected(3, 6, ValueType::Str));
+    assert_eq!(i.eval("while [1] { 1 }"), expected(6, 9, ValueType::List));
+    assert_eq!(i.eval("[1] ? 1 : 2"), expected(0, 3, ValueType::List));
+}
+
+#[test]
+fn blocks() {
+    assert_same(&[
+        "for i in 0..3 { nope, i }",
+        "for i in 0..3 { nope; i }",
+        "n = 0, while n < 3 { qq2 += n; n += 1 }",
+        "for i in 0..3 { jb = i * 2\n jb + 1 }",
+        "if x > 2 { a1 = 1; a1 + 1; }",
+        "for i in 0..3 { (nope, i) }",
+        "for i in 0..4 { if i == 2 { break }\n i }",
+        "if x { 1 +\n 2 }",
+        "wl = 0, while wl < 5 { wl += 1, if wl == 2 { continue }, wl * 100 }",
+    ]);
+}
+
+#[test]
+fn macros() {
+    assert_same(&[
+        "{sq} + 1",
+        "{bad}",
+        "{missing}",
+        "if x {sq}",
+        "if x {sq} else {x}",
+        "for i in 0..{sq} {i}",
+        "{ml}",
+        "let lv = 3 in {ml}",
+        "let lv = 3 in ({ma}, lv)",
+        "let lv = 3 in ({ma}, nv)",
+        "nv",
+        "for lv in 0..3 { {ml} }",
+        "for lv in 0..3 { {ma} }",
+        "mf(lv) = {ml}",
+        "mf(4)",
+        "mg(lv) = ({ma}, lv * 10)",
+        "mg(4)",
+    ]);
+}